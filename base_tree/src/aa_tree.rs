@@ -0,0 +1,359 @@
+//! AA-trees: red-black trees where only right children may be red, kept as levels.
+
+use alloc::boxed::Box;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use crate::tree::{alloc, free_subtree, leftmost, successor, unlink, Link};
+use crate::{Node, SizeAug, TreeStats};
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    // Leaves are at level one. A left child is always one level below its parent, a right child
+    // at most one, and a right grandchild strictly below its grandparent.
+    level: usize,
+}
+
+type AaNode<K, V> = Node<Entry<K, V>, SizeAug>;
+
+/// An ordered map kept as an AA-tree. Rebalancing comes down to two rules, both a single
+/// rotation: a left child on its parent's level is rotated up (skew), and a right grandchild on
+/// its grandparent's level lifts its parent a level up (split).
+pub struct AaTreeMap<K, V> {
+    root: Link<Entry<K, V>, SizeAug>,
+    rotations: usize,
+    _marker: PhantomData<Box<AaNode<K, V>>>,
+}
+
+impl<K, V> AaTreeMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            rotations: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.map_or(0, |root| unsafe { root.as_ref() }.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Counts the nodes, leaves and levels of the tree.
+    pub fn stats(&self) -> TreeStats {
+        self.root
+            .map(|root| TreeStats::of(unsafe { root.as_ref() }))
+            .unwrap_or_default()
+    }
+
+    /// How many rotations skews and splits have made to keep the tree balanced.
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            next: self.root.map(|root| unsafe { leftmost(root) }),
+            remaining: self.len(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K: Ord, V> AaTreeMap<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map(|ptr| &unsafe { ptr.as_ref() }.get().value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map(|ptr| &mut unsafe { &mut *ptr.as_ptr() }.data.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaces, if any. The key already in
+    /// the map is kept in that case.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let entry = Entry {
+            key,
+            value,
+            level: 1,
+        };
+        let Some(mut ptr) = self.root else {
+            self.root = Some(alloc(entry));
+            return None;
+        };
+
+        let new = loop {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            let slot = match entry.key.cmp(&node.data.key) {
+                Ordering::Equal => return Some(mem::replace(&mut node.data.value, entry.value)),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+            };
+            match *slot {
+                Some(child) => ptr = child,
+                None => {
+                    let new = alloc(entry);
+                    *slot = Some(new);
+                    unsafe { (*new.as_ptr()).parent = Some(ptr) };
+                    break new;
+                }
+            }
+        };
+
+        let mut current = Some(new);
+        while let Some(ptr) = current {
+            unsafe {
+                Node::refresh(ptr);
+                let rotations = &mut self.rotations;
+                let ptr = split(skew(ptr, rotations), rotations);
+                self.root = Some(ptr);
+                current = ptr.as_ref().parent;
+            }
+        }
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let ptr = self.find(key)?;
+        // Safety: the node is part of this map, which we have exclusive access to. A node with a
+        // single child is on level one, and its child takes its place there.
+        let (mut node, parent) = unsafe { unlink(&mut self.root, ptr) };
+        if !ptr::eq(&*node, ptr.as_ptr()) {
+            // The entry was traded with its successor's, but the levels belong to the nodes.
+            let level = unsafe { &mut (*ptr.as_ptr()).data.level };
+            mem::swap(&mut node.data.level, level);
+        }
+
+        let mut current = parent;
+        while let Some(ptr) = current {
+            unsafe {
+                Node::refresh(ptr);
+                lower_level(ptr);
+                let rotations = &mut self.rotations;
+                let ptr = skew(ptr, rotations);
+                if let Some(right) = ptr.as_ref().right {
+                    let right = skew(right, rotations);
+                    if let Some(grandchild) = right.as_ref().right {
+                        skew(grandchild, rotations);
+                    }
+                }
+                let ptr = split(ptr, rotations);
+                if let Some(right) = ptr.as_ref().right {
+                    split(right, rotations);
+                }
+                self.root = Some(ptr);
+                current = ptr.as_ref().parent;
+            }
+        }
+
+        let Entry { key, value, .. } = node.data;
+        Some((key, value))
+    }
+
+    fn find<Q>(&self, key: &Q) -> Link<Entry<K, V>, SizeAug>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut ptr = self.root?;
+        loop {
+            let node = unsafe { ptr.as_ref() };
+            ptr = match key.cmp(node.get().key.borrow()) {
+                Ordering::Equal => return Some(ptr),
+                Ordering::Less => node.left?,
+                Ordering::Greater => node.right?,
+            };
+        }
+    }
+}
+
+fn level<K, V>(link: Link<Entry<K, V>, SizeAug>) -> usize {
+    link.map_or(0, |ptr| unsafe { ptr.as_ref() }.get().level)
+}
+
+/// Rotates a left child on the level of `ptr` up, returning the root of the subtree, and
+/// counting the rotation in `rotations`.
+///
+/// # Safety
+///
+/// The caller must have exclusive access to `ptr`, its parent and its subtree.
+unsafe fn skew<K, V>(ptr: NonNull<AaNode<K, V>>, rotations: &mut usize) -> NonNull<AaNode<K, V>> {
+    let node = unsafe { ptr.as_ref() };
+    if level(node.left) == node.data.level {
+        *rotations += 1;
+        unsafe { Node::rotate_right_raw(ptr) }.unwrap()
+    } else {
+        ptr
+    }
+}
+
+/// Rotates the right child of `ptr` up a level if the right grandchild is on the level of `ptr`,
+/// returning the root of the subtree.
+///
+/// # Safety
+///
+/// See [`skew`].
+unsafe fn split<K, V>(ptr: NonNull<AaNode<K, V>>, rotations: &mut usize) -> NonNull<AaNode<K, V>> {
+    let node = unsafe { ptr.as_ref() };
+    let grandchild = node.right.and_then(|right| unsafe { right.as_ref() }.right);
+    if level(grandchild) != node.data.level {
+        return ptr;
+    }
+    *rotations += 1;
+    let top = unsafe { Node::rotate_left_raw(ptr) }.unwrap();
+    unsafe { (*top.as_ptr()).data.level += 1 };
+    top
+}
+
+/// Lowers `ptr`, and a right child on its level, to just above the lower of its children.
+///
+/// # Safety
+///
+/// See [`skew`].
+unsafe fn lower_level<K, V>(ptr: NonNull<AaNode<K, V>>) {
+    let node = unsafe { &mut *ptr.as_ptr() };
+    let expected = level(node.left).min(level(node.right)) + 1;
+    if expected < node.data.level {
+        node.data.level = expected;
+        if let Some(right) = node.right {
+            let right = unsafe { &mut (*right.as_ptr()).data };
+            right.level = right.level.min(expected);
+        }
+    }
+}
+
+impl<K, V> Default for AaTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for AaTreeMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Drop for AaTreeMap<K, V> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a AaTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    next: Link<Entry<K, V>, SizeAug>,
+    remaining: usize,
+    _marker: PhantomData<&'a AaNode<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ptr = self.next?;
+        self.remaining -= 1;
+        // Safety: the map is borrowed for `'a`, so its nodes stay put and unchanged.
+        self.next = unsafe { successor(ptr) };
+        let entry = unsafe { ptr.as_ref() }.get();
+        Some((&entry.key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::random_updates;
+
+    fn check_levels<K, V>(map: &AaTreeMap<K, V>) {
+        let mut stack: Vec<_> = map.root.into_iter().collect();
+        while let Some(ptr) = stack.pop() {
+            let node = unsafe { ptr.as_ref() };
+            let here = node.data.level;
+            let grandchild = node.right.and_then(|right| unsafe { right.as_ref() }.right);
+            assert_eq!(level(node.left) + 1, here);
+            assert!(level(node.right) + 1 == here || level(node.right) == here);
+            assert!(level(grandchild) < here);
+            assert!(here == 1 || (node.left.is_some() && node.right.is_some()));
+            for child in node.left.into_iter().chain(node.right) {
+                assert_eq!(unsafe { child.as_ref() }.parent, Some(ptr));
+                stack.push(child);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut map = AaTreeMap::new();
+        let mut model = random_updates(&mut map, 3, 4000, 600, check_levels);
+        assert!(map.iter().eq(model.iter()));
+        for key in 0..600 {
+            assert_eq!(map.get(&key), model.get(&key));
+        }
+        while !model.is_empty() {
+            let key = *model.iter().next().unwrap().0;
+            assert_eq!(map.remove_entry(&key), model.remove_entry(&key));
+            check_levels(&map);
+        }
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::Node;
+
+/// Bookkeeping stored in every node, recomputed from the node's payload and the summaries of its
+/// children whenever either changes. Size, height, sum and interval-max trees are all instances.
+pub trait Augment<T> {
+    type Summary;
+
+    fn combine(
+        left: Option<&Self::Summary>,
+        data: &T,
+        right: Option<&Self::Summary>,
+    ) -> Self::Summary;
+
+    /// The number of nodes in a subtree with this summary, if it counts them.
+    fn subtree_size(_summary: &Self::Summary) -> Option<usize> {
+        None
+    }
+}
+
+/// No bookkeeping at all, the default for [`Node`].
+impl<T> Augment<T> for () {
+    type Summary = ();
+
+    fn combine(_: Option<&()>, _: &T, _: Option<&()>) {}
+}
+
+/// Keeps track of the number of nodes in every subtree.
+pub struct SizeAug;
+
+impl<T> Augment<T> for SizeAug {
+    type Summary = usize;
+
+    fn combine(left: Option<&usize>, _: &T, right: Option<&usize>) -> usize {
+        1 + left.copied().unwrap_or(0) + right.copied().unwrap_or(0)
+    }
+
+    fn subtree_size(summary: &usize) -> Option<usize> {
+        Some(*summary)
+    }
+}
+
+/// Room for the Euler numbers written by [`Node::index`], which are wiped from a node and its
+/// ancestors whenever their subtrees change.
+pub struct EulerAug;
+
+/// When a node was entered and left in a pre-order walk of the subtree [`Node::index`] was
+/// called on, counting both events on one clock. A node is an ancestor of another if it was
+/// entered before it and left after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EulerNumbers {
+    pub entry: usize,
+    pub exit: usize,
+    // Numbers are told apart from those of other calls to `index`.
+    pub(crate) generation: u64,
+}
+
+impl<T> Augment<T> for EulerAug {
+    type Summary = Option<EulerNumbers>;
+
+    fn combine(_: Option<&Self::Summary>, _: &T, _: Option<&Self::Summary>) -> Self::Summary {
+        None
+    }
+}
+
+/// Mutable access to the payload of a node, returned by [`Node::get_mut`]. The summaries of the
+/// node and its ancestors are brought up to date when this is dropped.
+pub struct DataMut<'a, T, A: Augment<T> = ()> {
+    pub(crate) node: &'a mut Node<T, A>,
+}
+
+impl<T, A: Augment<T>> Deref for DataMut<'_, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node.data
+    }
+}
+
+impl<T, A: Augment<T>> DerefMut for DataMut<'_, T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node.data
+    }
+}
+
+impl<T, A: Augment<T>> Drop for DataMut<'_, T, A> {
+    fn drop(&mut self) {
+        unsafe { Node::refresh_upwards((&mut *self.node).into()) };
+    }
+}
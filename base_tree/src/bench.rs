@@ -0,0 +1,264 @@
+//! Timings of the trees of this crate against `BTreeMap`, for payloads of the caller's choosing,
+//! so that claims about their speed can be checked on the machine and data at hand.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hint::black_box;
+use std::string::String;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::compressed::{CompactTree, Index};
+use crate::{BstMap, Side};
+
+/// Payloads to time the trees with, each made up from a number.
+pub trait Payload: Ord + Clone {
+    /// The payload for `n`, distinct for distinct numbers.
+    fn nth(n: u64) -> Self;
+}
+
+impl Payload for u64 {
+    fn nth(n: u64) -> Self {
+        n
+    }
+}
+
+impl Payload for u32 {
+    fn nth(n: u64) -> Self {
+        u32::try_from(n).expect("at most 2^32 payloads of type u32")
+    }
+}
+
+impl Payload for String {
+    fn nth(n: u64) -> Self {
+        std::format!("{n:016x}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Structure {
+    /// [`BstMap`], with a heap allocation per node linked by pointers.
+    BstMap,
+    /// A plain search tree in a [`CompactTree`], with nodes side by side linked by index.
+    Arena,
+    /// The standard library's B-tree.
+    BTreeMap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Inserting every payload, in random order.
+    Insert,
+    /// Looking up every payload, in another random order.
+    Lookup,
+    /// Visiting every payload in order.
+    Traverse,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Measurement {
+    pub structure: Structure,
+    pub operation: Operation,
+    /// How long the operation took for all payloads together.
+    pub elapsed: Duration,
+}
+
+/// The measurements of [`compare`], shown as a table with the time taken per payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub payloads: usize,
+    pub measurements: Vec<Measurement>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:<10} {:>12}",
+            "structure", "operation", "ns/payload"
+        )?;
+        for measurement in &self.measurements {
+            let nanos = measurement.elapsed.as_nanos() as f64 / self.payloads.max(1) as f64;
+            let (structure, operation) = (
+                std::format!("{:?}", measurement.structure),
+                std::format!("{:?}", measurement.operation),
+            );
+            writeln!(f, "{structure:<10} {operation:<10} {nanos:>12.1}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Times inserting, looking up and traversing `payloads` payloads of type `P` in each
+/// [`Structure`], shuffled by `seed`.
+pub fn compare<P: Payload>(payloads: usize, seed: u64) -> Report {
+    let mut lcg = crate::lcg(seed);
+    let mut random = |below: usize| lcg(below as u64) as usize;
+    let mut shuffled = |values: &mut Vec<P>| {
+        for at in (1..values.len()).rev() {
+            values.swap(at, random(at + 1));
+        }
+    };
+    let mut inserted: Vec<P> = (0..payloads as u64).map(P::nth).collect();
+    shuffled(&mut inserted);
+    let mut looked_up = inserted.clone();
+    shuffled(&mut looked_up);
+
+    let mut measurements = Vec::new();
+    let mut time = |structure, operation, run: &mut dyn FnMut()| {
+        let start = Instant::now();
+        run();
+        measurements.push(Measurement {
+            structure,
+            operation,
+            elapsed: start.elapsed(),
+        });
+    };
+
+    let mut map = BstMap::new();
+    time(Structure::BstMap, Operation::Insert, &mut || {
+        for payload in &inserted {
+            map.insert(payload.clone(), ());
+        }
+    });
+    time(Structure::BstMap, Operation::Lookup, &mut || {
+        for payload in &looked_up {
+            black_box(map.get(payload));
+        }
+    });
+    time(Structure::BstMap, Operation::Traverse, &mut || {
+        map.iter().for_each(|entry| {
+            black_box(entry);
+        });
+    });
+
+    let mut arena = Arena::default();
+    time(Structure::Arena, Operation::Insert, &mut || {
+        for payload in &inserted {
+            arena.insert(payload.clone());
+        }
+    });
+    time(Structure::Arena, Operation::Lookup, &mut || {
+        for payload in &looked_up {
+            black_box(arena.contains(payload));
+        }
+    });
+    time(Structure::Arena, Operation::Traverse, &mut || {
+        arena.for_each(|payload| {
+            black_box(payload);
+        });
+    });
+
+    let mut map = BTreeMap::new();
+    time(Structure::BTreeMap, Operation::Insert, &mut || {
+        for payload in &inserted {
+            map.insert(payload.clone(), ());
+        }
+    });
+    time(Structure::BTreeMap, Operation::Lookup, &mut || {
+        for payload in &looked_up {
+            black_box(map.get(payload));
+        }
+    });
+    time(Structure::BTreeMap, Operation::Traverse, &mut || {
+        map.iter().for_each(|entry| {
+            black_box(entry);
+        });
+    });
+
+    Report {
+        payloads,
+        measurements,
+    }
+}
+
+/// An unbalanced search tree in a [`CompactTree`], which random insertions keep shallow.
+struct Arena<P> {
+    tree: CompactTree<P>,
+    root: Option<Index>,
+}
+
+impl<P> Default for Arena<P> {
+    fn default() -> Self {
+        Self {
+            tree: CompactTree::new(),
+            root: None,
+        }
+    }
+}
+
+impl<P: Ord> Arena<P> {
+    /// Where `payload` is, or else the node and side it would go below.
+    fn search(&self, payload: &P) -> Result<Index, Option<(Index, Side)>> {
+        let mut slot = None;
+        let mut next = self.root.map(|root| self.tree.node(root));
+        while let Some(node) = next {
+            let side = match payload.cmp(node.get()) {
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+                Ordering::Equal => return Ok(node.index()),
+            };
+            slot = Some((node.index(), side));
+            next = node.child(side);
+        }
+        Err(slot)
+    }
+
+    fn insert(&mut self, payload: P) {
+        let Err(slot) = self.search(&payload) else {
+            return;
+        };
+        let index = self.tree.add(payload);
+        match slot {
+            None => self.root = Some(index),
+            // A fresh node can't be an ancestor, so there's no cycle to look for, as there isn't in
+            // `BstMap`.
+            Some((parent, side)) => self.tree.attach_unchecked(parent, side, index),
+        }
+    }
+
+    fn contains(&self, payload: &P) -> bool {
+        self.search(payload).is_ok()
+    }
+
+    fn for_each(&self, mut visit: impl FnMut(&P)) {
+        let mut stack = Vec::new();
+        let mut next = self.root.map(|root| self.tree.node(root));
+        loop {
+            while let Some(node) = next {
+                stack.push(node);
+                next = node.left();
+            }
+            let Some(node) = stack.pop() else {
+                return;
+            };
+            visit(node.get());
+            next = node.right();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_every_structure() {
+        let report = compare::<String>(500, 7);
+        assert_eq!(report.measurements.len(), 9);
+        assert!(report.measurements.iter().any(|measurement| {
+            measurement.structure == Structure::Arena && measurement.operation == Operation::Lookup
+        }));
+        assert_eq!(report.to_string().lines().count(), 10);
+
+        let mut arena = Arena::default();
+        for n in [5u64, 2, 8, 2, 1] {
+            arena.insert(n);
+        }
+        let mut order = Vec::new();
+        arena.for_each(|&n| order.push(n));
+        assert_eq!(order, [1, 2, 5, 8]);
+        assert!(arena.contains(&8) && !arena.contains(&3));
+    }
+}
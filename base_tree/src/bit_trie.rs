@@ -0,0 +1,287 @@
+//! A binary trie, descending left on a `0` bit and right on a `1` bit of its keys.
+
+use alloc::boxed::Box;
+use core::ptr::NonNull;
+
+use crate::tree::{alloc, free_subtree};
+use crate::Node;
+
+/// A key made up of bits, read from the most significant one.
+pub trait BitKey {
+    fn bit_len(&self) -> usize;
+
+    /// Bit number `index`, where `0` is the most significant. `index` is below `bit_len`.
+    fn bit(&self, index: usize) -> bool;
+}
+
+macro_rules! impl_bit_key {
+    ($($int:ty),*) => {$(
+        impl BitKey for $int {
+            fn bit_len(&self) -> usize {
+                <$int>::BITS as usize
+            }
+
+            fn bit(&self, index: usize) -> bool {
+                (self >> (<$int>::BITS as usize - 1 - index)) & 1 == 1
+            }
+        }
+    )*};
+}
+
+impl_bit_key!(u8, u16, u32, u64, u128, usize);
+
+impl BitKey for &[u8] {
+    fn bit_len(&self) -> usize {
+        self.len() * 8
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        (self[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+}
+
+impl<const N: usize> BitKey for &[u8; N] {
+    fn bit_len(&self) -> usize {
+        N * 8
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.as_slice().bit(index)
+    }
+}
+
+impl BitKey for &str {
+    fn bit_len(&self) -> usize {
+        self.as_bytes().bit_len()
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.as_bytes().bit(index)
+    }
+}
+
+/// A map from bit strings to values. Besides whole keys, any prefix of a key can be used, which
+/// makes it a routing table: [`BitTrie::longest_prefix_match`] finds the most specific prefix
+/// stored for an address.
+pub struct BitTrie<V> {
+    // The root stands for the empty prefix, and always exists. Every other node is either holding
+    // a value or has a descendant that does.
+    root: NonNull<Node<Option<V>>>,
+    len: usize,
+}
+
+impl<V> BitTrie<V> {
+    pub fn new() -> Self {
+        Self {
+            root: alloc(None),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: impl BitKey, value: V) -> Option<V> {
+        let bits = key.bit_len();
+        self.insert_prefix(key, bits, value)
+    }
+
+    /// Stores `value` under the first `bits` bits of `key`.
+    ///
+    /// # Panics
+    ///
+    /// If `key` is shorter than `bits`.
+    pub fn insert_prefix(&mut self, key: impl BitKey, bits: usize, value: V) -> Option<V> {
+        assert!(bits <= key.bit_len(), "prefix longer than its key");
+        let mut ptr = self.root;
+        for index in 0..bits {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            let slot = if key.bit(index) {
+                &mut node.right
+            } else {
+                &mut node.left
+            };
+            ptr = *slot.get_or_insert_with(|| {
+                let child = alloc(None);
+                unsafe { (*child.as_ptr()).parent = Some(ptr) };
+                child
+            });
+        }
+        let old = unsafe { &mut *ptr.as_ptr() }.data.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn get(&self, key: impl BitKey) -> Option<&V> {
+        let bits = key.bit_len();
+        self.get_prefix(key, bits)
+    }
+
+    /// The value stored under exactly the first `bits` bits of `key`.
+    pub fn get_prefix(&self, key: impl BitKey, bits: usize) -> Option<&V> {
+        let ptr = self.find(&key, bits)?;
+        unsafe { ptr.as_ref() }.get().as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: impl BitKey) -> Option<&mut V> {
+        let bits = key.bit_len();
+        let ptr = self.find(&key, bits)?;
+        unsafe { &mut *ptr.as_ptr() }.data.as_mut()
+    }
+
+    /// The value stored under the longest prefix of `key`, along with the length of that prefix.
+    pub fn longest_prefix_match(&self, key: impl BitKey) -> Option<(usize, &V)> {
+        let mut best = None;
+        let mut node = Some(unsafe { self.root.as_ref() });
+        let mut depth = 0;
+        while let Some(current) = node {
+            if let Some(value) = current.get() {
+                best = Some((depth, value));
+            }
+            if depth == key.bit_len() {
+                break;
+            }
+            node = if key.bit(depth) {
+                current.right()
+            } else {
+                current.left()
+            };
+            depth += 1;
+        }
+        best
+    }
+
+    pub fn remove(&mut self, key: impl BitKey) -> Option<V> {
+        let bits = key.bit_len();
+        self.remove_prefix(key, bits)
+    }
+
+    /// Removes the value stored under exactly the first `bits` bits of `key`, pruning the nodes
+    /// that no longer lead to any value.
+    pub fn remove_prefix(&mut self, key: impl BitKey, bits: usize) -> Option<V> {
+        let mut ptr = self.find(&key, bits)?;
+        let value = unsafe { &mut *ptr.as_ptr() }.data.take()?;
+        self.len -= 1;
+
+        loop {
+            let node = unsafe { ptr.as_ref() };
+            let (None, None, None, Some(parent)) = (node.left, node.right, node.get(), node.parent)
+            else {
+                break;
+            };
+            let parent_node = unsafe { &mut *parent.as_ptr() };
+            if parent_node.left == Some(ptr) {
+                parent_node.left = None;
+            } else {
+                parent_node.right = None;
+            }
+            // Safety: the node was allocated by this trie, and is no longer linked.
+            drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+            ptr = parent;
+        }
+        Some(value)
+    }
+
+    fn find(&self, key: &impl BitKey, bits: usize) -> Option<NonNull<Node<Option<V>>>> {
+        if bits > key.bit_len() {
+            return None;
+        }
+        let mut ptr = self.root;
+        for index in 0..bits {
+            let node = unsafe { ptr.as_ref() };
+            ptr = if key.bit(index) {
+                node.right
+            } else {
+                node.left
+            }?;
+        }
+        Some(ptr)
+    }
+}
+
+impl<V> Default for BitTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Drop for BitTrie<V> {
+    fn drop(&mut self) {
+        unsafe { free_subtree(self.root) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routing_table() {
+        let mut routes = BitTrie::new();
+        assert_eq!(routes.insert_prefix(0x0a00_0000u32, 8, "10/8"), None);
+        assert_eq!(routes.insert_prefix(0x0a01_0000u32, 16, "10.1/16"), None);
+        assert_eq!(routes.insert_prefix(0u32, 0, "default"), None);
+        assert_eq!(routes.insert(0x0a01_0203u32, "host"), None);
+        assert_eq!(
+            routes.insert_prefix(0x0a00_0000u32, 8, "10/8 again"),
+            Some("10/8")
+        );
+        assert_eq!(routes.len(), 4);
+
+        assert_eq!(
+            routes.longest_prefix_match(0x0a01_0203u32),
+            Some((32, &"host"))
+        );
+        assert_eq!(
+            routes.longest_prefix_match(0x0a01_0204u32),
+            Some((16, &"10.1/16"))
+        );
+        assert_eq!(
+            routes.longest_prefix_match(0x0a02_0000u32),
+            Some((8, &"10/8 again"))
+        );
+        assert_eq!(
+            routes.longest_prefix_match(0x0b00_0000u32),
+            Some((0, &"default"))
+        );
+        assert_eq!(routes.get_prefix(0x0a01_ffffu32, 16), Some(&"10.1/16"));
+        assert_eq!(routes.get(0x0a01_0000u32), None);
+
+        assert_eq!(routes.remove(0x0a01_0203u32), Some("host"));
+        assert_eq!(routes.remove(0x0a01_0203u32), None);
+        assert_eq!(
+            routes.longest_prefix_match(0x0a01_0203u32),
+            Some((16, &"10.1/16"))
+        );
+        assert_eq!(routes.remove_prefix(0x0a01_0000u32, 16), Some("10.1/16"));
+        // Only the path down to 10/8 is left below the root.
+        let mut depth = 0;
+        let mut node = unsafe { routes.root.as_ref() };
+        while let Some(child) = node.left().or(node.right()) {
+            node = child;
+            depth += 1;
+        }
+        assert_eq!(depth, 8);
+    }
+
+    #[test]
+    fn byte_string_keys() {
+        let mut trie = BitTrie::new();
+        trie.insert("ab", 1);
+        trie.insert(b"a", 2);
+        trie.insert(&[0x61u8, 0x62, 0x63][..], 3);
+        assert_eq!(trie.get("a"), Some(&2));
+        *trie.get_mut("abc").unwrap() += 10;
+        assert_eq!(trie.longest_prefix_match("abd"), Some((16, &1)));
+        assert_eq!(trie.longest_prefix_match("abc"), Some((24, &13)));
+        assert_eq!(trie.longest_prefix_match("b"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::{fmt, mem};
+
+use crate::{Augment, DataMut, Node};
+
+/// The key to a tree opened with [`Node::brand`]: reading through a [`BrandedNode`] borrows the
+/// token, and writing borrows it mutably, so any number of nodes can be read at once while none
+/// is written.
+pub struct TreeToken<'brand> {
+    // Invariant, so that a token only opens the nodes of its own call to `Node::brand`.
+    _brand: Brand<'brand>,
+}
+
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// A handle to a node of a tree opened with [`Node::brand`], which can be copied and kept
+/// alongside handles to any other node of the same tree.
+pub struct BrandedNode<'tree, 'brand, T, A: Augment<T> = ()> {
+    ptr: NonNull<Node<T, A>>,
+    // The node `Node::brand` was called on, above which nothing is reachable.
+    root: NonNull<Node<T, A>>,
+    _marker: (PhantomData<&'tree mut Node<T, A>>, Brand<'brand>),
+}
+
+impl<T, A: Augment<T>> Node<T, A> {
+    /// Calls `f` with a handle to this node and the only [`TreeToken`] for it, through which this
+    /// node and its descendants are read and written for the length of the call.
+    pub fn brand<'tree, R>(
+        &'tree mut self,
+        f: impl for<'brand> FnOnce(BrandedNode<'tree, 'brand, T, A>, TreeToken<'brand>) -> R,
+    ) -> R {
+        let root = NonNull::from(self);
+        let node = BrandedNode {
+            ptr: root,
+            root,
+            _marker: (PhantomData, PhantomData),
+        };
+        f(
+            node,
+            TreeToken {
+                _brand: PhantomData,
+            },
+        )
+    }
+}
+
+impl<'tree, 'brand, T, A: Augment<T>> BrandedNode<'tree, 'brand, T, A> {
+    fn at(self, ptr: Option<NonNull<Node<T, A>>>) -> Option<Self> {
+        ptr.map(|ptr| Self { ptr, ..self })
+    }
+
+    pub fn left(self, _token: &TreeToken<'brand>) -> Option<Self> {
+        // Safety: the tree is borrowed for 'tree, and only written through the token, which is
+        // borrowed here.
+        self.at(unsafe { self.ptr.as_ref() }.left)
+    }
+
+    pub fn right(self, _token: &TreeToken<'brand>) -> Option<Self> {
+        // Safety: as above.
+        self.at(unsafe { self.ptr.as_ref() }.right)
+    }
+
+    /// The parent of this node, or `None` for the node the tree was opened at.
+    pub fn parent(self, _token: &TreeToken<'brand>) -> Option<Self> {
+        if self.ptr == self.root {
+            return None;
+        }
+        // Safety: as above.
+        self.at(unsafe { self.ptr.as_ref() }.parent)
+    }
+
+    pub fn get<'a>(self, _token: &'a TreeToken<'brand>) -> &'a T
+    where
+        'tree: 'a,
+    {
+        // Safety: as above, for as long as the token stays borrowed.
+        &unsafe { self.ptr.as_ref() }.data
+    }
+
+    pub fn summary<'a>(self, _token: &'a TreeToken<'brand>) -> &'a A::Summary
+    where
+        'tree: 'a,
+    {
+        // Safety: as above.
+        &unsafe { self.ptr.as_ref() }.summary
+    }
+
+    /// The payload of this node, as [`Node::get_mut`] gives it.
+    pub fn get_mut<'a>(self, _token: &'a mut TreeToken<'brand>) -> DataMut<'a, T, A>
+    where
+        'tree: 'a,
+    {
+        // Safety: the token is borrowed mutably, so nothing else reads or writes the tree, and
+        // the ancestors refreshed after the write are covered by the borrow `Node::brand` took.
+        unsafe { &mut *self.ptr.as_ptr() }.get_mut()
+    }
+
+    /// Swaps payloads with `other`, as [`Node::swap_data`] does.
+    pub fn swap_data(self, other: Self, _token: &mut TreeToken<'brand>) {
+        if self.ptr == other.ptr {
+            return;
+        }
+        // Safety: as above, and the nodes are distinct.
+        unsafe {
+            mem::swap(
+                &mut (*self.ptr.as_ptr()).data,
+                &mut (*other.ptr.as_ptr()).data,
+            );
+            Node::refresh_upwards(self.ptr);
+            Node::refresh_upwards(other.ptr);
+        }
+    }
+}
+
+impl<T, A: Augment<T>> Clone for BrandedNode<'_, '_, T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, A: Augment<T>> Copy for BrandedNode<'_, '_, T, A> {}
+
+impl<T, A: Augment<T>> PartialEq for BrandedNode<'_, '_, T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T, A: Augment<T>> Eq for BrandedNode<'_, '_, T, A> {}
+
+impl<T, A: Augment<T>> fmt::Debug for BrandedNode<'_, '_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BrandedNode").field(&self.ptr).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SizeAug;
+
+    #[test]
+    fn reads_coexist_until_a_write() {
+        let (mut root, mut a, mut b, mut c) = (
+            Node::<i32, SizeAug>::augmented(1),
+            Node::augmented(0),
+            Node::augmented(2),
+            Node::augmented(3),
+        );
+        b.replace_right(Some(&mut c));
+        root.replace_left(Some(&mut a));
+        root.replace_right(Some(&mut b));
+
+        let sum = root.brand(|root, mut token| {
+            let left = root.left(&token).unwrap();
+            let deepest = root.right(&token).unwrap().right(&token).unwrap();
+            // References into several nodes at once.
+            let (low, high) = (left.get(&token), deepest.get(&token));
+            assert_eq!((*low, *high), (0, 3));
+            assert_eq!(deepest.parent(&token).unwrap().parent(&token), Some(root));
+            assert_eq!(root.parent(&token), None);
+
+            *deepest.get_mut(&mut token) *= 10;
+            left.swap_data(deepest, &mut token);
+            assert_eq!(*left.get(&token), 30);
+            assert_eq!(*root.summary(&token), 4);
+            [left, root, deepest.parent(&token).unwrap(), deepest]
+                .iter()
+                .map(|node| *node.get(&token))
+                .sum::<i32>()
+        });
+        assert_eq!(sum, 33);
+        assert_eq!(*root.left().unwrap().get(), 30);
+    }
+}
@@ -0,0 +1,1876 @@
+//! Ordered maps kept as binary search trees.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+use core::ptr::NonNull;
+
+use crate::tree::{
+    alloc, free_subtree, leftmost, link_in_order, predecessor, rightmost, successor, unlink, Link,
+};
+use crate::{Node, Side, SizeAug, TreeStats};
+
+/// No subtree may hold more than this share of its parent's nodes for long.
+pub(crate) const ALPHA: f64 = 0.7;
+
+type MapNode<K, V> = Node<(K, V), SizeAug>;
+
+/// An ordered map, balanced as a scapegoat tree. Nodes carry nothing but their subtree size:
+/// an insertion landing too deep rebuilds the subtree of an ancestor that has become lopsided,
+/// and enough removals rebuild the whole tree, for amortized O(log n) updates and worst case
+/// O(log n) lookups. The sizes also make order statistics cheap.
+///
+/// Keys are ordered by `C`, which is their [`Ord`] implementation unless the map is made
+/// [`with_comparator`](BstMap::with_comparator). Keys the comparator finds equal share an entry.
+pub struct BstMap<K, V, C = Natural> {
+    root: Link<(K, V), SizeAug>,
+    // The largest the map has been since it was last rebuilt completely.
+    max_len: usize,
+    rebuilds: usize,
+    cmp: C,
+    _marker: PhantomData<Box<MapNode<K, V>>>,
+}
+
+/// How a [`BstMap`] orders its keys, and the keys it is looked up by. Any
+/// `Fn(&Q, &Q) -> Ordering` will do, which must be a total order, as [`Ord`] would be.
+pub trait Compare<Q: ?Sized> {
+    fn compare(&self, a: &Q, b: &Q) -> Ordering;
+}
+
+/// Orders keys by their [`Ord`] implementation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Natural;
+
+impl<Q: Ord + ?Sized> Compare<Q> for Natural {
+    fn compare(&self, a: &Q, b: &Q) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<Q: ?Sized, F: Fn(&Q, &Q) -> Ordering> Compare<Q> for F {
+    fn compare(&self, a: &Q, b: &Q) -> Ordering {
+        self(a, b)
+    }
+}
+
+impl<K, V> BstMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<K, V, C> BstMap<K, V, C> {
+    /// An empty map ordering its keys by `cmp`, such as a closure comparing strings without
+    /// regard to case.
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            max_len: 0,
+            rebuilds: 0,
+            cmp,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The comparator the keys are ordered by.
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.map_or(0, |root| unsafe { root.as_ref() }.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        unsafe { Iter::new(self.root) }
+    }
+
+    /// Counts the nodes, leaves and levels of the tree.
+    pub fn stats(&self) -> TreeStats {
+        self.root
+            .map(|root| TreeStats::of(unsafe { root.as_ref() }))
+            .unwrap_or_default()
+    }
+
+    /// How many times a subtree, or the whole tree, has been rebuilt to restore the balance.
+    pub fn rebuilds(&self) -> usize {
+        self.rebuilds
+    }
+
+    /// The entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        unsafe { key_value(self.root.map(|root| leftmost(root))) }
+    }
+
+    /// The entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        unsafe { key_value(self.root.map(|root| rightmost(root))) }
+    }
+
+    /// Removes the entry with the smallest key, so the map can serve as a priority queue.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let ptr = unsafe { leftmost(self.root?) };
+        // Safety: the node is part of this map.
+        Some(unsafe { self.remove_node(ptr) })
+    }
+
+    /// Removes the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let ptr = unsafe { rightmost(self.root?) };
+        // Safety: the node is part of this map.
+        Some(unsafe { self.remove_node(ptr) })
+    }
+
+    /// The entry with the smallest key, to look at and then maybe remove.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V, C>> {
+        let ptr = unsafe { leftmost(self.root?) };
+        Some(OccupiedEntry { map: self, ptr })
+    }
+
+    /// The entry with the largest key.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V, C>> {
+        let ptr = unsafe { rightmost(self.root?) };
+        Some(OccupiedEntry { map: self, ptr })
+    }
+
+    /// Rebalances the whole tree, which is then allowed to shrink by a factor `ALPHA` before that
+    /// happens again.
+    fn rebuild(&mut self) {
+        if let Some(root) = self.root {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            self.root = Some(unsafe { (*root.as_ptr()).balance() }.into());
+            self.rebuilds += 1;
+        }
+        self.max_len = self.len();
+    }
+
+    /// Keeps only the entries `f` returns true for, calling it on every entry in key order. The
+    /// tree is rebuilt once at the end, perfectly balanced, for O(n) in all rather than a
+    /// removal for each dropped entry.
+    pub fn retain(&mut self, f: impl FnMut(&K, &mut V) -> bool) {
+        if !self.is_empty() {
+            self.rebuild_keeping(f);
+        }
+    }
+
+    /// Drops the entries `keep` turns down, then rebuilds the tree from the rest in one go,
+    /// perfectly balanced. This takes O(n), whatever is dropped.
+    pub(crate) fn rebuild_keeping(&mut self, mut keep: impl FnMut(&K, &mut V) -> bool) {
+        let mut nodes = Vec::with_capacity(self.len());
+        let mut next = self.root.map(|root| unsafe { leftmost(root) });
+        while let Some(ptr) = next {
+            nodes.push(ptr);
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            next = unsafe { successor(ptr) };
+        }
+        // Decided before anything is unlinked, so that a panic in `keep` leaves a sound tree.
+        let kept: Vec<bool> = nodes
+            .iter()
+            .map(|ptr| {
+                let (key, value) = &mut unsafe { &mut *ptr.as_ptr() }.data;
+                keep(key, value)
+            })
+            .collect();
+
+        self.root = None;
+        let mut live = Vec::with_capacity(nodes.len());
+        for (ptr, kept) in nodes.into_iter().zip(kept) {
+            let node = unsafe { &mut *ptr.as_ptr() };
+            (node.left, node.right, node.parent) = (None, None, None);
+            if kept {
+                live.push(ptr);
+            } else {
+                drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+            }
+        }
+        self.root = unsafe { link_in_order(&live, |start, end| start + (end - start) / 2) };
+        self.max_len = live.len();
+        self.rebuilds += 1;
+    }
+
+    /// Unlinks and frees `ptr`, rebuilding the tree once it has shrunk enough.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a node of this map.
+    unsafe fn remove_node(&mut self, ptr: NonNull<MapNode<K, V>>) -> (K, V) {
+        let (node, parent) = unsafe { unlink(&mut self.root, ptr) };
+        if let Some(parent) = parent {
+            unsafe { Node::refresh_upwards(parent) };
+        }
+        if (self.len() as f64) < ALPHA * self.max_len as f64 {
+            self.rebuild();
+        }
+        node.data
+    }
+}
+
+impl<K, V, C: Compare<K>> BstMap<K, V, C> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.find(key).map(|ptr| &unsafe { ptr.as_ref() }.get().1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.find(key)
+            .map(|ptr| &mut unsafe { &mut *ptr.as_ptr() }.data.1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Iterates over the entries with keys in `range`, in key order. Finding both ends takes
+    /// O(log n).
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+        R: RangeBounds<Q>,
+    {
+        unsafe { Iter::range(self.root, &self.cmp, range.start_bound(), range.end_bound()) }
+    }
+
+    /// The entry with the largest key at most `key`.
+    pub fn floor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { key_value(last_until(self.root, &self.cmp, Bound::Included(key))) }
+    }
+
+    /// The entry with the smallest key at least `key`.
+    pub fn ceiling<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { key_value(first_from(self.root, &self.cmp, Bound::Included(key))) }
+    }
+
+    /// The entry with the largest key below `key`.
+    pub fn lower<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { key_value(last_until(self.root, &self.cmp, Bound::Excluded(key))) }
+    }
+
+    /// The entry with the smallest key above `key`.
+    pub fn upper<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { key_value(first_from(self.root, &self.cmp, Bound::Excluded(key))) }
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaces, if any. The key already in
+    /// the map is kept in that case.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// The entry for `key`, found with a single search whether it is then read, changed, filled
+    /// in or removed.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        match self.search(&key) {
+            Ok(ptr) => Entry::Occupied(OccupiedEntry { map: self, ptr }),
+            Err((slot, depth)) => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+                slot,
+                depth,
+            }),
+        }
+    }
+
+    /// Gives the entry under `old` the key `new`, returning the old key. No node is allocated or
+    /// freed: the entry stays where it is if the new key falls between the keys on either side
+    /// of it, and otherwise the node unlinked for it is hung back in where the new key goes.
+    /// Fails, handing back `new`, if there is no entry under `old` or another one under `new`.
+    pub fn replace_key<Q>(&mut self, old: &Q, new: K) -> Result<K, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        let Some(ptr) = self.find(old) else {
+            return Err(new);
+        };
+        // Safety: we have exclusive access to all nodes through `&mut self`.
+        let fits = unsafe {
+            predecessor(ptr).is_none_or(|before| self.less(&before.as_ref().data.0, &new))
+                && successor(ptr).is_none_or(|after| self.less(&new, &after.as_ref().data.0))
+        };
+        if fits {
+            return Ok(mem::replace(&mut unsafe { &mut *ptr.as_ptr() }.data.0, new));
+        }
+        if self.search(&new).is_ok() {
+            return Err(new);
+        }
+
+        let (node, parent) = unsafe { unlink(&mut self.root, ptr) };
+        if let Some(parent) = parent {
+            unsafe { Node::refresh_upwards(parent) };
+        }
+        let node = NonNull::from(Box::leak(node));
+        let old = mem::replace(&mut unsafe { &mut *node.as_ptr() }.data.0, new);
+        // Searched for again, as unlinking changed the tree.
+        let Err((slot, depth)) = self.search(unsafe { &node.as_ref().data.0 }) else {
+            unreachable!("the new key is not in the map");
+        };
+        unsafe { self.attach(node, slot, depth) };
+        Ok(old)
+    }
+
+    /// The node holding `key`, or else the side of a node where it would be hung, along with
+    /// the depth it would land at.
+    #[allow(clippy::type_complexity)]
+    fn search(
+        &self,
+        key: &K,
+    ) -> Result<NonNull<MapNode<K, V>>, (Option<(NonNull<MapNode<K, V>>, Side)>, usize)> {
+        let (mut current, mut slot, mut depth) = (self.root, None, 1);
+        while let Some(ptr) = current {
+            // Safety: the map is borrowed, so its nodes stay put and unchanged.
+            let node = unsafe { ptr.as_ref() };
+            let side = match self.cmp.compare(key, &node.data.0) {
+                Ordering::Equal => return Ok(ptr),
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            current = node.link(side);
+            slot = Some((ptr, side));
+            depth += 1;
+        }
+        Err((slot, depth))
+    }
+
+    /// Hangs the unlinked node `new` on the free side `slot` names, or makes it the root if
+    /// there is none, and rebalances like [`BstMap::insert`] does if it lands `depth` deep.
+    ///
+    /// # Safety
+    ///
+    /// `new` must be unlinked and belong at `slot` in order, and the side there must be free.
+    unsafe fn attach(
+        &mut self,
+        new: NonNull<MapNode<K, V>>,
+        slot: Option<(NonNull<MapNode<K, V>>, Side)>,
+        depth: usize,
+    ) {
+        match slot {
+            None => self.root = Some(new),
+            Some((parent, side)) => unsafe {
+                *(*parent.as_ptr()).link_mut(side) = Some(new);
+                (*new.as_ptr()).parent = Some(parent);
+            },
+        }
+        unsafe { Node::refresh_upwards(new) };
+
+        let len = self.len();
+        self.max_len = self.max_len.max(len);
+        if depth > depth_limit(len) {
+            unsafe { self.rebuild_scapegoat(new) };
+        }
+    }
+
+    /// Inserts entries sorted by key, like [`BstMap::insert`] would. Each entry with a key greater
+    /// than any in the map is appended to the right spine in O(1), and the spine is rebalanced
+    /// once the run of such entries ends. Any other entries are inserted one at a time.
+    pub fn insert_sorted_batch(&mut self, entries: impl IntoIterator<Item = (K, V)>) {
+        let mut tail = self.root.map(|root| unsafe { rightmost(root) });
+        let mut appended = false;
+        for (key, value) in entries {
+            if tail.is_some_and(|tail| !self.less(&unsafe { tail.as_ref() }.data.0, &key)) {
+                if appended {
+                    unsafe { self.rebalance_spine(tail.unwrap()) };
+                    appended = false;
+                }
+                self.insert(key, value);
+                tail = self.root.map(|root| unsafe { rightmost(root) });
+                continue;
+            }
+
+            let new = alloc((key, value));
+            match tail {
+                None => self.root = Some(new),
+                // Safety: we have exclusive access to all nodes through `&mut self`.
+                Some(tail) => unsafe {
+                    (*tail.as_ptr()).right = Some(new);
+                    (*new.as_ptr()).parent = Some(tail);
+                },
+            }
+            tail = Some(new);
+            appended = true;
+        }
+        if let Some(tail) = tail.filter(|_| appended) {
+            unsafe { self.rebalance_spine(tail) };
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        let ptr = self.find(key)?;
+        // Safety: the node is part of this map.
+        Some(unsafe { self.remove_node(ptr) })
+    }
+
+    /// A cursor at the first entry with a key within `bound`, or at the ghost past the end if
+    /// there is none.
+    pub fn lower_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> MapCursorMut<'_, K, V, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        let current = unsafe { first_from(self.root, &self.cmp, bound) };
+        MapCursorMut { map: self, current }
+    }
+
+    fn less(&self, a: &K, b: &K) -> bool {
+        self.cmp.compare(a, b) == Ordering::Less
+    }
+
+    fn find<Q>(&self, key: &Q) -> Link<(K, V), SizeAug>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { find(self.root, &self.cmp, key) }
+    }
+
+    /// Brings the sizes along the right spine up to date after appending to it up to `tail`, and
+    /// rebalances the subtree of the highest node on the spine with a child holding more than
+    /// `ALPHA` of its nodes.
+    ///
+    /// # Safety
+    ///
+    /// `tail` must be the last node of this map.
+    unsafe fn rebalance_spine(&mut self, tail: NonNull<MapNode<K, V>>) {
+        unsafe { Node::refresh_upwards(tail) };
+        self.max_len = self.max_len.max(self.len());
+
+        let mut current = self.root;
+        while let Some(ptr) = current {
+            let node = unsafe { &mut *ptr.as_ptr() };
+            let heavy = [node.left, node.right]
+                .into_iter()
+                .flatten()
+                .any(|child| unsafe { child.as_ref() }.size() as f64 > ALPHA * node.size() as f64);
+            if heavy {
+                self.rebuilds += 1;
+                let rebuilt = node.balance();
+                if rebuilt.parent.is_none() {
+                    self.root = Some(rebuilt.into());
+                }
+                return;
+            }
+            current = node.right;
+        }
+    }
+
+    /// Rebalances the subtree of the lowest ancestor of `new` having a child that holds more than
+    /// `ALPHA` of its nodes. One exists whenever `new` is deeper than `depth_limit` allows.
+    ///
+    /// # Safety
+    ///
+    /// `new` must be a node of this map.
+    unsafe fn rebuild_scapegoat(&mut self, new: NonNull<MapNode<K, V>>) {
+        let mut child = unsafe { &*new.as_ptr() };
+        while let Some(parent) = child.parent {
+            let parent = unsafe { &mut *parent.as_ptr() };
+            if child.size() as f64 > ALPHA * parent.size() as f64 {
+                self.rebuilds += 1;
+                let rebuilt = parent.balance();
+                if rebuilt.parent.is_none() {
+                    self.root = Some(rebuilt.into());
+                }
+                return;
+            }
+            child = parent;
+        }
+    }
+}
+
+/// A place in a [`BstMap`], holding an entry or not, as given by [`BstMap::entry`].
+pub enum Entry<'a, K, V, C = Natural> {
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    Vacant(VacantEntry<'a, K, V, C>),
+}
+
+/// An entry that is in the map.
+pub struct OccupiedEntry<'a, K, V, C = Natural> {
+    map: &'a mut BstMap<K, V, C>,
+    ptr: NonNull<MapNode<K, V>>,
+}
+
+/// A key that is not in the map, together with where it would go.
+pub struct VacantEntry<'a, K, V, C = Natural> {
+    map: &'a mut BstMap<K, V, C>,
+    key: K,
+    // The node to hang the new one below, and on which side. None for an empty map.
+    slot: Option<(NonNull<MapNode<K, V>>, Side)>,
+    depth: usize,
+}
+
+impl<'a, K, V, C: Compare<K>> Entry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with(|| value)
+    }
+
+    pub fn or_insert_with(self, f: impl FnOnce() -> V) -> &'a mut V {
+        self.or_insert_with_key(|_| f())
+    }
+
+    /// Fills a vacant entry with what `f` makes of its key.
+    pub fn or_insert_with_key(self, f: impl FnOnce(&K) -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = f(&entry.key);
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value of an occupied entry, then hands the entry on.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V, C: Compare<K>> OccupiedEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        // Safety: the map is borrowed mutably for `'a`, and the node is part of it.
+        &unsafe { self.ptr.as_ref() }.data.0
+    }
+
+    pub fn get(&self) -> &V {
+        &unsafe { self.ptr.as_ref() }.data.1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut unsafe { &mut *self.ptr.as_ptr() }.data.1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut unsafe { &mut *self.ptr.as_ptr() }.data.1
+    }
+
+    /// Replaces the value, returning the old one. The key stays as it is.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        unsafe { self.map.remove_node(self.ptr) }
+    }
+}
+
+impl<'a, K, V, C: Compare<K>> VacantEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Puts `value` in the entry, rebalancing like [`BstMap::insert`] does.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let new = alloc((self.key, value));
+        // Safety: the map is borrowed mutably, and has not changed since the search.
+        unsafe { self.map.attach(new, self.slot, self.depth) };
+        // Rebuilding moves nodes around, but not their payloads.
+        &mut unsafe { &mut *new.as_ptr() }.data.1
+    }
+}
+
+impl<K: Debug, V: Debug, C> Debug for Entry<'_, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Occupied").field(entry).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Vacant").field(entry).finish(),
+        }
+    }
+}
+
+impl<K: Debug, V: Debug, C> Debug for OccupiedEntry<'_, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (key, value) = unsafe { self.ptr.as_ref() }.get();
+        f.debug_struct("OccupiedEntry")
+            .field("key", key)
+            .field("value", value)
+            .finish()
+    }
+}
+
+impl<K: Debug, V, C> Debug for VacantEntry<'_, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.key).finish()
+    }
+}
+
+/// A cursor over a [`BstMap`] that can change it in place, as given by
+/// [`BstMap::lower_bound_mut`]. It points at an entry, or at a ghost between the last entry and
+/// the first, so that it can go around in both directions.
+pub struct MapCursorMut<'a, K, V, C = Natural> {
+    map: &'a mut BstMap<K, V, C>,
+    current: Link<(K, V), SizeAug>,
+}
+
+impl<K, V, C: Compare<K>> MapCursorMut<'_, K, V, C> {
+    /// The key at the cursor, or None at the ghost.
+    pub fn key(&self) -> Option<&K> {
+        // Safety: the map is borrowed mutably by the cursor, and its nodes are only changed
+        // through it.
+        self.current.map(|ptr| &unsafe { ptr.as_ref() }.data.0)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.current.map(|ptr| &unsafe { ptr.as_ref() }.data.1)
+    }
+
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        self.current
+            .map(|ptr| &mut unsafe { &mut *ptr.as_ptr() }.data.1)
+    }
+
+    /// Moves to the next entry, or from the ghost to the first entry.
+    pub fn move_next(&mut self) {
+        self.current = self.next();
+    }
+
+    /// Moves to the previous entry, or from the ghost to the last entry.
+    pub fn move_prev(&mut self) {
+        self.current = self.prev();
+    }
+
+    pub fn peek_next(&self) -> Option<(&K, &V)> {
+        unsafe { key_value(self.next()) }
+    }
+
+    pub fn peek_prev(&self) -> Option<(&K, &V)> {
+        unsafe { key_value(self.prev()) }
+    }
+
+    /// Inserts an entry just after the cursor, or at the front at the ghost, leaving the cursor
+    /// where it is.
+    ///
+    /// # Panics
+    ///
+    /// If `key` doesn't sort strictly between the entries on either side of the new one.
+    pub fn insert_after(&mut self, key: K, value: V) {
+        let (before, after) = (self.current, self.next());
+        self.check_order(&key, before, after);
+        let slot = match self.current {
+            None => self
+                .map
+                .root
+                .map(|root| unsafe { (leftmost(root), Side::Left) }),
+            // Safety: the cursor borrows the map mutably.
+            Some(ptr) => Some(match unsafe { ptr.as_ref() }.right {
+                None => (ptr, Side::Right),
+                Some(right) => (unsafe { leftmost(right) }, Side::Left),
+            }),
+        };
+        unsafe { self.insert_at(slot, key, value) };
+    }
+
+    /// Inserts an entry just before the cursor, or at the back at the ghost, leaving the cursor
+    /// where it is.
+    ///
+    /// # Panics
+    ///
+    /// If `key` doesn't sort strictly between the entries on either side of the new one.
+    pub fn insert_before(&mut self, key: K, value: V) {
+        let (before, after) = (self.prev(), self.current);
+        self.check_order(&key, before, after);
+        let slot = match self.current {
+            None => self
+                .map
+                .root
+                .map(|root| unsafe { (rightmost(root), Side::Right) }),
+            Some(ptr) => Some(match unsafe { ptr.as_ref() }.left {
+                None => (ptr, Side::Left),
+                Some(left) => (unsafe { rightmost(left) }, Side::Right),
+            }),
+        };
+        unsafe { self.insert_at(slot, key, value) };
+    }
+
+    /// Removes the entry at the cursor, which moves on to the next one. Nothing happens at the
+    /// ghost.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let ptr = self.current?;
+        let node = unsafe { ptr.as_ref() };
+        // A node with two children takes over the payload of its successor, and stays.
+        self.current = match (node.left, node.right) {
+            (Some(_), Some(_)) => Some(ptr),
+            _ => unsafe { successor(ptr) },
+        };
+        // Rebuilding moves nodes around, but not their payloads, so the cursor stays valid.
+        Some(unsafe { self.map.remove_node(ptr) })
+    }
+
+    fn next(&self) -> Link<(K, V), SizeAug> {
+        match self.current {
+            None => self.map.root.map(|root| unsafe { leftmost(root) }),
+            Some(ptr) => unsafe { successor(ptr) },
+        }
+    }
+
+    fn prev(&self) -> Link<(K, V), SizeAug> {
+        match self.current {
+            None => self.map.root.map(|root| unsafe { rightmost(root) }),
+            Some(ptr) => unsafe { predecessor(ptr) },
+        }
+    }
+
+    fn check_order(&self, key: &K, before: Link<(K, V), SizeAug>, after: Link<(K, V), SizeAug>) {
+        let key_of = |link: Link<(K, V), SizeAug>| link.map(|ptr| &unsafe { ptr.as_ref() }.data.0);
+        assert!(
+            key_of(before).is_none_or(|before| self.map.less(before, key))
+                && key_of(after).is_none_or(|after| self.map.less(key, after)),
+            "the key is out of order at the cursor"
+        );
+    }
+
+    /// Hangs a new node on the free side `slot` names, or makes it the root if there is none.
+    ///
+    /// # Safety
+    ///
+    /// The side of the node in `slot` must be free, and the new node must belong there in order.
+    unsafe fn insert_at(&mut self, slot: Option<(NonNull<MapNode<K, V>>, Side)>, key: K, value: V) {
+        let mut depth = 1;
+        let mut ancestor = slot.map(|(parent, _)| parent);
+        while let Some(ptr) = ancestor {
+            depth += 1;
+            ancestor = unsafe { ptr.as_ref() }.parent;
+        }
+        unsafe { self.map.attach(alloc((key, value)), slot, depth) };
+    }
+}
+
+/// The node holding `key` in the search tree rooted at `root`.
+///
+/// # Safety
+///
+/// The nodes of the tree must be dereferentiable, and not mutably borrowed.
+unsafe fn find<K, V, Q, C>(root: Link<(K, V), SizeAug>, cmp: &C, key: &Q) -> Link<(K, V), SizeAug>
+where
+    K: Borrow<Q>,
+    Q: ?Sized,
+    C: Compare<Q>,
+{
+    let mut ptr = root?;
+    loop {
+        let node = unsafe { ptr.as_ref() };
+        ptr = match cmp.compare(key, node.get().0.borrow()) {
+            Ordering::Equal => return Some(ptr),
+            Ordering::Less => node.left?,
+            Ordering::Greater => node.right?,
+        };
+    }
+}
+
+/// The first node of the search tree rooted at `root` whose key is within `start`.
+///
+/// # Safety
+///
+/// See [`find`].
+unsafe fn first_from<K, V, Q, C>(
+    root: Link<(K, V), SizeAug>,
+    cmp: &C,
+    start: Bound<&Q>,
+) -> Link<(K, V), SizeAug>
+where
+    K: Borrow<Q>,
+    Q: ?Sized,
+    C: Compare<Q>,
+{
+    let (mut current, mut first) = (root, None);
+    while let Some(ptr) = current {
+        let node = unsafe { ptr.as_ref() };
+        let key = node.get().0.borrow();
+        let within = match start {
+            Bound::Included(start) => cmp.compare(key, start).is_ge(),
+            Bound::Excluded(start) => cmp.compare(key, start).is_gt(),
+            Bound::Unbounded => true,
+        };
+        if within {
+            first = Some(ptr);
+            current = node.left;
+        } else {
+            current = node.right;
+        }
+    }
+    first
+}
+
+/// The last node of the search tree rooted at `root` whose key is within `end`.
+///
+/// # Safety
+///
+/// See [`find`].
+unsafe fn last_until<K, V, Q, C>(
+    root: Link<(K, V), SizeAug>,
+    cmp: &C,
+    end: Bound<&Q>,
+) -> Link<(K, V), SizeAug>
+where
+    K: Borrow<Q>,
+    Q: ?Sized,
+    C: Compare<Q>,
+{
+    let (mut current, mut last) = (root, None);
+    while let Some(ptr) = current {
+        let node = unsafe { ptr.as_ref() };
+        let key = node.get().0.borrow();
+        let within = match end {
+            Bound::Included(end) => cmp.compare(key, end).is_le(),
+            Bound::Excluded(end) => cmp.compare(key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        if within {
+            last = Some(ptr);
+            current = node.right;
+        } else {
+            current = node.left;
+        }
+    }
+    last
+}
+
+/// The entry of `link`, borrowed for as long as the caller likes.
+///
+/// # Safety
+///
+/// See [`find`], for all of `'a`.
+unsafe fn key_value<'a, K, V>(link: Link<(K, V), SizeAug>) -> Option<(&'a K, &'a V)> {
+    let (key, value) = unsafe { link?.as_ref() }.get();
+    Some((key, value))
+}
+
+/// The number of nodes before `ptr` in in-order, in the whole tree holding it.
+///
+/// # Safety
+///
+/// See [`find`].
+unsafe fn rank<K, V>(ptr: NonNull<MapNode<K, V>>) -> usize {
+    let size = |link: Link<(K, V), SizeAug>| link.map_or(0, |ptr| unsafe { ptr.as_ref() }.size());
+    let mut rank = size(unsafe { ptr.as_ref() }.left);
+    let mut child = ptr;
+    while let Some(parent) = unsafe { child.as_ref() }.parent {
+        let parent_node = unsafe { parent.as_ref() };
+        if parent_node.right == Some(child) {
+            rank += size(parent_node.left) + 1;
+        }
+        child = parent;
+    }
+    rank
+}
+
+/// How deep nodes may be in a map of `len` entries: log base 1/`ALPHA` of `len`.
+pub(crate) fn depth_limit(len: usize) -> usize {
+    // Counted out rather than taken with `ln`, which needs `std`.
+    let mut limit = 0;
+    let mut size = 1.0 / ALPHA;
+    while size <= len as f64 {
+        size /= ALPHA;
+        limit += 1;
+    }
+    limit
+}
+
+impl<K, V, C: Default> Default for BstMap<K, V, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<K: Debug, V: Debug, C> Debug for BstMap<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, C: Compare<K>> Extend<(K, V)> for BstMap<K, V, C> {
+    /// Inserts every entry, like [`BstMap::insert`] does. An empty map is instead built in one
+    /// go, perfectly balanced, after sorting the entries.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        if !self.is_empty() {
+            for (key, value) in entries {
+                self.insert(key, value);
+            }
+            return;
+        }
+
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.sort_by(|a, b| self.cmp.compare(&a.0, &b.0));
+        // As with repeated insertion, the first key of a run wins, and the last value.
+        let mut nodes: Vec<NonNull<MapNode<K, V>>> = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            match nodes.last() {
+                Some(&last) if !self.less(&unsafe { last.as_ref() }.data.0, &key) => unsafe {
+                    (*last.as_ptr()).data.1 = value;
+                },
+                _ => nodes.push(alloc((key, value))),
+            }
+        }
+        // Safety: the nodes were just allocated, and are not linked yet.
+        self.root = unsafe { link_in_order(&nodes, |start, end| start + (end - start) / 2) };
+        self.max_len = nodes.len();
+    }
+}
+
+impl<K, V, C: Compare<K> + Default> FromIterator<(K, V)> for BstMap<K, V, C> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = Self::default();
+        map.extend(entries);
+        map
+    }
+}
+
+impl<K, V, C> Drop for BstMap<K, V, C> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a BstMap<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// The default balance factor of a [`WeightBalancedMap`].
+const WEIGHT_ALPHA: f64 = 0.25;
+
+/// An ordered map kept as a weight-balanced tree, BB\[α\]: each subtree holds at least a share α
+/// of the weight (size plus one) of its parent's subtree. Single and double rotations restore
+/// this after every update, for O(log n) operations in the worst case. The balance depends on
+/// sizes alone, which makes splitting a map at a key, and joining two maps back together, cheap.
+///
+/// Keys are ordered by `C` as in a [`BstMap`].
+pub struct WeightBalancedMap<K, V, C = Natural> {
+    root: Link<(K, V), SizeAug>,
+    alpha: f64,
+    rotations: usize,
+    cmp: C,
+    _marker: PhantomData<Box<MapNode<K, V>>>,
+}
+
+impl<K, V> WeightBalancedMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_alpha(WEIGHT_ALPHA)
+    }
+
+    /// Creates a map balancing with the factor `alpha`. Smaller factors rebalance less often, but
+    /// allow deeper trees.
+    ///
+    /// # Panics
+    ///
+    /// If `alpha` is not in `(2/11, 1 - 1/√2]`, the range for which rotations keep the tree
+    /// balanced.
+    pub fn with_alpha(alpha: f64) -> Self {
+        Self::with_alpha_and_comparator(alpha, Natural)
+    }
+}
+
+impl<K, V, C> WeightBalancedMap<K, V, C> {
+    /// An empty map ordering its keys by `cmp`, as [`BstMap::with_comparator`] makes one.
+    pub fn with_comparator(cmp: C) -> Self {
+        Self::with_alpha_and_comparator(WEIGHT_ALPHA, cmp)
+    }
+
+    /// An empty map balancing with the factor `alpha`, and ordering its keys by `cmp`.
+    ///
+    /// # Panics
+    ///
+    /// As [`WeightBalancedMap::with_alpha`] does.
+    pub fn with_alpha_and_comparator(alpha: f64, cmp: C) -> Self {
+        assert!(
+            2.0 / 11.0 < alpha && alpha <= 1.0 - core::f64::consts::FRAC_1_SQRT_2,
+            "balance factor {alpha} out of range"
+        );
+        Self {
+            root: None,
+            alpha,
+            rotations: 0,
+            cmp,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Counts the nodes, leaves and levels of the tree.
+    pub fn stats(&self) -> TreeStats {
+        self.root
+            .map(|root| TreeStats::of(unsafe { root.as_ref() }))
+            .unwrap_or_default()
+    }
+
+    /// How many rotations have been made to keep the tree balanced, a double rotation counting
+    /// as two. The maps split off by [`WeightBalancedMap::split`] start out with the count of the
+    /// map they came from, and a joined map keeps that of the left one.
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.map_or(0, |root| unsafe { root.as_ref() }.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        unsafe { Iter::new(self.root) }
+    }
+
+    /// Whether a subtree of weight `heavy` outweighs a sibling subtree of weight `light` by more
+    /// than α allows.
+    fn too_heavy(&self, heavy: usize, light: usize) -> bool {
+        (light as f64) < self.alpha * (heavy + light) as f64
+    }
+
+    /// Refreshes `ptr` and its ancestors, rotating wherever a subtree has become too heavy, and
+    /// returns the root of the tree.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have exclusive access to the tree holding `ptr`, and the subtrees below
+    /// `ptr` must be balanced.
+    unsafe fn rebalance_upwards(&mut self, ptr: NonNull<MapNode<K, V>>) -> NonNull<MapNode<K, V>> {
+        let mut ptr = ptr;
+        loop {
+            unsafe { Node::refresh(ptr) };
+            let top = unsafe { self.rebalance(ptr) };
+            match unsafe { top.as_ref() }.parent {
+                Some(parent) => ptr = parent,
+                None => return top,
+            }
+        }
+    }
+
+    /// Rotates at `ptr` if one of its subtrees is too heavy, and returns the root of the subtree
+    /// it was at.
+    ///
+    /// # Safety
+    ///
+    /// See [`WeightBalancedMap::rebalance_upwards`].
+    unsafe fn rebalance(&mut self, ptr: NonNull<MapNode<K, V>>) -> NonNull<MapNode<K, V>> {
+        let node = unsafe { ptr.as_ref() };
+        let (left, right) = (weight(node.left), weight(node.right));
+        // A child is rotated up on its own, unless that would leave its inner grandchild too
+        // heavy a share of the rotated down node, in which case the grandchild goes up.
+        let single = |outer: usize, inner: usize| {
+            (inner as f64) < (outer + inner) as f64 / (2.0 - self.alpha)
+        };
+        unsafe {
+            if self.too_heavy(right, left) {
+                let child = node.right.unwrap();
+                let child_node = child.as_ref();
+                if !single(weight(child_node.right), weight(child_node.left)) {
+                    Node::rotate_right_raw(child);
+                    self.rotations += 1;
+                }
+                self.rotations += 1;
+                Node::rotate_left_raw(ptr).unwrap()
+            } else if self.too_heavy(left, right) {
+                let child = node.left.unwrap();
+                let child_node = child.as_ref();
+                if !single(weight(child_node.left), weight(child_node.right)) {
+                    Node::rotate_left_raw(child);
+                    self.rotations += 1;
+                }
+                self.rotations += 1;
+                Node::rotate_right_raw(ptr).unwrap()
+            } else {
+                ptr
+            }
+        }
+    }
+
+    /// Links the balanced trees `left` and `right` below the detached node `ptr`, hanging it
+    /// down the inner spine of the heavier tree if need be, and returns the root of the result.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have exclusive access to all three, and `left` and `right` must be roots.
+    unsafe fn join_raw(
+        &mut self,
+        left: Link<(K, V), SizeAug>,
+        ptr: NonNull<MapNode<K, V>>,
+        right: Link<(K, V), SizeAug>,
+    ) -> NonNull<MapNode<K, V>> {
+        let (mut left, mut right) = (left, right);
+        let mut parent = None;
+        let left_heavy = self.too_heavy(weight(left), weight(right));
+        if left_heavy {
+            while self.too_heavy(weight(left), weight(right)) {
+                parent = left;
+                left = left.and_then(|node| unsafe { node.as_ref() }.right);
+            }
+        } else {
+            while self.too_heavy(weight(right), weight(left)) {
+                parent = right;
+                right = right.and_then(|node| unsafe { node.as_ref() }.left);
+            }
+        }
+
+        let node = unsafe { &mut *ptr.as_ptr() };
+        node.left = left;
+        node.right = right;
+        node.parent = parent;
+        for child in left.into_iter().chain(right) {
+            unsafe { (*child.as_ptr()).parent = Some(ptr) };
+        }
+        if let Some(parent) = parent {
+            let parent = unsafe { &mut *parent.as_ptr() };
+            if left_heavy {
+                parent.right = Some(ptr);
+            } else {
+                parent.left = Some(ptr);
+            }
+        }
+        unsafe { self.rebalance_upwards(ptr) }
+    }
+}
+
+impl<K, V, C: Compare<K>> WeightBalancedMap<K, V, C> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { find(self.root, &self.cmp, key) }.map(|ptr| &unsafe { ptr.as_ref() }.get().1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { find(self.root, &self.cmp, key) }
+            .map(|ptr| &mut unsafe { &mut *ptr.as_ptr() }.data.1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        unsafe { find(self.root, &self.cmp, key) }.is_some()
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaces, if any. The key already in
+    /// the map is kept in that case.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let Some(mut ptr) = self.root else {
+            self.root = Some(alloc((key, value)));
+            return None;
+        };
+
+        let new = loop {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            let slot = match self.cmp.compare(&key, &node.data.0) {
+                Ordering::Equal => return Some(mem::replace(&mut node.data.1, value)),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+            };
+            match *slot {
+                Some(child) => ptr = child,
+                None => {
+                    let new = alloc((key, value));
+                    *slot = Some(new);
+                    unsafe { (*new.as_ptr()).parent = Some(ptr) };
+                    break new;
+                }
+            }
+        };
+        self.root = Some(unsafe { self.rebalance_upwards(new) });
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        let ptr = unsafe { find(self.root, &self.cmp, key) }?;
+        // Safety: the node is part of this map, which we have exclusive access to.
+        let (node, parent) = unsafe { unlink(&mut self.root, ptr) };
+        if let Some(parent) = parent {
+            self.root = Some(unsafe { self.rebalance_upwards(parent) });
+        }
+        Some(node.data)
+    }
+
+    /// Splits the map into the entries with keys less than `key`, the entry with `key` if there
+    /// is one, and the entries with greater keys, in O(log n).
+    pub fn split<Q>(mut self, key: &Q) -> (Self, Option<(K, V)>, Self)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q> + Clone,
+    {
+        // Cut the path down to `key` loose from the rest of the tree, remembering which way it
+        // went at every node.
+        let mut path = Vec::new();
+        let mut current = self.root.take();
+        let mut found = None;
+        while let Some(ptr) = current {
+            // Safety: we own all nodes, and each is visited once.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            node.parent = None;
+            let went_left = match self.cmp.compare(key, node.data.0.borrow()) {
+                Ordering::Equal => {
+                    found = Some(ptr);
+                    break;
+                }
+                Ordering::Less => true,
+                Ordering::Greater => false,
+            };
+            current = if went_left {
+                node.left.take()
+            } else {
+                node.right.take()
+            };
+            path.push((ptr, went_left));
+        }
+
+        let detach = |link: Link<(K, V), SizeAug>| {
+            if let Some(ptr) = link {
+                unsafe { (*ptr.as_ptr()).parent = None };
+            }
+            link
+        };
+        let (mut less, mut greater) = match found {
+            Some(ptr) => {
+                let node = unsafe { &mut *ptr.as_ptr() };
+                (detach(node.left.take()), detach(node.right.take()))
+            }
+            None => (None, None),
+        };
+        // Going back up, every node of the path is joined, together with the subtree it kept,
+        // onto the side of `key` it belongs to.
+        for (ptr, went_left) in path.into_iter().rev() {
+            let node = unsafe { &mut *ptr.as_ptr() };
+            if went_left {
+                let right = detach(node.right.take());
+                greater = Some(unsafe { self.join_raw(greater, ptr, right) });
+            } else {
+                let left = detach(node.left.take());
+                less = Some(unsafe { self.join_raw(left, ptr, less) });
+            }
+        }
+
+        let entry = found.map(|ptr| unsafe { Box::from_raw(ptr.as_ptr()) }.data);
+        let part = |root| Self {
+            root,
+            alpha: self.alpha,
+            rotations: self.rotations,
+            cmp: self.cmp.clone(),
+            _marker: PhantomData,
+        };
+        (part(less), entry, part(greater))
+    }
+
+    /// Joins `left`, the entry `key`, `value`, and `right` into one map, in O(log n). The result
+    /// balances with the factor, and orders by the comparator, of `left`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the keys of `left` are not all less than `key`, or those of `right` not
+    /// all greater.
+    pub fn join(mut left: Self, key: K, value: V, mut right: Self) -> Self {
+        let less = |a: &K, b: &K| left.cmp.compare(a, b) == Ordering::Less;
+        debug_assert!(left
+            .iter()
+            .next_back()
+            .is_none_or(|(last, _)| less(last, &key)));
+        debug_assert!(right
+            .iter()
+            .next()
+            .is_none_or(|(first, _)| less(&key, first)));
+        let (low, high) = (left.root.take(), right.root.take());
+        left.root = Some(unsafe { left.join_raw(low, alloc((key, value)), high) });
+        left
+    }
+}
+
+/// The weight of a subtree: its size plus one.
+fn weight<K, V>(link: Link<(K, V), SizeAug>) -> usize {
+    link.map_or(0, |ptr| unsafe { ptr.as_ref() }.size()) + 1
+}
+
+impl<K, V, C: Default> Default for WeightBalancedMap<K, V, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<K: Debug, V: Debug, C> Debug for WeightBalancedMap<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, C> Drop for WeightBalancedMap<K, V, C> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a WeightBalancedMap<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    front: Link<(K, V), SizeAug>,
+    back: Link<(K, V), SizeAug>,
+    remaining: usize,
+    _marker: PhantomData<&'a MapNode<K, V>>,
+}
+
+impl<K, V> Iter<'_, K, V> {
+    /// # Safety
+    ///
+    /// The tree rooted at `root` must stay untouched for as long as the iterator lives.
+    unsafe fn new(root: Link<(K, V), SizeAug>) -> Self {
+        Iter {
+            front: root.map(|root| unsafe { leftmost(root) }),
+            back: root.map(|root| unsafe { rightmost(root) }),
+            remaining: root.map_or(0, |root| unsafe { root.as_ref() }.size()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Iter::new`].
+    unsafe fn range<Q, C>(
+        root: Link<(K, V), SizeAug>,
+        cmp: &C,
+        start: Bound<&Q>,
+        end: Bound<&Q>,
+    ) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        let (front, back) = unsafe { (first_from(root, cmp, start), last_until(root, cmp, end)) };
+        let remaining = match (front, back) {
+            (Some(front), Some(back)) => {
+                let (first, last) = unsafe { (rank(front), rank(back)) };
+                (last + 1).saturating_sub(first)
+            }
+            _ => 0,
+        };
+        Iter {
+            front,
+            back,
+            remaining,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ptr = self.front?;
+        self.remaining -= 1;
+        // Safety: the map is borrowed for `'a`, so its nodes stay put and unchanged.
+        self.front = unsafe { successor(ptr) };
+        let (key, value) = unsafe { ptr.as_ref() }.get();
+        Some((key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ptr = self.back?;
+        self.remaining -= 1;
+        self.back = unsafe { predecessor(ptr) };
+        let (key, value) = unsafe { ptr.as_ref() }.get();
+        Some((key, value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::model::random_updates;
+
+    fn height<K, V>(map: &BstMap<K, V>) -> usize {
+        let mut height = 0;
+        let mut stack: Vec<_> = map.root.map(|root| (root, 1)).into_iter().collect();
+        while let Some((ptr, depth)) = stack.pop() {
+            let node = unsafe { ptr.as_ref() };
+            height = height.max(depth);
+            stack.extend(node.left.map(|child| (child, depth + 1)));
+            stack.extend(node.right.map(|child| (child, depth + 1)));
+        }
+        height
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut map = BstMap::new();
+        let model = random_updates(&mut map, 99, 5000, 1000, |map| {
+            assert!(height(map) <= depth_limit(map.max_len) + 1);
+        });
+        assert!(map.iter().eq(model.iter()));
+        assert!(map.iter().rev().eq(model.iter().rev()));
+        for key in 0..1000 {
+            assert_eq!(map.get(&key), model.get(&key));
+        }
+        *map.get_mut(model.iter().next().unwrap().0).unwrap() = 0;
+        assert_eq!(map.iter().next().map(|(_, value)| *value), Some(0));
+    }
+
+    #[test]
+    fn neighbors() {
+        let map: BstMap<_, _> = (0..50).map(|key| (key * 10, key)).collect();
+        assert_eq!(map.floor(&35), Some((&30, &3)));
+        assert_eq!(map.floor(&30), Some((&30, &3)));
+        assert_eq!(map.lower(&30), Some((&20, &2)));
+        assert_eq!(map.ceiling(&35), Some((&40, &4)));
+        assert_eq!(map.ceiling(&40), Some((&40, &4)));
+        assert_eq!(map.upper(&40), Some((&50, &5)));
+        assert_eq!((map.floor(&-1), map.lower(&0)), (None, None));
+        assert_eq!((map.ceiling(&491), map.upper(&490)), (None, None));
+        assert_eq!(map.upper(&-5), map.first());
+    }
+
+    #[test]
+    fn entries() {
+        let mut map = BstMap::new();
+        let mut model = BTreeMap::new();
+        for word in "the cat and the hat and the bat".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+            *model.entry(word).or_insert(0) += 1;
+        }
+        assert!(map.iter().eq(model.iter()));
+        map.entry("cat").and_modify(|n| *n *= 10).or_default();
+        assert_eq!(map.get("cat"), Some(&10));
+        assert_eq!(*map.entry("dog").or_insert_with_key(|key| key.len()), 3);
+
+        match map.entry("the") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), ("the", 3)),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match map.entry("the") {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "the"),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(map.first_entry().unwrap().key(), &"and");
+        assert_eq!(map.last_entry().unwrap().remove(), 1);
+        assert_eq!(map.len(), 4);
+
+        // Filling entries in order still keeps the tree shallow.
+        let mut map = BstMap::new();
+        for key in 0..1000 {
+            map.entry(key).or_insert(key);
+        }
+        assert!(height(&map) <= depth_limit(1000) + 1);
+    }
+
+    #[test]
+    fn cursor() {
+        let mut map: BstMap<_, _> = (0..100).map(|key| (key * 10, key)).collect();
+        let mut cursor = map.lower_bound_mut(Bound::Excluded(&300));
+        assert_eq!(cursor.key(), Some(&310));
+        assert_eq!(cursor.peek_prev(), Some((&300, &30)));
+        cursor.insert_before(305, 0);
+        cursor.insert_after(315, 0);
+        assert_eq!(cursor.peek_next(), Some((&315, &0)));
+        *cursor.value_mut().unwrap() = -1;
+        // Thin out every other entry from here on, then add to both ends through the ghost.
+        while cursor.key().is_some() {
+            assert!(cursor.remove_current().is_some());
+            cursor.move_next();
+        }
+        cursor.insert_after(-10, 0);
+        cursor.insert_before(2000, 0);
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&2000));
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.key(), Some(&-10));
+
+        let expected = (-1..=30)
+            .map(|key| key * 10)
+            .chain([305, 315])
+            .chain((330..1000).step_by(20))
+            .chain([2000]);
+        assert!(map.iter().map(|(key, _)| *key).eq(expected));
+        assert!(height(&map) <= depth_limit(map.max_len) + 1);
+
+        let mut map: BstMap<i32, ()> = BstMap::new();
+        let mut cursor = map.lower_bound_mut(Bound::Unbounded);
+        for key in (0..1000).rev() {
+            cursor.insert_after(key, ());
+        }
+        assert!(map.iter().map(|(key, _)| *key).eq(0..1000));
+        assert!(height(&map) <= depth_limit(1000) + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of order")]
+    fn cursor_checks_order() {
+        let mut map: BstMap<_, _> = (0..10).map(|key| (key, ())).collect();
+        map.lower_bound_mut(Bound::Included(&5)).insert_after(3, ());
+    }
+
+    #[test]
+    fn retain_in_one_pass() {
+        let mut map: BstMap<_, _> = (0..1000).map(|key| (key, key)).collect();
+        let mut model: BTreeMap<_, _> = map.iter().map(|(&key, &value)| (key, value)).collect();
+        let mut seen = Vec::new();
+        map.retain(|&key, value| {
+            seen.push(key);
+            *value *= 2;
+            key % 3 != 0
+        });
+        model.retain(|&key, value| {
+            *value *= 2;
+            key % 3 != 0
+        });
+        assert!(seen.into_iter().eq(0..1000));
+        assert!(map.iter().eq(model.iter()));
+        assert!(height(&map) <= depth_limit(map.len()) + 1);
+        map.retain(|_, _| false);
+        assert!(map.is_empty() && map.first().is_none());
+        map.insert(1, 1);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn replacing_keys() {
+        let mut map: BstMap<_, _> = (0..500).map(|key| (key * 2, key)).collect();
+        let mut model: BTreeMap<_, _> = map.iter().map(|(&key, &value)| (key, value)).collect();
+        let mut random = crate::lcg(3);
+        for _ in 0..2000 {
+            let (old, new) = (random(1000), random(1000));
+            let replaced = map.replace_key(&old, new);
+            if model.contains_key(&old) && (old == new || !model.contains_key(&new)) {
+                assert_eq!(replaced, Ok(old));
+                let value = model.remove(&old).unwrap();
+                model.insert(new, value);
+            } else {
+                assert_eq!(replaced, Err(new));
+            }
+            assert!(height(&map) <= depth_limit(map.max_len) + 1);
+        }
+        assert!(map.iter().eq(model.iter()));
+
+        // A key that still fits between its neighbors is changed in place.
+        let mut map: BstMap<_, _> = [(0, 'a'), (2, 'b'), (4, 'c')].into_iter().collect();
+        let node = map.get(&2).unwrap() as *const char;
+        assert_eq!(map.replace_key(&2, 3), Ok(2));
+        assert_eq!(map.get(&3).unwrap() as *const char, node);
+        assert_eq!(map.replace_key(&3, 4), Err(4));
+    }
+
+    #[test]
+    fn custom_comparator() {
+        let by_case = |a: &str, b: &str| a.to_lowercase().cmp(&b.to_lowercase());
+        let mut map = BstMap::with_comparator(|a: &&str, b: &&str| by_case(a, b));
+        for (round, word) in "Apple banana APPLE Cherry apple BANANA date"
+            .split(' ')
+            .enumerate()
+        {
+            map.entry(word).or_insert(Vec::new()).push(round);
+        }
+        let keys: Vec<_> = map.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, ["Apple", "banana", "Cherry", "date"]);
+        assert_eq!(map.get(&"aPPLE"), Some(&vec![0, 2, 4]));
+        assert_eq!(map.ceiling(&"c").map(|(key, _)| *key), Some("Cherry"));
+        assert_eq!(map.range("B".."D").len(), 2);
+        assert_eq!(map.remove(&"CHERRY"), Some(vec![3]));
+
+        // Reversed, and bulk built through the same comparator.
+        let mut map = BstMap::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        map.extend((0..100).map(|key| (key, ())));
+        assert!(map.iter().map(|(key, _)| *key).eq((0..100).rev()));
+        assert_eq!(map.first().map(|(key, _)| *key), Some(99));
+        assert_eq!(map.floor(&50).map(|(key, _)| *key), Some(50));
+        assert_eq!(map.lower(&50).map(|(key, _)| *key), Some(51));
+
+        // Split and joined again in the order of the comparator.
+        let mut map = WeightBalancedMap::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        for key in 0..100 {
+            map.insert(key, ());
+        }
+        let (greater, entry, less) = map.split(&50);
+        assert_eq!(entry, Some((50, ())));
+        assert_eq!(greater.iter().next().map(|(key, _)| *key), Some(99));
+        assert_eq!(less.iter().next().map(|(key, _)| *key), Some(49));
+        let map = WeightBalancedMap::join(greater, 50, (), less);
+        assert!(map.iter().map(|(key, _)| *key).eq((0..100).rev()));
+        assert!(map.contains_key(&0) && !map.contains_key(&100));
+    }
+
+    #[test]
+    fn first_and_last() {
+        let mut map: BstMap<_, _> = (0..100).map(|key| (key, -key)).collect();
+        let mut model: BTreeMap<_, _> = map.iter().map(|(&key, &value)| (key, value)).collect();
+        assert_eq!(map.first(), model.first_key_value());
+        assert_eq!(map.last(), model.last_key_value());
+        while !model.is_empty() {
+            assert_eq!(map.pop_first(), model.pop_first());
+            assert_eq!(map.pop_last(), model.pop_last());
+            assert_eq!(map.len(), model.len());
+            assert!(height(&map) <= depth_limit(map.max_len) + 1);
+        }
+        assert!(map.first().is_none() && map.pop_last().is_none());
+    }
+
+    #[test]
+    fn extend_and_collect() {
+        let entries = [(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e')];
+        let map: BstMap<_, _> = entries.into_iter().collect();
+        let model: BTreeMap<_, _> = entries.into_iter().collect();
+        assert!(map.iter().eq(model.iter()));
+
+        let mut map: BstMap<_, _> = (0..1000).map(|key| (key, ())).collect();
+        assert_eq!(height(&map), 10);
+        map.extend((500..1500).rev().map(|key| (key, ())));
+        assert_eq!(map.len(), 1500);
+        assert!(map.iter().map(|(key, _)| *key).eq(0..1500));
+        assert!(height(&map) <= depth_limit(map.max_len) + 1);
+    }
+
+    #[test]
+    fn sorted_batches() {
+        let mut map: BstMap<_, _> = (0..100).map(|key| (key * 2, 0)).collect();
+        let mut model: BTreeMap<_, _> = map.iter().map(|(&key, &value)| (key, value)).collect();
+        // Runs above the maximum, broken up by keys that land inside the map.
+        let batch: Vec<_> = (150..5000)
+            .chain([7, 7, 9000, 3])
+            .chain(9001..12000)
+            .map(|key| (key, 1))
+            .collect();
+        map.insert_sorted_batch(batch.iter().copied());
+        model.extend(batch);
+        assert!(map.iter().eq(model.iter()));
+        assert!(height(&map) <= depth_limit(map.max_len) + 1);
+
+        let mut map = BstMap::new();
+        map.insert_sorted_batch((0..1000).map(|key| (key, ())));
+        assert_eq!(map.len(), 1000);
+        assert!(height(&map) <= depth_limit(1000) + 1);
+    }
+
+    #[test]
+    fn sorted_insertions_stay_shallow() {
+        let mut map = BstMap::new();
+        for key in 0..10_000 {
+            map.insert(key, ());
+        }
+        assert!(height(&map) <= depth_limit(10_000) + 1);
+        for key in 0..9_000 {
+            map.remove(&key);
+        }
+        assert_eq!(map.iter().len(), 1000);
+        assert!(height(&map) <= depth_limit(map.max_len) + 1);
+
+        let map: BstMap<String, i32> = BstMap::new();
+        assert!(map.get("missing").is_none());
+    }
+
+    fn check_weights<K, V>(map: &WeightBalancedMap<K, V>) {
+        let mut stack: Vec<_> = map.root.into_iter().collect();
+        while let Some(ptr) = stack.pop() {
+            let node = unsafe { ptr.as_ref() };
+            let (left, right) = (weight(node.left), weight(node.right));
+            assert_eq!(node.size() + 1, left + right);
+            assert!(!map.too_heavy(left, right) && !map.too_heavy(right, left));
+            for child in node.left.into_iter().chain(node.right) {
+                assert_eq!(unsafe { child.as_ref() }.parent, Some(ptr));
+                stack.push(child);
+            }
+        }
+    }
+
+    #[test]
+    fn weight_balanced_matches_btree_map() {
+        for alpha in [0.2, 0.25, 1.0 - std::f64::consts::FRAC_1_SQRT_2] {
+            let mut map = WeightBalancedMap::with_alpha(alpha);
+            let mut model = random_updates(&mut map, 7, 3000, 500, check_weights);
+            for key in 0..2000 {
+                let value = key as usize;
+                assert_eq!(map.insert(key, value), model.insert(key, value));
+                check_weights(&map);
+            }
+            assert!(map.iter().eq(model.iter()));
+        }
+    }
+
+    #[test]
+    fn split_and_join() {
+        let mut map = WeightBalancedMap::new();
+        for key in (0..1000).map(|key| key * 2) {
+            map.insert(key, -key);
+        }
+
+        let (less, entry, greater) = map.split(&600);
+        check_weights(&less);
+        check_weights(&greater);
+        assert_eq!(entry, Some((600, -600)));
+        assert!(less
+            .iter()
+            .map(|(key, _)| *key)
+            .eq((0..300).map(|key| key * 2)));
+        assert!(greater
+            .iter()
+            .map(|(key, _)| *key)
+            .eq((301..1000).map(|key| key * 2)));
+
+        let (small, none, rest) = greater.split(&1001);
+        assert_eq!((small.len(), none, rest.len()), (200, None, 499));
+        let greater = WeightBalancedMap::join(small, 1001, 0, rest);
+        check_weights(&greater);
+
+        let map = WeightBalancedMap::join(less, 600, 0, greater);
+        check_weights(&map);
+        assert_eq!(map.len(), 1001);
+        assert_eq!(map.get(&1001), Some(&0));
+
+        // Joining very differently sized maps hangs the small one down the spine of the other.
+        let map = WeightBalancedMap::join(map, 5000, 0, WeightBalancedMap::new());
+        check_weights(&map);
+        let (empty, _, map) = map.split(&-1);
+        assert!(empty.is_empty());
+        let map = WeightBalancedMap::join(WeightBalancedMap::with_alpha(0.2), -1, 0, map);
+        check_weights(&map);
+        assert_eq!(map.len(), 1003);
+    }
+}
@@ -0,0 +1,585 @@
+//! Trees whose nodes link to each other by 32-bit indices into one allocation instead of by
+//! address, halving the memory taken by links in trees of hundreds of millions of nodes.
+//!
+//! A tree keeps each payload next to its links by default ([`Interleaved`]), or all payloads in
+//! one array of their own ([`Split`]) so that scans over payloads alone read contiguous memory.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::num::NonZeroU32;
+use core::ops::{Deref, DerefMut};
+
+use crate::{Augment, Side, SizeAug, TreeError};
+
+/// The place of a node in its [`CompactTree`], four bytes even when optional.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Index(NonZeroU32);
+
+impl Index {
+    fn new(at: usize) -> Self {
+        u32::try_from(at + 1)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .map(Index)
+            .expect("a compact tree holds at most u32::MAX nodes")
+    }
+
+    fn at(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Everything a node keeps besides its payload.
+struct Links<S> {
+    summary: S,
+    left: Option<Index>,
+    right: Option<Index>,
+    parent: Option<Index>,
+}
+
+/// How a [`CompactTree`] lays out its nodes in memory.
+pub trait Layout: sealed::Sealed {
+    #[doc(hidden)]
+    type Store<T, R>: sealed::Store<T, R>;
+}
+
+/// Each payload stored next to the links of its node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Interleaved;
+
+/// All payloads stored together in one array, and all links in another.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Split;
+
+impl Layout for Interleaved {
+    type Store<T, R> = Vec<(T, R)>;
+}
+
+impl Layout for Split {
+    type Store<T, R> = (Vec<T>, Vec<R>);
+}
+
+mod sealed {
+    use alloc::vec::Vec;
+
+    pub trait Sealed {}
+
+    impl Sealed for super::Interleaved {}
+    impl Sealed for super::Split {}
+
+    pub trait Store<T, R> {
+        fn new() -> Self;
+        fn with_capacity(capacity: usize) -> Self;
+        fn len(&self) -> usize;
+        fn push(&mut self, data: T, record: R);
+        fn clear(&mut self);
+        fn swap(&mut self, a: usize, b: usize);
+        fn data(&self, at: usize) -> &T;
+        fn data_mut(&mut self, at: usize) -> &mut T;
+        fn record(&self, at: usize) -> &R;
+        fn record_mut(&mut self, at: usize) -> &mut R;
+    }
+
+    impl<T, R> Store<T, R> for Vec<(T, R)> {
+        fn new() -> Self {
+            Vec::new()
+        }
+
+        fn with_capacity(capacity: usize) -> Self {
+            Vec::with_capacity(capacity)
+        }
+
+        fn len(&self) -> usize {
+            self.len()
+        }
+
+        fn push(&mut self, data: T, record: R) {
+            self.push((data, record));
+        }
+
+        fn clear(&mut self) {
+            self.clear();
+        }
+
+        fn swap(&mut self, a: usize, b: usize) {
+            self.as_mut_slice().swap(a, b);
+        }
+
+        fn data(&self, at: usize) -> &T {
+            &self[at].0
+        }
+
+        fn data_mut(&mut self, at: usize) -> &mut T {
+            &mut self[at].0
+        }
+
+        fn record(&self, at: usize) -> &R {
+            &self[at].1
+        }
+
+        fn record_mut(&mut self, at: usize) -> &mut R {
+            &mut self[at].1
+        }
+    }
+
+    impl<T, R> Store<T, R> for (Vec<T>, Vec<R>) {
+        fn new() -> Self {
+            (Vec::new(), Vec::new())
+        }
+
+        fn with_capacity(capacity: usize) -> Self {
+            (Vec::with_capacity(capacity), Vec::with_capacity(capacity))
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn push(&mut self, data: T, record: R) {
+            self.0.push(data);
+            self.1.push(record);
+        }
+
+        fn clear(&mut self) {
+            self.0.clear();
+            self.1.clear();
+        }
+
+        fn swap(&mut self, a: usize, b: usize) {
+            self.0.swap(a, b);
+            self.1.swap(a, b);
+        }
+
+        fn data(&self, at: usize) -> &T {
+            &self.0[at]
+        }
+
+        fn data_mut(&mut self, at: usize) -> &mut T {
+            &mut self.0[at]
+        }
+
+        fn record(&self, at: usize) -> &R {
+            &self.1[at]
+        }
+
+        fn record_mut(&mut self, at: usize) -> &mut R {
+            &mut self.1[at]
+        }
+    }
+}
+
+use sealed::Store as _;
+
+type Store<T, A, L> = <L as Layout>::Store<T, Links<<A as Augment<T>>::Summary>>;
+
+/// Nodes kept side by side in one allocation and linked by [`Index`], with the same operations as
+/// [`Node`](crate::Node) but reached through the tree. Nodes are added unlinked, and stay until the
+/// tree is dropped, only moving when it is [compacted](CompactTree::compact).
+pub struct CompactTree<T, A: Augment<T> = (), L: Layout = Interleaved> {
+    store: Store<T, A, L>,
+    _layout: PhantomData<L>,
+}
+
+impl<T, A: Augment<T>, L: Layout> CompactTree<T, A, L> {
+    pub fn new() -> Self {
+        Self {
+            store: Store::<T, A, L>::new(),
+            _layout: PhantomData,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            store: Store::<T, A, L>::with_capacity(capacity),
+            _layout: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every node, keeping the allocation to add nodes to again. Indices handed out before
+    /// refer to the new nodes taking their places.
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    /// Adds an unlinked node holding `data`.
+    pub fn add(&mut self, data: T) -> Index {
+        let index = Index::new(self.len());
+        let links = Links {
+            summary: A::combine(None, &data, None),
+            left: None,
+            right: None,
+            parent: None,
+        };
+        self.store.push(data, links);
+        index
+    }
+
+    /// The node at `index`, which must come from this tree.
+    pub fn node(&self, index: Index) -> CompactNode<'_, T, A, L> {
+        assert!(index.at() < self.len(), "the index is out of bounds");
+        CompactNode { tree: self, index }
+    }
+
+    /// The payload of the node at `index`, whose summaries are brought up to date as with
+    /// [`Node::get_mut`](crate::Node::get_mut).
+    pub fn get_mut(&mut self, index: Index) -> CompactDataMut<'_, T, A, L> {
+        assert!(index.at() < self.len(), "the index is out of bounds");
+        CompactDataMut { tree: self, index }
+    }
+
+    /// Hangs `child` below `parent` on `side`, refusing as [`Node::try_attach`] does.
+    ///
+    /// [`Node::try_attach`]: crate::Node::try_attach
+    pub fn try_attach(&mut self, parent: Index, side: Side, child: Index) -> Result<(), TreeError> {
+        if self.node(parent).child(side).is_some() {
+            return Err(TreeError::SlotOccupied);
+        }
+        if self.node(child).parent().is_some() {
+            return Err(TreeError::DifferentTrees);
+        }
+        let mut ancestor = Some(self.node(parent));
+        while let Some(node) = ancestor {
+            if node.index == child {
+                return Err(TreeError::WouldCreateCycle);
+            }
+            ancestor = node.parent();
+        }
+        self.attach_unchecked(parent, side, child);
+        Ok(())
+    }
+
+    /// [`CompactTree::try_attach`] without looking for a cycle through the ancestors of
+    /// `parent`, for callers that know `child` to be the root of a tree of its own.
+    pub(crate) fn attach_unchecked(&mut self, parent: Index, side: Side, child: Index) {
+        debug_assert!(self.node(parent).child(side).is_none());
+        debug_assert!(self.node(child).parent().is_none());
+        *self.link_mut(parent, side) = Some(child);
+        self.store.record_mut(child.at()).parent = Some(parent);
+        self.refresh_upwards(parent);
+    }
+
+    /// Cuts off the child of `parent` on `side`, returning it.
+    pub fn detach(&mut self, parent: Index, side: Side) -> Option<Index> {
+        let child = self.link_mut(parent, side).take()?;
+        self.store.record_mut(child.at()).parent = None;
+        self.refresh_upwards(parent);
+        Some(child)
+    }
+
+    /// Moves the nodes so that each tree is laid out in pre-order, every subtree in one run,
+    /// trees taking their turn by the index of their root. Where nodes went is told by the
+    /// returned [`Remap`], as the indices held before now refer to other nodes.
+    pub fn compact(&mut self) -> Remap {
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack = Vec::new();
+        for at in 0..self.len() {
+            if self.store.record(at).parent.is_some() {
+                continue;
+            }
+            stack.push(Index::new(at));
+            while let Some(index) = stack.pop() {
+                order.push(index);
+                let links = self.store.record(index.at());
+                stack.extend(links.right);
+                stack.extend(links.left);
+            }
+        }
+        let mut new = order.clone();
+        for (at, old) in order.iter().enumerate() {
+            new[old.at()] = Index::new(at);
+        }
+
+        // Swapping each node straight to where it goes until the one that belongs in its place
+        // comes back follows every cycle of the permutation once.
+        let mut goes_to = new.clone();
+        for at in 0..goes_to.len() {
+            while goes_to[at].at() != at {
+                let to = goes_to[at].at();
+                self.store.swap(at, to);
+                goes_to.swap(at, to);
+            }
+        }
+        for at in 0..self.len() {
+            let links = self.store.record_mut(at);
+            for link in [&mut links.left, &mut links.right, &mut links.parent] {
+                *link = link.map(|old| new[old.at()]);
+            }
+        }
+        Remap { new }
+    }
+
+    fn link_mut(&mut self, index: Index, side: Side) -> &mut Option<Index> {
+        let links = self.store.record_mut(index.at());
+        match side {
+            Side::Left => &mut links.left,
+            Side::Right => &mut links.right,
+        }
+    }
+
+    fn refresh_upwards(&mut self, index: Index) {
+        // As for `Node`, a zero sized summary has nothing to recompute.
+        if mem::size_of::<A::Summary>() == 0 {
+            return;
+        }
+        let mut next = Some(index);
+        while let Some(index) = next {
+            let links = self.store.record(index.at());
+            let summary =
+                |child: Option<Index>| child.map(|child| &self.store.record(child.at()).summary);
+            let data = self.store.data(index.at());
+            let summary = A::combine(summary(links.left), data, summary(links.right));
+            let links = self.store.record_mut(index.at());
+            links.summary = summary;
+            next = links.parent;
+        }
+    }
+}
+
+impl<T> CompactTree<T, (), Split> {
+    /// The payloads of all nodes, in the order they were added.
+    pub fn payloads(&self) -> &[T] {
+        &self.store.0
+    }
+
+    /// The payloads of all nodes, which have no summaries to keep up to date.
+    pub fn payloads_mut(&mut self) -> &mut [T] {
+        &mut self.store.0
+    }
+}
+
+/// Where [`CompactTree::compact`] moved each node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remap {
+    new: Vec<Index>,
+}
+
+impl Remap {
+    /// The index of the node that was at `old` before compacting.
+    pub fn get(&self, old: Index) -> Index {
+        self.new[old.at()]
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> Default for CompactTree<T, A, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> fmt::Debug for CompactTree<T, A, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactTree")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A node of a [`CompactTree`], read through the tree it is in.
+pub struct CompactNode<'a, T, A: Augment<T> = (), L: Layout = Interleaved> {
+    tree: &'a CompactTree<T, A, L>,
+    index: Index,
+}
+
+impl<'a, T, A: Augment<T>, L: Layout> CompactNode<'a, T, A, L> {
+    fn links(&self) -> &'a Links<A::Summary> {
+        self.tree.store.record(self.index.at())
+    }
+
+    fn at(&self, index: Option<Index>) -> Option<Self> {
+        index.map(|index| Self {
+            tree: self.tree,
+            index,
+        })
+    }
+
+    pub fn index(&self) -> Index {
+        self.index
+    }
+
+    pub fn get(&self) -> &'a T {
+        self.tree.store.data(self.index.at())
+    }
+
+    pub fn summary(&self) -> &'a A::Summary {
+        &self.links().summary
+    }
+
+    pub fn left(&self) -> Option<Self> {
+        self.at(self.links().left)
+    }
+
+    pub fn right(&self) -> Option<Self> {
+        self.at(self.links().right)
+    }
+
+    pub fn child(&self, side: Side) -> Option<Self> {
+        match side {
+            Side::Left => self.left(),
+            Side::Right => self.right(),
+        }
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        self.at(self.links().parent)
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.links().left.is_none() && self.links().right.is_none()
+    }
+}
+
+impl<T, L: Layout> CompactNode<'_, T, SizeAug, L> {
+    pub fn size(&self) -> usize {
+        *self.summary()
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> Clone for CompactNode<'_, T, A, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> Copy for CompactNode<'_, T, A, L> {}
+
+/// Mutable access to the payload of a node of a [`CompactTree`], as [`DataMut`](crate::DataMut)
+/// gives it for a [`Node`](crate::Node).
+pub struct CompactDataMut<'a, T, A: Augment<T> = (), L: Layout = Interleaved> {
+    tree: &'a mut CompactTree<T, A, L>,
+    index: Index,
+}
+
+impl<T, A: Augment<T>, L: Layout> Deref for CompactDataMut<'_, T, A, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.tree.store.data(self.index.at())
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> DerefMut for CompactDataMut<'_, T, A, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.tree.store.data_mut(self.index.at())
+    }
+}
+
+impl<T, A: Augment<T>, L: Layout> Drop for CompactDataMut<'_, T, A, L> {
+    fn drop(&mut self) {
+        self.tree.refresh_upwards(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Node;
+
+    #[test]
+    fn links_by_index() {
+        assert_eq!(mem::size_of::<Option<Index>>(), 4);
+        assert!(mem::size_of::<(u8, Links<()>)>() * 2 <= mem::size_of::<Node<u8>>());
+        links_by_index_in::<Interleaved>();
+        links_by_index_in::<Split>();
+
+        let mut tree = CompactTree::<u32, (), Split>::new();
+        let (root, leaf) = (tree.add(1), tree.add(2));
+        tree.try_attach(root, Side::Right, leaf).unwrap();
+        tree.payloads_mut().iter_mut().for_each(|data| *data *= 3);
+        assert_eq!(tree.payloads(), [3, 6]);
+        assert_eq!(tree.node(root).right().map(|node| *node.get()), Some(6));
+    }
+
+    fn links_by_index_in<L: Layout>() {
+        let mut tree = CompactTree::<u32, SizeAug, L>::new();
+        let nodes: Vec<Index> = (0..5).map(|data| tree.add(data)).collect();
+        tree.try_attach(nodes[1], Side::Left, nodes[0]).unwrap();
+        tree.try_attach(nodes[1], Side::Right, nodes[3]).unwrap();
+        tree.try_attach(nodes[3], Side::Left, nodes[2]).unwrap();
+        tree.try_attach(nodes[3], Side::Right, nodes[4]).unwrap();
+        assert_eq!(
+            tree.try_attach(nodes[4], Side::Left, nodes[1]),
+            Err(TreeError::WouldCreateCycle)
+        );
+        assert_eq!(
+            tree.try_attach(nodes[4], Side::Left, nodes[2]),
+            Err(TreeError::DifferentTrees)
+        );
+        assert_eq!(
+            tree.try_attach(nodes[1], Side::Left, nodes[4]),
+            Err(TreeError::SlotOccupied)
+        );
+
+        let root = tree.node(nodes[1]);
+        assert_eq!(root.size(), 5);
+        let leaf = root.right().and_then(|node| node.left()).unwrap();
+        assert_eq!((*leaf.get(), leaf.is_leaf()), (2, true));
+        assert_eq!(
+            leaf.parent()
+                .and_then(|node| node.parent())
+                .map(|node| node.index()),
+            Some(nodes[1])
+        );
+
+        *tree.get_mut(nodes[2]) += 10;
+        assert_eq!(*tree.node(nodes[2]).get(), 12);
+        assert_eq!(tree.detach(nodes[1], Side::Right), Some(nodes[3]));
+        assert_eq!(
+            (tree.node(nodes[1]).size(), tree.node(nodes[3]).size()),
+            (2, 3)
+        );
+        assert!(tree.node(nodes[3]).parent().is_none());
+        assert_eq!(tree.detach(nodes[1], Side::Right), None);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.add(7), nodes[0]);
+        assert_eq!(tree.node(nodes[0]).size(), 1);
+    }
+
+    #[test]
+    fn compact_in_preorder() {
+        compact_in_preorder_in::<Interleaved>();
+        compact_in_preorder_in::<Split>();
+    }
+
+    fn compact_in_preorder_in<L: Layout>() {
+        // Two trees added in no particular order, the second rooted at a lower index.
+        let mut tree = CompactTree::<u32, SizeAug, L>::new();
+        let nodes: Vec<Index> = [4, 10, 1, 3, 20, 2, 0].map(|data| tree.add(data)).to_vec();
+        tree.try_attach(nodes[6], Side::Left, nodes[2]).unwrap();
+        tree.try_attach(nodes[6], Side::Right, nodes[0]).unwrap();
+        tree.try_attach(nodes[2], Side::Right, nodes[5]).unwrap();
+        tree.try_attach(nodes[5], Side::Right, nodes[3]).unwrap();
+        tree.try_attach(nodes[1], Side::Right, nodes[4]).unwrap();
+
+        let remap = tree.compact();
+        let payloads: Vec<u32> = (0..tree.len())
+            .map(|at| *tree.node(Index::new(at)).get())
+            .collect();
+        assert_eq!(payloads, [10, 20, 0, 1, 2, 3, 4]);
+        for (old, data) in nodes.iter().zip([4, 10, 1, 3, 20, 2, 0]) {
+            assert_eq!(*tree.node(remap.get(*old)).get(), data);
+        }
+        let root = tree.node(remap.get(nodes[6]));
+        assert_eq!((root.index(), root.size()), (Index::new(2), 5));
+        let three = root.left().and_then(|node| node.right()?.right()).unwrap();
+        assert_eq!(*three.get(), 3);
+        assert_eq!(three.parent().map(|node| *node.get()), Some(2));
+        assert_eq!(tree.node(Index::new(0)).size(), 2);
+
+        // Compacting again leaves everything in place.
+        let again = tree.compact();
+        let moved: Vec<Index> = nodes.iter().map(|&old| remap.get(old)).collect();
+        assert!(moved.iter().all(|&index| again.get(index) == index));
+    }
+}
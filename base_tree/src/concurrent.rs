@@ -0,0 +1,409 @@
+//! An experimental ordered map for many readers and few writers, which never block the readers.
+//!
+//! Readers walk the tree without taking any lock, so memory unlinked by a writer can only be
+//! freed once every reader that might still be looking at it is done. Readers announce themselves
+//! in one of two counters, picked by the parity of a global epoch; a writer that has unlinked
+//! something moves the epoch on, so new readers go to the other counter, and waits for the old
+//! one to drain before freeing what it unlinked.
+
+use std::borrow::Borrow;
+use std::boxed::Box;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{self, AtomicPtr, AtomicUsize};
+use std::sync::Mutex;
+use std::thread;
+use std::vec;
+use std::vec::Vec;
+
+use atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+
+// Keys and values are allocated apart from the nodes, so that a node can be replaced by a copy
+// sharing them while readers may still be looking at the original.
+struct Node<K, V> {
+    key: *mut K,
+    value: AtomicPtr<V>,
+    left: AtomicPtr<Node<K, V>>,
+    right: AtomicPtr<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn alloc(key: *mut K, value: *mut V, left: *mut Self, right: *mut Self) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            key,
+            value: AtomicPtr::new(value),
+            left: AtomicPtr::new(left),
+            right: AtomicPtr::new(right),
+        }))
+    }
+}
+
+/// An unbalanced binary search tree that any number of threads can read while one at a time
+/// writes. A writer that removes or replaces something waits until the readers that started
+/// before it are done, so a thread must not write while it holds a [`Guard`] of the same map.
+pub struct ConcurrentBst<K, V> {
+    root: AtomicPtr<Node<K, V>>,
+    len: AtomicUsize,
+    writer: Mutex<()>,
+    epoch: AtomicUsize,
+    // The readers that entered while the epoch was even, and odd.
+    readers: [AtomicUsize; 2],
+    _marker: PhantomData<(Box<K>, Box<V>)>,
+}
+
+// Safety: readers on other threads get shared references to keys and values, and writers move
+// them in and out.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for ConcurrentBst<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for ConcurrentBst<K, V> {}
+
+/// What a writer unlinked, to be freed once no reader can reach it.
+enum Garbage<K, V> {
+    Node(*mut Node<K, V>),
+    Key(*mut K),
+    Value(*mut V),
+}
+
+impl<K, V> ConcurrentBst<K, V> {
+    pub fn new() -> Self {
+        Self {
+            root: AtomicPtr::new(ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of entries, which may be changing while it is read.
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Starts reading. Nothing the map hands out through the guard is freed before it is dropped.
+    pub fn pin(&self) -> Guard<'_, K, V> {
+        loop {
+            let epoch = self.epoch.load(SeqCst);
+            let readers = &self.readers[epoch % 2];
+            readers.fetch_add(1, SeqCst);
+            // A writer moving the epoch on in between may not have seen us, so go again.
+            if self.epoch.load(SeqCst) == epoch {
+                return Guard { map: self, readers };
+            }
+            readers.fetch_sub(1, SeqCst);
+        }
+    }
+
+    /// Waits until no reader can still reach `garbage`, and frees it.
+    fn reclaim(&self, garbage: Vec<Garbage<K, V>>) -> Option<V> {
+        if garbage.is_empty() {
+            return None;
+        }
+        let epoch = self.epoch.fetch_add(1, SeqCst);
+        while self.readers[epoch % 2].load(SeqCst) != 0 {
+            thread::yield_now();
+        }
+        let mut value = None;
+        // Safety: everything was unlinked before the epoch moved on, and the readers that might
+        // have seen it have left.
+        for garbage in garbage {
+            match garbage {
+                Garbage::Node(node) => drop(unsafe { Box::from_raw(node) }),
+                Garbage::Key(key) => drop(unsafe { Box::from_raw(key) }),
+                Garbage::Value(old) => value = Some(*unsafe { Box::from_raw(old) }),
+            }
+        }
+        value
+    }
+}
+
+impl<K: Ord, V> ConcurrentBst<K, V> {
+    /// Inserts `value` under `key`, returning the value it replaces once no reader can see that
+    /// any more. The key already in the map is kept in that case.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let _writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let value = Box::into_raw(Box::new(value));
+        let mut link = &self.root;
+        loop {
+            let node = link.load(Acquire);
+            if node.is_null() {
+                let key = Box::into_raw(Box::new(key));
+                let node = Node::alloc(key, value, ptr::null_mut(), ptr::null_mut());
+                link.store(node, Release);
+                self.len.fetch_add(1, Relaxed);
+                return None;
+            }
+            // Safety: only writers free nodes, and we are the only writer.
+            let node = unsafe { &*node };
+            link = match key.cmp(unsafe { &*node.key }) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => {
+                    let old = node.value.swap(value, Release);
+                    return self.reclaim(vec![Garbage::Value(old)]);
+                }
+            };
+        }
+    }
+
+    /// Removes `key`, returning its value once no reader can see it any more.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let _writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut link = &self.root;
+        let node = loop {
+            let node = link.load(Acquire);
+            if node.is_null() {
+                return None;
+            }
+            // Safety: only writers free nodes, and we are the only writer.
+            let node = unsafe { &*node };
+            link = match key.cmp(unsafe { &*node.key }.borrow()) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => break node,
+            };
+        };
+        let (left, right) = (node.left.load(Acquire), node.right.load(Acquire));
+        let mut garbage = vec![
+            Garbage::Node(ptr::from_ref(node).cast_mut()),
+            Garbage::Key(node.key),
+            Garbage::Value(node.value.load(Acquire)),
+        ];
+        if left.is_null() || right.is_null() {
+            link.store(if left.is_null() { right } else { left }, Release);
+        } else {
+            // A copy of the successor takes the place of the node, above copies of the nodes on
+            // the way down to it, all published by one store. A reader already past the node
+            // goes on through the old nodes, which still hold the successor, so the other keys
+            // can be found throughout.
+            let (mut path, mut successor) = (Vec::new(), right);
+            loop {
+                // Safety: as above.
+                let next = unsafe { &*successor }.left.load(Acquire);
+                if next.is_null() {
+                    break;
+                }
+                path.push(successor);
+                successor = next;
+            }
+            let successor_node = unsafe { &*successor };
+            let mut below = successor_node.right.load(Acquire);
+            for &old in path.iter().rev() {
+                let old = unsafe { &*old };
+                below = Node::alloc(
+                    old.key,
+                    old.value.load(Acquire),
+                    below,
+                    old.right.load(Acquire),
+                );
+            }
+            let copy = Node::alloc(
+                successor_node.key,
+                successor_node.value.load(Acquire),
+                left,
+                below,
+            );
+            link.store(copy, Release);
+            garbage.extend(path.into_iter().map(Garbage::Node));
+            garbage.push(Garbage::Node(successor));
+        }
+        self.len.fetch_sub(1, Relaxed);
+        self.reclaim(garbage)
+    }
+}
+
+impl<K, V> Default for ConcurrentBst<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for ConcurrentBst<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.pin().iter()).finish()
+    }
+}
+
+impl<K, V> Drop for ConcurrentBst<K, V> {
+    fn drop(&mut self) {
+        let mut stack = vec![*self.root.get_mut()];
+        while let Some(node) = stack.pop() {
+            if node.is_null() {
+                continue;
+            }
+            // Safety: nobody else can reach the map any more.
+            let mut node = unsafe { Box::from_raw(node) };
+            drop(unsafe { Box::from_raw(node.key) });
+            drop(unsafe { Box::from_raw(*node.value.get_mut()) });
+            stack.extend([*node.left.get_mut(), *node.right.get_mut()]);
+        }
+    }
+}
+
+/// Read access to a [`ConcurrentBst`], returned by [`ConcurrentBst::pin`]. What it hands out
+/// stays valid until it is dropped, though writers may have removed it from the map by then.
+pub struct Guard<'a, K, V> {
+    map: &'a ConcurrentBst<K, V>,
+    readers: &'a AtomicUsize,
+}
+
+impl<'a, K, V> Guard<'a, K, V> {
+    /// Iterates over the entries in key order. Entries inserted or removed meanwhile may or may
+    /// not be seen.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let mut stack = Vec::new();
+        let mut current = self.map.root.load(Acquire);
+        std::iter::from_fn(move || {
+            while !current.is_null() {
+                stack.push(current);
+                // Safety: the node can't be freed while we are pinned.
+                current = unsafe { &*current }.left.load(Acquire);
+            }
+            let node = unsafe { &*stack.pop()? };
+            current = node.right.load(Acquire);
+            Some(unsafe { (&*node.key, &*node.value.load(Acquire)) })
+        })
+    }
+}
+
+impl<'a, K: Ord, V> Guard<'a, K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.map.root.load(Acquire);
+        while !node.is_null() {
+            // Safety: the node can't be freed while we are pinned.
+            let current = unsafe { &*node };
+            node = match key.cmp(unsafe { &*current.key }.borrow()) {
+                Ordering::Less => current.left.load(Acquire),
+                Ordering::Greater => current.right.load(Acquire),
+                Ordering::Equal => return Some(unsafe { &*current.value.load(Acquire) }),
+            };
+        }
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+}
+
+impl<K, V> Drop for Guard<'_, K, V> {
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    #[test]
+    fn readers_alongside_a_writer() {
+        let map = Arc::new(ConcurrentBst::new());
+        let done = Arc::new(AtomicBool::new(false));
+        for key in (0..200).step_by(2) {
+            map.insert(key, key * 10);
+        }
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let (map, done) = (Arc::clone(&map), Arc::clone(&done));
+                thread::spawn(move || {
+                    let mut reads = 0;
+                    while !done.load(Relaxed) || reads == 0 {
+                        let guard = map.pin();
+                        // Even keys are never removed, and their values only ever replaced by
+                        // other multiples of ten.
+                        for key in (0..200).step_by(2) {
+                            assert_eq!(guard.get(&key).map(|value| value % 10), Some(0));
+                        }
+                        assert!(guard.iter().map(|(key, _)| key).is_sorted());
+                        reads += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let mut random = crate::lcg(9);
+        let mut odd = std::collections::BTreeSet::new();
+        for round in 0..2000 {
+            let key = random(200);
+            if key.is_multiple_of(2) {
+                assert_eq!(map.insert(key, round * 10).map(|value| value % 10), Some(0));
+            } else if round % 2 == 0 {
+                assert_eq!(map.insert(key, 1).is_none(), odd.insert(key));
+            } else {
+                assert_eq!(map.remove(&key).is_some(), odd.remove(&key));
+            }
+        }
+        done.store(true, Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(map.len(), 100 + odd.len());
+        let guard = map.pin();
+        let keys: Vec<u64> = guard.iter().map(|(&key, _)| key).collect();
+        let mut expected: Vec<u64> = (0..200).step_by(2).chain(odd).collect();
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+    #[test]
+    fn reader_keeps_a_deep_successor() {
+        for _ in 0..50 {
+            // The successor of 50 is 51, at the bottom of a long left spine under 999.
+            let map = Arc::new(ConcurrentBst::new());
+            for key in [50, 25].into_iter().chain((51..1000).rev()) {
+                map.insert(key, key);
+            }
+            let (started, done) = (
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(false)),
+            );
+            let reader = {
+                let (map, started, done) =
+                    (Arc::clone(&map), Arc::clone(&started), Arc::clone(&done));
+                thread::spawn(move || {
+                    while !done.load(Relaxed) {
+                        assert_eq!(map.pin().get(&51), Some(&51));
+                        started.store(true, Relaxed);
+                    }
+                })
+            };
+            while !started.load(Relaxed) {
+                thread::yield_now();
+            }
+            assert_eq!(map.remove(&50), Some(50));
+            done.store(true, Relaxed);
+            reader.join().unwrap();
+            let guard = map.pin();
+            assert!(guard
+                .iter()
+                .map(|(key, _)| key)
+                .eq([25].iter().chain(&(51..1000).collect::<Vec<_>>())));
+        }
+    }
+}
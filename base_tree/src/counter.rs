@@ -0,0 +1,326 @@
+//! A multiset counting how often each key occurs, able to count the occurrences in any range of
+//! keys in O(log n).
+
+use alloc::boxed::Box;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+use crate::bst::{depth_limit, ALPHA};
+use crate::tree::{alloc, free_subtree, leftmost, successor, unlink, Link};
+use crate::{Augment, Node};
+
+/// Keeps the number of nodes in every subtree, for balancing, and the sum of their counts.
+struct CountAug;
+
+impl<K> Augment<(K, usize)> for CountAug {
+    type Summary = (usize, usize);
+
+    fn combine(
+        left: Option<&(usize, usize)>,
+        (_, count): &(K, usize),
+        right: Option<&(usize, usize)>,
+    ) -> (usize, usize) {
+        let (left, right) = (
+            left.copied().unwrap_or_default(),
+            right.copied().unwrap_or_default(),
+        );
+        (1 + left.0 + right.0, count + left.1 + right.1)
+    }
+}
+
+type CounterNode<K> = Node<(K, usize), CountAug>;
+
+/// Counts occurrences of keys, with a node for every distinct key balanced as a scapegoat tree,
+/// like [`BstMap`](crate::BstMap). Each subtree knows the total of its counts, so the
+/// occurrences below or within any bounds are counted along a single path.
+pub struct BstCounter<K> {
+    root: Link<(K, usize), CountAug>,
+    // The most distinct keys there have been since the tree was last rebuilt completely.
+    max_len: usize,
+    _marker: PhantomData<Box<CounterNode<K>>>,
+}
+
+impl<K> BstCounter<K> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            max_len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of occurrences of all keys together.
+    pub fn len(&self) -> usize {
+        self.root
+            .map_or(0, |root| unsafe { root.as_ref() }.summary().1)
+    }
+
+    /// The number of distinct keys.
+    pub fn distinct(&self) -> usize {
+        self.root
+            .map_or(0, |root| unsafe { root.as_ref() }.summary().0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Iterates over the keys in order, with the number of times each occurs.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize)> {
+        let mut next = self.root.map(|root| unsafe { leftmost(root) });
+        core::iter::from_fn(move || {
+            let ptr = next?;
+            // Safety: the counter is borrowed, so its nodes stay put and unchanged.
+            next = unsafe { successor(ptr) };
+            let (key, count) = unsafe { ptr.as_ref() }.get();
+            Some((key, *count))
+        })
+    }
+
+    fn rebuild(&mut self) {
+        if let Some(root) = self.root {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            self.root = Some(unsafe { (*root.as_ptr()).balance() }.into());
+        }
+        self.max_len = self.distinct();
+    }
+}
+
+impl<K: Ord> BstCounter<K> {
+    /// Counts one more occurrence of `key`, returning how often it occurs now.
+    pub fn add(&mut self, key: K) -> usize {
+        let Some(mut ptr) = self.root else {
+            self.root = Some(alloc((key, 1)));
+            self.max_len = self.max_len.max(1);
+            return 1;
+        };
+
+        let mut depth = 1;
+        let new = loop {
+            // Safety: we have exclusive access to all nodes through `&mut self`.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            let slot = match key.cmp(&node.data.0) {
+                Ordering::Equal => {
+                    node.data.1 += 1;
+                    let count = node.data.1;
+                    unsafe { Node::refresh_upwards(ptr) };
+                    return count;
+                }
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+            };
+            match *slot {
+                Some(child) => {
+                    ptr = child;
+                    depth += 1;
+                }
+                None => {
+                    let new = alloc((key, 1));
+                    *slot = Some(new);
+                    unsafe { (*new.as_ptr()).parent = Some(ptr) };
+                    break new;
+                }
+            }
+        };
+        unsafe { Node::refresh_upwards(new) };
+
+        let distinct = self.distinct();
+        self.max_len = self.max_len.max(distinct);
+        if depth > depth_limit(distinct) {
+            // The lowest ancestor with a child holding more than `ALPHA` of its nodes.
+            let mut child = unsafe { new.as_ref() };
+            while let Some(parent) = child.parent {
+                let parent = unsafe { &mut *parent.as_ptr() };
+                if child.summary.0 as f64 > ALPHA * parent.summary.0 as f64 {
+                    let rebuilt = parent.balance();
+                    if rebuilt.parent.is_none() {
+                        self.root = Some(rebuilt.into());
+                    }
+                    break;
+                }
+                child = parent;
+            }
+        }
+        1
+    }
+
+    /// Takes away one occurrence of `key`, returning whether there was one.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(ptr) = self.find(key) else {
+            return false;
+        };
+        // Safety: the node is part of this counter, which we have exclusive access to.
+        let node = unsafe { &mut *ptr.as_ptr() };
+        if node.data.1 > 1 {
+            node.data.1 -= 1;
+            unsafe { Node::refresh_upwards(ptr) };
+            return true;
+        }
+        let (_, parent) = unsafe { unlink(&mut self.root, ptr) };
+        if let Some(parent) = parent {
+            unsafe { Node::refresh_upwards(parent) };
+        }
+        if (self.distinct() as f64) < ALPHA * self.max_len as f64 {
+            self.rebuild();
+        }
+        true
+    }
+
+    /// How often `key` occurs.
+    pub fn count<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map_or(0, |ptr| unsafe { ptr.as_ref() }.get().1)
+    }
+
+    /// The number of occurrences of keys in `range`, in O(log n).
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let before = match range.start_bound() {
+            Bound::Included(start) => self.count_until(Bound::Excluded(start)),
+            Bound::Excluded(start) => self.count_until(Bound::Included(start)),
+            Bound::Unbounded => 0,
+        };
+        self.count_until(range.end_bound()).saturating_sub(before)
+    }
+
+    /// The number of occurrences of keys within `end`.
+    fn count_until<Q>(&self, end: Bound<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let total = |link: Link<(K, usize), CountAug>| {
+            link.map_or(0, |ptr| unsafe { ptr.as_ref() }.summary().1)
+        };
+        let (mut current, mut count) = (self.root, 0);
+        while let Some(ptr) = current {
+            // Safety: the counter is borrowed, so its nodes stay put and unchanged.
+            let node = unsafe { ptr.as_ref() };
+            let key = node.data.0.borrow();
+            let within = match end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if within {
+                count += total(node.left) + node.data.1;
+                current = node.right;
+            } else {
+                current = node.left;
+            }
+        }
+        count
+    }
+
+    fn find<Q>(&self, key: &Q) -> Link<(K, usize), CountAug>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut ptr = self.root?;
+        loop {
+            // Safety: the counter is borrowed, so its nodes stay put and unchanged.
+            let node = unsafe { ptr.as_ref() };
+            ptr = match key.cmp(node.data.0.borrow()) {
+                Ordering::Equal => return Some(ptr),
+                Ordering::Less => node.left?,
+                Ordering::Greater => node.right?,
+            };
+        }
+    }
+}
+
+impl<K> Default for BstCounter<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug> Debug for BstCounter<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord> Extend<K> for BstCounter<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, keys: I) {
+        for key in keys {
+            self.add(key);
+        }
+    }
+}
+
+impl<K: Ord> FromIterator<K> for BstCounter<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(keys: I) -> Self {
+        let mut counter = Self::new();
+        counter.extend(keys);
+        counter
+    }
+}
+
+impl<K> Drop for BstCounter<K> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn counts_in_ranges() {
+        let mut counter = BstCounter::new();
+        let mut model: BTreeMap<u64, usize> = BTreeMap::new();
+        let mut random = crate::lcg(31);
+        for round in 0..5000 {
+            let key = random(300);
+            if round % 3 == 2 {
+                let present = model.contains_key(&key);
+                assert_eq!(counter.remove(&key), present);
+                if present {
+                    *model.get_mut(&key).unwrap() -= 1;
+                    model.retain(|_, count| *count > 0);
+                }
+            } else {
+                let count = model.entry(key).or_default();
+                *count += 1;
+                assert_eq!(counter.add(key), *count);
+            }
+        }
+        assert_eq!(counter.len(), model.values().sum::<usize>());
+        assert_eq!(counter.distinct(), model.len());
+        assert!(counter
+            .iter()
+            .eq(model.iter().map(|(key, &count)| (key, count))));
+        for key in 0..300 {
+            assert_eq!(counter.count(&key), model.get(&key).copied().unwrap_or(0));
+        }
+        let sum = |range: core::ops::Range<u64>| model.range(range).map(|(_, c)| c).sum::<usize>();
+        assert_eq!(counter.count_range(50..120), sum(50..120));
+        assert_eq!(counter.count_range(..=77), sum(0..78));
+        assert_eq!(
+            counter.count_range((Bound::Excluded(10), Bound::Unbounded)),
+            sum(11..300)
+        );
+        assert_eq!(counter.count_range(..), counter.len());
+    }
+}
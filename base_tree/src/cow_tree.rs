@@ -0,0 +1,243 @@
+//! Copy-on-write trees, sharing unchanged subtrees between snapshots.
+
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Order, Side, Tree};
+
+type Link<T> = Option<Rc<CowNode<T>>>;
+
+/// A tree whose subtrees are reference counted, so that cloning it takes a snapshot in O(1).
+/// Nodes are only copied when changed through one of the `_mut` methods while shared, and then
+/// only along the path down to them, as with [`Rc::make_mut`]. There are no parent pointers, as a
+/// shared subtree has many parents.
+pub struct CowTree<T> {
+    root: Link<T>,
+}
+
+pub struct CowNode<T> {
+    data: T,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> CowTree<T> {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    pub fn with_root(data: T) -> Self {
+        Self::join(Self::new(), data, Self::new())
+    }
+
+    /// Creates a tree with a root holding `data`, sharing `left` and `right` as its subtrees.
+    pub fn join(left: Self, data: T, right: Self) -> Self {
+        Self {
+            root: Some(Rc::new(CowNode {
+                data,
+                left: left.root,
+                right: right.root,
+            })),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn root(&self) -> Option<&CowNode<T>> {
+        self.root.as_deref()
+    }
+
+    /// Whether both trees are the very same, shared, nodes.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl<T: Clone> CowTree<T> {
+    /// The root, copied first if it is shared with another tree.
+    pub fn root_mut(&mut self) -> Option<&mut CowNode<T>> {
+        self.root.as_mut().map(Rc::make_mut)
+    }
+
+    /// The node at `path`, copying every shared node on the way down to it.
+    pub fn at_path_mut(&mut self, path: &[Side]) -> Option<&mut CowNode<T>> {
+        let mut node = self.root_mut()?;
+        for &side in path {
+            node = node.child_mut(side)?;
+        }
+        Some(node)
+    }
+}
+
+impl<T> CowNode<T> {
+    pub fn get(&self) -> &T {
+        &self.data
+    }
+
+    pub fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    pub fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    /// Replaces the subtree on `side` with `subtree`, returning the old one.
+    pub fn replace_child(&mut self, side: Side, subtree: CowTree<T>) -> CowTree<T> {
+        let link = match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        };
+        CowTree {
+            root: core::mem::replace(link, subtree.root),
+        }
+    }
+
+    /// Shares the subtree on `side` as a tree of its own.
+    pub fn child_tree(&self, side: Side) -> CowTree<T> {
+        let link = match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        };
+        CowTree { root: link.clone() }
+    }
+}
+
+impl<T: Clone> CowNode<T> {
+    /// Mutable nodes are only handed out once no other tree shares them, so this never copies.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    pub fn left_mut(&mut self) -> Option<&mut Self> {
+        self.child_mut(Side::Left)
+    }
+
+    pub fn right_mut(&mut self) -> Option<&mut Self> {
+        self.child_mut(Side::Right)
+    }
+
+    fn child_mut(&mut self, side: Side) -> Option<&mut Self> {
+        let link = match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        };
+        link.as_mut().map(Rc::make_mut)
+    }
+}
+
+/// Copies `data`, sharing the children with the original.
+impl<T: Clone> Clone for CowNode<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+/// Takes a snapshot, sharing every node.
+impl<T> Clone for CowTree<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T: Clone> From<&Tree<T>> for CowTree<T> {
+    fn from(tree: &Tree<T>) -> Self {
+        // Children are built before their parents, left before right.
+        let mut built = Vec::new();
+        for node in tree
+            .root()
+            .into_iter()
+            .flat_map(|root| root.iter(Order::Post))
+        {
+            let right = node
+                .right()
+                .map_or_else(Self::new, |_| built.pop().unwrap());
+            let left = node.left().map_or_else(Self::new, |_| built.pop().unwrap());
+            built.push(Self::join(left, node.get().clone(), right));
+        }
+        built.pop().unwrap_or_default()
+    }
+}
+
+impl<T> Default for CowTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for CowNode<T> {
+    fn drop(&mut self) {
+        // Dropping the children recursively could overflow the stack on a deep tree, so the
+        // nodes about to be freed are unlinked first.
+        let mut stack = vec![self.left.take(), self.right.take()];
+        while let Some(link) = stack.pop() {
+            if let Some(mut node) = link.and_then(|rc| Rc::try_unwrap(rc).ok()) {
+                stack.push(node.left.take());
+                stack.push(node.right.take());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Shape, Side::*};
+
+    fn in_order<T: Copy>(tree: &CowTree<T>) -> Vec<T> {
+        let mut out = Vec::new();
+        let mut stack = Vec::new();
+        let mut current = tree.root();
+        while current.is_some() || !stack.is_empty() {
+            while let Some(node) = current {
+                stack.push(node);
+                current = node.left();
+            }
+            let node = stack.pop().unwrap();
+            out.push(*node.get());
+            current = node.right();
+        }
+        out
+    }
+
+    #[test]
+    fn snapshots_share_until_written() {
+        let mut tree = CowTree::from(&Tree::from_shape(0..7, Shape::Balanced, |_| 0));
+        let snapshot = tree.clone();
+        assert!(tree.ptr_eq(&snapshot));
+
+        *tree.at_path_mut(&[Left, Right]).unwrap().get_mut() = 20;
+        assert_eq!(in_order(&tree), [0, 1, 20, 3, 4, 5, 6]);
+        assert_eq!(in_order(&snapshot), [0, 1, 2, 3, 4, 5, 6]);
+        // Only the path down to the change was copied.
+        let (root, old) = (tree.root().unwrap(), snapshot.root().unwrap());
+        assert!(root.child_tree(Right).ptr_eq(&old.child_tree(Right)));
+        let (left, old_left) = (root.left().unwrap(), old.left().unwrap());
+        assert!(left.child_tree(Left).ptr_eq(&old_left.child_tree(Left)));
+        assert!(!left.child_tree(Right).ptr_eq(&old_left.child_tree(Right)));
+
+        let right = tree
+            .root_mut()
+            .unwrap()
+            .replace_child(Right, CowTree::with_root(9));
+        assert!(right.ptr_eq(&snapshot.root().unwrap().child_tree(Right)));
+        assert_eq!(in_order(&tree), [0, 1, 20, 3, 9]);
+        assert!(tree.at_path_mut(&[Right, Right]).is_none());
+
+        // Deep enough to overflow the stack if dropped recursively.
+        let chain = Tree::from_shape(0..1_000_000, Shape::LeftSkewed, |_| unreachable!());
+        drop(CowTree::from(&chain));
+    }
+}
@@ -0,0 +1,86 @@
+//! Hash-consing, sharing every structurally identical subtree of a tree.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::cow_tree::CowTree;
+use crate::{Order, Tree};
+
+pub struct Dedup<T> {
+    /// The same tree, with a single shared copy of each distinct subtree.
+    pub tree: CowTree<T>,
+    /// How many nodes were merged into an identical subtree found earlier.
+    pub eliminated: usize,
+}
+
+/// Interns the subtrees of `tree` in O(n) expected time: subtrees with equal payloads in the same
+/// shape become one, shared, [`CowTree`].
+pub fn dedup<T: Clone + Eq + Hash>(tree: &Tree<T>) -> Dedup<T> {
+    // Distinct subtrees so far, and their indices by root payload and children.
+    let mut interned: Vec<CowTree<T>> = Vec::new();
+    let mut index = HashMap::new();
+    let mut eliminated = 0;
+    // Indices of the subtrees built but not yet joined to their parent, left before right.
+    let mut built = Vec::new();
+    for node in tree
+        .root()
+        .into_iter()
+        .flat_map(|root| root.iter(Order::Post))
+    {
+        let right = node.right().map(|_| built.pop().unwrap());
+        let left = node.left().map(|_| built.pop().unwrap());
+        let key = (node.get(), left, right);
+        let id = match index.get(&key) {
+            Some(&id) => {
+                eliminated += 1;
+                id
+            }
+            None => {
+                let subtree =
+                    |id: Option<usize>| id.map_or_else(CowTree::new, |id| interned[id].clone());
+                interned.push(CowTree::join(
+                    subtree(left),
+                    node.get().clone(),
+                    subtree(right),
+                ));
+                index.insert(key, interned.len() - 1);
+                interned.len() - 1
+            }
+        };
+        built.push(id);
+    }
+    Dedup {
+        tree: built
+            .pop()
+            .map_or_else(CowTree::new, |id| interned[id].clone()),
+        eliminated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Side::*;
+
+    #[test]
+    fn shares_repeated_subtrees() {
+        let sum = || Tree::join(Tree::with_root(1), 0, Tree::with_root(2));
+        // Both sums, and the leaves below them, collapse into one each.
+        let tree = Tree::join(sum(), 3, Tree::join(sum(), 4, Tree::with_root(1)));
+        let Dedup {
+            tree: shared,
+            eliminated,
+        } = dedup(&tree);
+        assert_eq!(eliminated, 4);
+
+        let root = shared.root().unwrap();
+        let right = root.right().unwrap();
+        assert!(root.child_tree(Left).ptr_eq(&right.child_tree(Left)));
+        let leaf = root.left().unwrap().child_tree(Left);
+        assert!(leaf.ptr_eq(&right.child_tree(Right)));
+        assert_eq!(*right.get(), 4);
+
+        assert_eq!(dedup(&Tree::<u8>::new()).eliminated, 0);
+        assert!(dedup(&Tree::<u8>::new()).tree.is_empty());
+    }
+}
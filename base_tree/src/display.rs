@@ -0,0 +1,144 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display, Write};
+use core::ptr::NonNull;
+
+use crate::{Augment, Node, Side};
+
+/// The path from the root down to a node, displayed as `root > child > grandchild` with each
+/// payload written by a formatter of its own. Returned by [`Node::breadcrumbs`].
+pub struct Breadcrumbs<'a, T, A: Augment<T>, F> {
+    node: &'a Node<T, A>,
+    format: F,
+}
+
+impl<'a, T, A: Augment<T>, F> Breadcrumbs<'a, T, A, F>
+where
+    F: Fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+{
+    pub(crate) fn new(node: &'a Node<T, A>, format: F) -> Self {
+        Self { node, format }
+    }
+}
+
+impl<T, A: Augment<T>, F> Display for Breadcrumbs<'_, T, A, F>
+where
+    F: Fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut path = Vec::new();
+        let mut current = Some(self.node);
+        while let Some(node) = current {
+            path.push(node);
+            current = node.parent();
+        }
+        for (i, node) in path.into_iter().rev().enumerate() {
+            if i > 0 {
+                f.write_str(" > ")?;
+            }
+            (self.format)(node.get(), f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dumps every node reachable from `start`, numbered in pre-order from the topmost ancestor, with
+/// its payload, where its parent pointer leads, and its children. Parent pointers that do not
+/// lead back to the node the walk came from are flagged, and nodes reached twice are only
+/// described once, so this finishes on trees that have been corrupted.
+///
+/// # Safety
+///
+/// Every pointer reachable from `start`, other than parent pointers, must be dereferentiable.
+pub(crate) unsafe fn snapshot<T: Debug, A: Augment<T>>(start: NonNull<Node<T, A>>) -> String {
+    let node = |ptr: NonNull<Node<T, A>>| unsafe { ptr.as_ref() };
+    let mut out = String::new();
+
+    // Parent pointers are only followed after they are known to lead to nodes, which no node
+    // has been yet, so the climb to the top is trusting.
+    let mut top = start;
+    let mut climbed = BTreeSet::from([start]);
+    while let Some(parent) = node(top).parent {
+        if !climbed.insert(parent) {
+            out.push_str("parent pointers loop; starting from where they do\n");
+            break;
+        }
+        top = parent;
+    }
+
+    // Number the nodes, remembering where each was first reached from.
+    let mut ids = BTreeMap::new();
+    let mut order = Vec::new();
+    let mut stack = vec![(top, None)];
+    while let Some((ptr, from)) = stack.pop() {
+        if ids.contains_key(&ptr) {
+            continue;
+        }
+        ids.insert(ptr, order.len());
+        order.push((ptr, from));
+        let current = node(ptr);
+        for (side, child) in [(Side::Right, current.right), (Side::Left, current.left)] {
+            if let Some(child) = child {
+                stack.push((child, Some((ptr, side))));
+            }
+        }
+    }
+
+    let side_name = |side| match side {
+        Side::Left => "left",
+        Side::Right => "right",
+    };
+    for (id, &(ptr, from)) in order.iter().enumerate() {
+        let current = node(ptr);
+        // Writing to a `String` can't fail.
+        let _ = write!(out, "#{id} {:?} parent: ", current.get());
+        match current.parent {
+            None => out.push('-'),
+            Some(parent) => match ids.get(&parent) {
+                None => {
+                    let _ = write!(out, "{parent:p} (not in the tree)");
+                }
+                Some(parent_id) => {
+                    let parent_node = node(parent);
+                    let slot = if parent_node.left == Some(ptr) {
+                        "left"
+                    } else if parent_node.right == Some(ptr) {
+                        "right"
+                    } else {
+                        "(which has no child here)"
+                    };
+                    let _ = write!(out, "#{parent_id} {slot}");
+                }
+            },
+        }
+        for (name, child) in [("left", current.left), ("right", current.right)] {
+            match child {
+                None => {
+                    let _ = write!(out, " {name}: -");
+                }
+                Some(child) => {
+                    let _ = write!(out, " {name}: #{}", ids[&child]);
+                    if ids[&child] <= id {
+                        out.push_str(" (seen before)");
+                    }
+                }
+            }
+        }
+        match from {
+            Some((parent, side)) if current.parent != Some(parent) => {
+                let _ = write!(
+                    out,
+                    " <- reached from #{} {}",
+                    ids[&parent],
+                    side_name(side)
+                );
+            }
+            None if current.parent.is_some() => out.push_str(" <- top of the tree"),
+            _ => {}
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,70 @@
+use core::error::Error;
+use core::fmt::{self, Debug, Display};
+
+use crate::Side;
+
+/// Why a structural change was refused by one of the fallible methods of
+/// [`Node`](crate::Node), which leave the tree untouched when they fail, or of
+/// [`Tree`](crate::Tree).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TreeError {
+    /// The node to be attached is this node or one of its ancestors.
+    WouldCreateCycle,
+    /// The slot to attach to already holds a child.
+    SlotOccupied,
+    /// There is no child where one was expected.
+    NotAChild,
+    /// The path runs off the tree before its last step.
+    PathOutOfBounds,
+    /// The node to be attached still hangs below a parent in some tree.
+    DifferentTrees,
+    /// The trees to be combined don't have the same shape.
+    ShapeMismatch,
+}
+
+impl Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TreeError::WouldCreateCycle => "attaching the node would create a cycle",
+            TreeError::SlotOccupied => "the slot already holds a child",
+            TreeError::NotAChild => "there is no child there",
+            TreeError::PathOutOfBounds => "the path leads out of the tree",
+            TreeError::DifferentTrees => "the node is still attached to a parent",
+            TreeError::ShapeMismatch => "the trees have different shapes",
+        })
+    }
+}
+
+impl Error for TreeError {}
+
+/// Why [`Tree::from_edges`](crate::Tree::from_edges) found its input not to be a single binary
+/// tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeError<I> {
+    /// An edge names a node without a payload.
+    UnknownNode(I),
+    /// The node is the child in more than one edge.
+    MultipleParents(I),
+    /// The node has more than one child on the same side.
+    SlotOccupied(I, Side),
+    /// More than one node is nobody's child.
+    MultipleRoots,
+    /// Some nodes are not below the root, so they are in a cycle.
+    Cycle,
+}
+
+impl<I: Debug> Display for EdgeError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeError::UnknownNode(id) => write!(f, "node {id:?} has no payload"),
+            EdgeError::MultipleParents(id) => write!(f, "node {id:?} has more than one parent"),
+            EdgeError::SlotOccupied(id, side) => {
+                write!(f, "node {id:?} has more than one {side:?} child")
+            }
+            EdgeError::MultipleRoots => write!(f, "the edges form more than one tree"),
+            EdgeError::Cycle => write!(f, "the edges form a cycle"),
+        }
+    }
+}
+
+impl<I: Debug> Error for EdgeError<I> {}
@@ -0,0 +1,333 @@
+//! Arithmetic expression trees: parsing infix notation, printing in all three orders, and
+//! evaluation.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display, Write};
+use core::ops::{Add, Div, Mul, Sub};
+use core::str::FromStr;
+
+use crate::{Node, Tree};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+        }
+    }
+}
+
+/// The payload of an expression tree. Operators have their operands as left and right children,
+/// numbers are leaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expr<N> {
+    Num(N),
+    Op(Op),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A character, or a token in a place it can't be, at the given byte offset.
+    UnexpectedToken(usize),
+    /// The number starting at the given byte offset could not be parsed.
+    InvalidNumber(usize),
+    /// The parenthesis at the given byte offset has no partner.
+    UnmatchedParenthesis(usize),
+    UnexpectedEnd,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken(at) => write!(f, "unexpected token at offset {at}"),
+            ParseError::InvalidNumber(at) => write!(f, "invalid number at offset {at}"),
+            ParseError::UnmatchedParenthesis(at) => {
+                write!(f, "unmatched parenthesis at offset {at}")
+            }
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses an infix expression of numbers, `+ - * /` and parentheses, with the usual precedence
+/// and left associativity.
+pub fn parse<N: FromStr>(input: &str) -> Result<Tree<Expr<N>>, ParseError> {
+    enum Pending {
+        Op(Op),
+        Parenthesis(usize),
+    }
+
+    fn apply<N>(operands: &mut Vec<Tree<Expr<N>>>, op: Op) {
+        // The parser only ever pushes an operator after an operand, so there are always two.
+        let right = operands.pop().unwrap_or_default();
+        let left = operands.pop().unwrap_or_default();
+        operands.push(Tree::join(left, Expr::Op(op), right));
+    }
+
+    let mut operands = Vec::new();
+    let mut pending = Vec::new();
+    let mut expect_operand = true;
+    let mut chars = input.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let op = match c {
+            '+' => Op::Add,
+            '-' => Op::Sub,
+            '*' => Op::Mul,
+            '/' => Op::Div,
+            c if c.is_whitespace() => continue,
+            '(' if expect_operand => {
+                pending.push(Pending::Parenthesis(at));
+                continue;
+            }
+            ')' if !expect_operand => {
+                loop {
+                    match pending.pop() {
+                        Some(Pending::Op(op)) => apply(&mut operands, op),
+                        Some(Pending::Parenthesis(_)) => break,
+                        None => return Err(ParseError::UnmatchedParenthesis(at)),
+                    }
+                }
+                continue;
+            }
+            c if expect_operand && (c.is_alphanumeric() || c == '.') => {
+                let mut end = at + c.len_utf8();
+                while let Some(&(next, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '.') {
+                        break;
+                    }
+                    end = next + c.len_utf8();
+                    chars.next();
+                }
+                let number = input[at..end]
+                    .parse()
+                    .map_err(|_| ParseError::InvalidNumber(at))?;
+                operands.push(Tree::with_root(Expr::Num(number)));
+                expect_operand = false;
+                continue;
+            }
+            _ => return Err(ParseError::UnexpectedToken(at)),
+        };
+        if expect_operand {
+            return Err(ParseError::UnexpectedToken(at));
+        }
+        while let Some(Pending::Op(top)) = pending.last() {
+            if top.precedence() < op.precedence() {
+                break;
+            }
+            apply(&mut operands, *top);
+            pending.pop();
+        }
+        pending.push(Pending::Op(op));
+        expect_operand = true;
+    }
+
+    if expect_operand {
+        return Err(ParseError::UnexpectedEnd);
+    }
+    while let Some(top) = pending.pop() {
+        match top {
+            Pending::Op(op) => apply(&mut operands, op),
+            Pending::Parenthesis(at) => return Err(ParseError::UnmatchedParenthesis(at)),
+        }
+    }
+    Ok(operands.pop().unwrap_or_default())
+}
+
+fn write_payload<N: Display>(out: &mut String, node: &Node<Expr<N>>) {
+    let _ = match node.get() {
+        Expr::Num(number) => write!(out, "{number}"),
+        Expr::Op(op) => write!(out, "{}", op.symbol()),
+    };
+}
+
+/// Prints the expression in infix notation, with only the parentheses the shape of the tree
+/// requires.
+pub fn to_infix<N: Display>(root: &Node<Expr<N>>) -> String {
+    enum Work<'a, N> {
+        Node(&'a Node<Expr<N>>, bool),
+        Op(Op),
+        Text(&'static str),
+    }
+
+    let precedence = |node: Option<&Node<Expr<N>>>| match node.map(Node::get) {
+        Some(Expr::Op(op)) => op.precedence(),
+        _ => u8::MAX,
+    };
+
+    let mut out = String::new();
+    let mut stack = vec![Work::Node(root, false)];
+    while let Some(work) = stack.pop() {
+        let (node, parenthesized) = match work {
+            Work::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Work::Op(op) => {
+                let _ = write!(out, " {} ", op.symbol());
+                continue;
+            }
+            Work::Node(node, parenthesized) => (node, parenthesized),
+        };
+        let Expr::Op(op) = node.get() else {
+            write_payload(&mut out, node);
+            continue;
+        };
+        if parenthesized {
+            stack.push(Work::Text(")"));
+        }
+        match node.right() {
+            Some(right) => stack.push(Work::Node(
+                right,
+                precedence(Some(right)) <= op.precedence(),
+            )),
+            None => stack.push(Work::Text("?")),
+        }
+        stack.push(Work::Op(*op));
+        match node.left() {
+            Some(left) => stack.push(Work::Node(left, precedence(Some(left)) < op.precedence())),
+            None => stack.push(Work::Text("?")),
+        }
+        if parenthesized {
+            stack.push(Work::Text("("));
+        }
+    }
+    out
+}
+
+/// Prints the expression in prefix (Polish) notation, separated by spaces.
+pub fn to_prefix<N: Display>(root: &Node<Expr<N>>) -> String {
+    let mut out = String::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        write_payload(&mut out, node);
+        stack.extend(node.right());
+        stack.extend(node.left());
+    }
+    out
+}
+
+/// Prints the expression in postfix (reverse Polish) notation, separated by spaces.
+pub fn to_postfix<N: Display>(root: &Node<Expr<N>>) -> String {
+    let mut out = String::new();
+    for node in post_order(root) {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        write_payload(&mut out, node);
+    }
+    out
+}
+
+/// Evaluates the expression without recursing, or returns `None` if an operator is missing an
+/// operand. Arithmetic follows `N`, so integer division by zero panics as usual.
+pub fn evaluate<N>(root: &Node<Expr<N>>) -> Option<N>
+where
+    N: Clone + Add<Output = N> + Sub<Output = N> + Mul<Output = N> + Div<Output = N>,
+{
+    let mut values = Vec::new();
+    for node in post_order(root) {
+        let value = match node.get() {
+            Expr::Num(number) => number.clone(),
+            Expr::Op(op) => {
+                if node.left().is_none() || node.right().is_none() {
+                    return None;
+                }
+                let right = values.pop()?;
+                let left = values.pop()?;
+                match op {
+                    Op::Add => left + right,
+                    Op::Sub => left - right,
+                    Op::Mul => left * right,
+                    Op::Div => left / right,
+                }
+            }
+        };
+        values.push(value);
+    }
+    values.pop()
+}
+
+fn post_order<T>(root: &Node<T>) -> Vec<&Node<T>> {
+    // A pre-order visiting right before left, reversed.
+    let mut order = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        order.push(node);
+        stack.extend(node.left());
+        stack.extend(node.right());
+    }
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_print_evaluate() {
+        let tree = parse::<i64>("2 * (3 + 4) - 10 / (5 - 3) - 1").unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(to_infix(root), "2 * (3 + 4) - 10 / (5 - 3) - 1");
+        assert_eq!(to_prefix(root), "- - * 2 + 3 4 / 10 - 5 3 1");
+        assert_eq!(to_postfix(root), "2 3 4 + * 10 5 3 - / - 1 -");
+        assert_eq!(evaluate(root), Some(8));
+
+        let tree = parse::<f64>("1 - (2 - 3.5)").unwrap();
+        assert_eq!(to_infix(tree.root().unwrap()), "1 - (2 - 3.5)");
+        assert_eq!(evaluate(tree.root().unwrap()), Some(2.5));
+
+        assert_eq!(parse::<i64>("1 +").err(), Some(ParseError::UnexpectedEnd));
+        assert_eq!(
+            parse::<i64>("(1 + 2").err(),
+            Some(ParseError::UnmatchedParenthesis(0))
+        );
+        assert_eq!(
+            parse::<i64>("1 + 2)").err(),
+            Some(ParseError::UnmatchedParenthesis(5))
+        );
+        assert_eq!(
+            parse::<i64>("1 2").err(),
+            Some(ParseError::UnexpectedToken(2))
+        );
+        assert_eq!(
+            parse::<i64>("1 + x").err(),
+            Some(ParseError::InvalidNumber(4))
+        );
+        assert!(parse::<i64>("").is_err());
+    }
+
+    #[test]
+    fn long_chains_do_not_recurse() {
+        let input = vec!["1"; 100_000].join(" - ");
+        let tree = parse::<i64>(&input).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(evaluate(root), Some(1 - 99_999));
+        assert_eq!(to_infix(root), input);
+    }
+}
@@ -0,0 +1,346 @@
+//! A harness for fuzzing: sequences of operations applied both to the trees and maps of this
+//! crate and to plain reference models, [`VecMap`] for the maps, panicking as soon as the two
+//! disagree. A cargo-fuzz target only needs to call `run(&decode(data))`.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+use crate::aa_tree::AaTreeMap;
+use crate::model::VecMap;
+use crate::{BstMap, Order, Side, Tree, WeightBalancedMap};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Hangs a new leaf into the empty slot at `path`, or makes it the root of the empty tree.
+    Attach {
+        path: Vec<Side>,
+        value: u32,
+    },
+    /// Removes the subtree at `path`.
+    Detach {
+        path: Vec<Side>,
+    },
+    /// Rotates the node at `path` towards `side`.
+    Rotate {
+        path: Vec<Side>,
+        side: Side,
+    },
+    /// Inserts the key into the maps, with the number of the operation as its value.
+    Insert(u8),
+    Remove(u8),
+    /// Walks the tree in the given order.
+    Iterate(Order),
+}
+
+/// Turns arbitrary bytes into operations, a byte picking the kind of each and the next ones its
+/// arguments. Paths are at most seven steps long. Trailing bytes too few for an operation are
+/// ignored.
+pub fn decode(data: &[u8]) -> Vec<Op> {
+    let mut bytes = data.iter().copied();
+    let mut ops = Vec::new();
+    let path = |length: u8, bits: u8| -> Vec<Side> {
+        (0..length % 8)
+            .map(|bit| match bits >> bit & 1 {
+                0 => Side::Left,
+                _ => Side::Right,
+            })
+            .collect()
+    };
+    while let Some(kind) = bytes.next() {
+        let op = match kind % 6 {
+            0 => {
+                let (Some(length), Some(bits), Some(value)) =
+                    (bytes.next(), bytes.next(), bytes.next())
+                else {
+                    break;
+                };
+                Op::Attach {
+                    path: path(length, bits),
+                    value: value.into(),
+                }
+            }
+            1 => {
+                let (Some(length), Some(bits)) = (bytes.next(), bytes.next()) else {
+                    break;
+                };
+                Op::Detach {
+                    path: path(length, bits),
+                }
+            }
+            2 => {
+                let (Some(length), Some(bits)) = (bytes.next(), bytes.next()) else {
+                    break;
+                };
+                let side = if length & 0x80 == 0 {
+                    Side::Left
+                } else {
+                    Side::Right
+                };
+                Op::Rotate {
+                    path: path(length, bits),
+                    side,
+                }
+            }
+            3 => match bytes.next() {
+                Some(key) => Op::Insert(key),
+                None => break,
+            },
+            4 => match bytes.next() {
+                Some(key) => Op::Remove(key),
+                None => break,
+            },
+            _ => match bytes.next().map(|order| order % 4) {
+                Some(0) => Op::Iterate(Order::Pre),
+                Some(1) => Op::Iterate(Order::In),
+                Some(2) => Op::Iterate(Order::Post),
+                Some(_) => Op::Iterate(Order::Level),
+                None => break,
+            },
+        };
+        ops.push(op);
+    }
+    ops
+}
+
+/// A tree kept as indices into a list of nodes, where nothing can go wrong. Detached nodes are
+/// left in the list, unreachable.
+#[derive(Default)]
+struct Model {
+    // The value of each node, and its children in the order of `Side::Left` and `Side::Right`.
+    nodes: Vec<(u32, [Option<usize>; 2])>,
+    root: Option<usize>,
+}
+
+fn index(side: Side) -> usize {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+    }
+}
+
+impl Model {
+    /// Where `path` ends: the root, or a parent and the index of one of its children.
+    fn slot(&self, path: &[Side]) -> Option<Option<(usize, usize)>> {
+        let mut slot = None;
+        for &side in path {
+            let node = self.get(slot)?;
+            slot = Some((node, index(side)));
+        }
+        Some(slot)
+    }
+
+    fn get(&self, slot: Option<(usize, usize)>) -> Option<usize> {
+        match slot {
+            None => self.root,
+            Some((parent, child)) => self.nodes[parent].1[child],
+        }
+    }
+
+    fn set(&mut self, slot: Option<(usize, usize)>, node: Option<usize>) {
+        match slot {
+            None => self.root = node,
+            Some((parent, child)) => self.nodes[parent].1[child] = node,
+        }
+    }
+
+    fn attach(&mut self, path: &[Side], value: u32) -> bool {
+        match self.slot(path) {
+            Some(slot) if self.get(slot).is_none() => {
+                self.set(slot, Some(self.nodes.len()));
+                self.nodes.push((value, [None, None]));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn detach(&mut self, path: &[Side]) -> bool {
+        match self.slot(path) {
+            Some(slot @ Some(_)) if self.get(slot).is_some() => {
+                self.set(slot, None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn rotate(&mut self, path: &[Side], side: Side) -> bool {
+        let (towards, away) = (index(side), 1 - index(side));
+        let Some(slot) = self.slot(path) else {
+            return false;
+        };
+        let Some(node) = self.get(slot) else {
+            return false;
+        };
+        let Some(pivot) = self.nodes[node].1[away] else {
+            return false;
+        };
+        self.nodes[node].1[away] = self.nodes[pivot].1[towards];
+        self.nodes[pivot].1[towards] = Some(node);
+        self.set(slot, Some(pivot));
+        true
+    }
+
+    fn values(&self, order: Order) -> Vec<u32> {
+        let mut values = Vec::new();
+        match order {
+            Order::Pre | Order::Post => {
+                // Post-order is pre-order with right subtrees first, backwards.
+                let first = if order == Order::Pre { 1 } else { 0 };
+                let mut stack: Vec<usize> = self.root.into_iter().collect();
+                while let Some(node) = stack.pop() {
+                    let (value, children) = self.nodes[node];
+                    values.push(value);
+                    stack.extend(children[first]);
+                    stack.extend(children[1 - first]);
+                }
+                if order == Order::Post {
+                    values.reverse();
+                }
+            }
+            Order::In => {
+                let (mut stack, mut current) = (Vec::new(), self.root);
+                while current.is_some() || !stack.is_empty() {
+                    while let Some(node) = current {
+                        stack.push(node);
+                        current = self.nodes[node].1[0];
+                    }
+                    let node = stack.pop().unwrap();
+                    values.push(self.nodes[node].0);
+                    current = self.nodes[node].1[1];
+                }
+            }
+            Order::Level => {
+                let mut queue: VecDeque<usize> = self.root.into_iter().collect();
+                while let Some(node) = queue.pop_front() {
+                    let (value, children) = self.nodes[node];
+                    values.push(value);
+                    queue.extend(children.into_iter().flatten());
+                }
+            }
+        }
+        values
+    }
+
+    /// Every slot in pre-order, empty ones included, which pins down the whole shape.
+    fn slots(&self) -> Vec<Option<u32>> {
+        let mut slots = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(slot) = stack.pop() {
+            slots.push(slot.map(|node| self.nodes[node].0));
+            if let Some(node) = slot {
+                stack.extend([self.nodes[node].1[1], self.nodes[node].1[0]]);
+            }
+        }
+        slots
+    }
+}
+
+fn slots(tree: &Tree<u32>) -> Vec<Option<u32>> {
+    let mut slots = Vec::new();
+    let mut stack = vec![tree.root()];
+    while let Some(slot) = stack.pop() {
+        slots.push(slot.map(|node| *node.get()));
+        if let Some(node) = slot {
+            stack.extend([node.right(), node.left()]);
+        }
+    }
+    slots
+}
+
+/// Applies `ops` to a tree and to each of the maps, and the same to the models, checking after
+/// every operation that they agree.
+///
+/// # Panics
+///
+/// As soon as they don't.
+pub fn run(ops: &[Op]) {
+    let mut tree = Tree::new();
+    let mut model = Model::default();
+    let mut scapegoat = BstMap::new();
+    let mut weighted = WeightBalancedMap::new();
+    let mut aa = AaTreeMap::new();
+    let mut map_model = VecMap::new();
+
+    for (step, op) in ops.iter().enumerate() {
+        match op {
+            Op::Attach { path, value } => {
+                let attached = tree.graft(path, Tree::with_root(*value)).is_ok();
+                assert_eq!(attached, model.attach(path, *value), "{op:?}");
+            }
+            Op::Detach { path } => {
+                // Safety: the detached subtree is handed straight to a tree of its own.
+                let detached = unsafe { tree.root_mut() }
+                    .and_then(|root| root.try_detach(path).ok())
+                    .map(|node| drop(unsafe { Tree::from_root_ptr(Some(NonNull::from(node))) }));
+                assert_eq!(detached.is_some(), model.detach(path), "{op:?}");
+            }
+            Op::Rotate { path, side } => {
+                // Safety: rotations only move nodes around within the tree.
+                let node = unsafe { tree.root_mut() }.and_then(|root| root.at_path_mut(path));
+                let rotated = node.is_some_and(|node| match side {
+                    Side::Left => node.rotate_left().is_some(),
+                    Side::Right => node.rotate_right().is_some(),
+                });
+                assert_eq!(rotated, model.rotate(path, *side), "{op:?}");
+            }
+            Op::Insert(key) => {
+                let expected = map_model.insert(*key, step);
+                assert_eq!(scapegoat.insert(*key, step), expected, "{op:?}");
+                assert_eq!(weighted.insert(*key, step), expected, "{op:?}");
+                assert_eq!(aa.insert(*key, step), expected, "{op:?}");
+            }
+            Op::Remove(key) => {
+                let expected = map_model.remove(key);
+                assert_eq!(scapegoat.remove(key), expected, "{op:?}");
+                assert_eq!(weighted.remove(key), expected, "{op:?}");
+                assert_eq!(aa.remove(key), expected, "{op:?}");
+            }
+            Op::Iterate(order) => {
+                let values: Vec<u32> = tree
+                    .root()
+                    .map(|root| root.iter(*order).map(|node| *node.get()).collect())
+                    .unwrap_or_default();
+                assert_eq!(values, model.values(*order), "{op:?}");
+                assert!(scapegoat.iter().eq(map_model.iter()));
+                assert!(weighted.iter().eq(map_model.iter()));
+                assert!(aa.iter().eq(map_model.iter()));
+            }
+        }
+        assert_eq!(slots(&tree), model.slots(), "after {op:?}");
+        assert_eq!(scapegoat.len(), map_model.len());
+        assert_eq!(weighted.len(), map_model.len());
+        assert_eq!(aa.len(), map_model.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_operations() {
+        let mut random = crate::lcg(17);
+        let data: Vec<u8> = (0..20_000).map(|_| random(256) as u8).collect();
+        let ops = decode(&data);
+        assert!(ops.len() > 5000);
+        run(&ops);
+
+        assert_eq!(
+            decode(&[6, 2, 0b01, 9, 8, 130, 0b10, 9, 1, 3]),
+            [
+                Op::Attach {
+                    path: vec![Side::Right, Side::Left],
+                    value: 9
+                },
+                Op::Rotate {
+                    path: vec![Side::Left, Side::Right],
+                    side: Side::Right
+                },
+                Op::Insert(1),
+            ]
+        );
+    }
+}
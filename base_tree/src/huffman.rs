@@ -0,0 +1,116 @@
+//! Huffman coding trees, built by repeatedly joining the two lightest trees.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+use std::ops::Add;
+
+use crate::{Node, Tree};
+
+pub struct HuffmanNode<S, W> {
+    /// The total weight of the symbols below this node.
+    pub weight: W,
+    /// The symbol of a leaf, `None` for internal nodes.
+    pub symbol: Option<S>,
+}
+
+pub struct Huffman<S, W> {
+    pub tree: Tree<HuffmanNode<S, W>>,
+    /// The path to every symbol's leaf, where `false` means going left and `true` going right.
+    pub codes: HashMap<S, Vec<bool>>,
+}
+
+/// Builds the Huffman tree of the given symbols, along with the code of every symbol. Ties between
+/// equal weights are broken in favour of the earlier symbol (or merged tree), so the result is
+/// deterministic. A lone symbol becomes the root with an empty code.
+pub fn build<S, W>(weights: impl IntoIterator<Item = (S, W)>) -> Huffman<S, W>
+where
+    S: Clone + Eq + Hash,
+    W: Copy + Ord + Add<Output = W>,
+{
+    let mut trees = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for (symbol, weight) in weights {
+        heap.push(Reverse((weight, trees.len())));
+        trees.push(Some(Tree::with_root(HuffmanNode {
+            weight,
+            symbol: Some(symbol),
+        })));
+    }
+
+    while let (Some(Reverse((left_weight, left))), Some(Reverse((right_weight, right)))) =
+        (heap.pop(), heap.peek().copied())
+    {
+        heap.pop();
+        let weight = left_weight + right_weight;
+        let joined = Tree::join(
+            trees[left].take().unwrap_or_default(),
+            HuffmanNode {
+                weight,
+                symbol: None,
+            },
+            trees[right].take().unwrap_or_default(),
+        );
+        heap.push(Reverse((weight, trees.len())));
+        trees.push(Some(joined));
+    }
+
+    let tree = trees.pop().flatten().unwrap_or_default();
+    let mut codes = HashMap::new();
+    let mut stack: Vec<(&Node<_>, Vec<bool>)> =
+        tree.root().map(|root| (root, vec![])).into_iter().collect();
+    while let Some((node, code)) = stack.pop() {
+        if let Some(symbol) = &node.get().symbol {
+            codes.insert(symbol.clone(), code.clone());
+        }
+        for (child, bit) in [(node.left(), false), (node.right(), true)] {
+            if let Some(child) = child {
+                let mut code = code.clone();
+                code.push(bit);
+                stack.push((child, code));
+            }
+        }
+    }
+
+    Huffman { tree, codes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimal_code_lengths() {
+        let weights = [
+            ('a', 45),
+            ('b', 13),
+            ('c', 12),
+            ('d', 16),
+            ('e', 9),
+            ('f', 5),
+        ];
+        let Huffman { tree, codes } = build(weights);
+        assert_eq!(tree.root().map(|root| root.get().weight), Some(100));
+
+        let total: usize = weights
+            .iter()
+            .map(|(symbol, weight)| codes[symbol].len() * weight)
+            .sum();
+        assert_eq!(total, 224);
+        assert_eq!(codes[&'a'], [false]);
+
+        // The codes are prefix free, and lead to their symbols.
+        for (symbol, code) in &codes {
+            let mut node = tree.root().unwrap();
+            for &bit in code {
+                node = if bit { node.right() } else { node.left() }.unwrap();
+            }
+            assert_eq!(node.get().symbol, Some(*symbol));
+        }
+
+        let Huffman { tree, codes } = build([("only", 3)]);
+        assert_eq!(codes["only"], Vec::<bool>::new());
+        assert!(tree.root().is_some_and(|root| root.left().is_none()));
+        assert!(build::<char, u32>([]).tree.is_empty());
+    }
+}
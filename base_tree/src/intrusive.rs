@@ -0,0 +1,684 @@
+//! Binary search trees threaded through links embedded in the values they hold, so that a tree
+//! allocates nothing of its own.
+
+use core::borrow::Borrow;
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+use crate::bst::{depth_limit, ALPHA};
+use crate::Side;
+
+/// A link to another value of whatever type holds the [`TreeLink`].
+type Erased = Cell<Option<NonNull<()>>>;
+
+type Link<A> = Option<NonNull<<A as Adapter>::Value>>;
+
+// The last identity handed to a tree, to tell which tree a value is linked into.
+static TREES: AtomicU64 = AtomicU64::new(0);
+
+/// The links of an [`IntrusiveBst`], to be embedded as a field of the values it holds. A value is
+/// in at most one tree at a time through each of its links.
+#[derive(Default)]
+pub struct TreeLink {
+    left: Erased,
+    right: Erased,
+    parent: Erased,
+    // The identity of the tree the value is in, or 0.
+    tree: Cell<u64>,
+}
+
+impl TreeLink {
+    pub const fn new() -> Self {
+        Self {
+            left: Cell::new(None),
+            right: Cell::new(None),
+            parent: Cell::new(None),
+            tree: Cell::new(0),
+        }
+    }
+
+    /// Whether the value holding this link is in a tree through it.
+    pub fn is_linked(&self) -> bool {
+        self.tree.get() != 0
+    }
+
+    fn child(&self, side: Side) -> &Erased {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn reset(&self) {
+        self.left.set(None);
+        self.right.set(None);
+        self.parent.set(None);
+        self.tree.set(0);
+    }
+}
+
+impl Debug for TreeLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TreeLink")
+            .field("linked", &self.is_linked())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Finds the [`TreeLink`] embedded in a value. [`intrusive_adapter!`](crate::intrusive_adapter)
+/// implements it for a field.
+///
+/// # Safety
+///
+/// `link` must return the same link every time it is given the same value, and that link must be
+/// part of the value and of no other.
+pub unsafe trait Adapter {
+    type Value;
+
+    fn link(value: &Self::Value) -> &TreeLink;
+}
+
+/// Declares a unit struct `$adapter`, an [`Adapter`](crate::intrusive::Adapter) linking values of
+/// type `$value` through their `$field`, which must be a
+/// [`TreeLink`](crate::intrusive::TreeLink).
+#[macro_export]
+macro_rules! intrusive_adapter {
+    ($vis:vis $adapter:ident = $value:ty { $field:ident }) => {
+        $vis struct $adapter;
+
+        // Safety: the link is a field of the value.
+        unsafe impl $crate::intrusive::Adapter for $adapter {
+            type Value = $value;
+
+            fn link(value: &$value) -> &$crate::intrusive::TreeLink {
+                &value.$field
+            }
+        }
+    };
+}
+
+/// A binary search tree of values borrowed for `'a`, ordered by [`Ord`] and linked through the
+/// [`TreeLink`] that `A` finds in each. It is balanced as a scapegoat tree, like
+/// [`BstMap`](crate::BstMap), but keeps no subtree sizes: they are counted when a subtree might
+/// need rebuilding, and it is rebuilt in place, so that the tree never allocates.
+///
+/// Dropping the tree unlinks its values, which can then go into another one.
+pub struct IntrusiveBst<'a, A: Adapter> {
+    root: Link<A>,
+    len: usize,
+    // The largest the tree has been since it was last rebuilt completely.
+    max_len: usize,
+    // Minted on the first insertion, so that `new` can be const.
+    id: u64,
+    _marker: PhantomData<&'a A::Value>,
+}
+
+impl<'a, A: Adapter> IntrusiveBst<'a, A> {
+    pub const fn new() -> Self {
+        Self {
+            root: None,
+            len: 0,
+            max_len: 0,
+            id: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Iterates over the values in order.
+    pub fn iter(&self) -> Iter<'_, A> {
+        // Safety: the tree is borrowed, so its links stay unchanged.
+        unsafe {
+            Iter {
+                front: self.root.map(|root| extreme::<A>(root, Side::Left)),
+                back: self.root.map(|root| extreme::<A>(root, Side::Right)),
+                remaining: self.len,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// Whether `value` itself is in this tree, rather than just an equal one.
+    pub fn contains_value(&self, value: &A::Value) -> bool {
+        self.id != 0 && A::link(value).tree.get() == self.id
+    }
+
+    /// Unlinks every value, in O(n).
+    pub fn clear(&mut self) {
+        let mut current = self.root.take();
+        while let Some(ptr) = current {
+            // Safety: the nodes of the tree are borrowed for `'a`, and the tree is borrowed
+            // mutably. Each node is unlinked after its children are visited.
+            unsafe {
+                current = match (child::<A>(ptr, Side::Left), child::<A>(ptr, Side::Right)) {
+                    (Some(left), _) => {
+                        set_child::<A>(ptr, Side::Left, None);
+                        Some(left)
+                    }
+                    (None, Some(right)) => {
+                        set_child::<A>(ptr, Side::Right, None);
+                        Some(right)
+                    }
+                    (None, None) => {
+                        let parent = parent::<A>(ptr);
+                        link::<A>(ptr).reset();
+                        parent
+                    }
+                };
+            }
+        }
+        (self.len, self.max_len) = (0, 0);
+    }
+
+    /// Removes `value`, returning whether it was in this tree.
+    pub fn remove(&mut self, value: &'a A::Value) -> bool {
+        if !self.contains_value(value) {
+            return false;
+        }
+        let ptr = NonNull::from(value);
+        // Safety: the value is in this tree, which is borrowed mutably.
+        unsafe {
+            let (left, right) = (child::<A>(ptr, Side::Left), child::<A>(ptr, Side::Right));
+            match (left, right) {
+                (Some(left), Some(right)) => {
+                    // The successor takes the place of the value, as payloads can't be swapped.
+                    let next = extreme::<A>(right, Side::Left);
+                    if next != right {
+                        self.replace(next, child::<A>(next, Side::Right));
+                        set_child::<A>(next, Side::Right, Some(right));
+                        set_parent::<A>(right, Some(next));
+                    }
+                    set_child::<A>(next, Side::Left, Some(left));
+                    set_parent::<A>(left, Some(next));
+                    self.replace(ptr, Some(next));
+                }
+                (child, None) | (None, child) => self.replace(ptr, child),
+            }
+            link::<A>(ptr).reset();
+        }
+        self.len -= 1;
+        if (self.len as f64) < ALPHA * self.max_len as f64 {
+            if let Some(root) = self.root {
+                unsafe { self.rebuild(root) };
+            }
+            self.max_len = self.len;
+        }
+        true
+    }
+
+    /// Puts `new` where `old` hangs, or makes it the root if `old` was.
+    ///
+    /// # Safety
+    ///
+    /// `old` must be in this tree and `new`, if any, must be ready to take its place.
+    unsafe fn replace(&mut self, old: NonNull<A::Value>, new: Link<A>) {
+        let parent = unsafe { parent::<A>(old) };
+        match parent {
+            None => self.root = new,
+            Some(parent) => unsafe {
+                let side = side_of::<A>(parent, old);
+                set_child::<A>(parent, side, new);
+            },
+        }
+        if let Some(new) = new {
+            unsafe { set_parent::<A>(new, parent) };
+        }
+    }
+
+    /// Rotates the child of `ptr` opposite `side` up into its place, if there is one, returning
+    /// it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be in this tree.
+    unsafe fn rotate(&mut self, ptr: NonNull<A::Value>, side: Side) -> Link<A> {
+        let other = opposite(side);
+        let pivot = unsafe { child::<A>(ptr, other) }?;
+        unsafe {
+            let inner = child::<A>(pivot, side);
+            set_child::<A>(ptr, other, inner);
+            if let Some(inner) = inner {
+                set_parent::<A>(inner, Some(ptr));
+            }
+            self.replace(ptr, Some(pivot));
+            set_child::<A>(pivot, side, Some(ptr));
+            set_parent::<A>(ptr, Some(pivot));
+        }
+        Some(pivot)
+    }
+
+    /// Rebalances the subtree rooted at `root` in place, by turning it into a vine and folding
+    /// that back up, as [`Node::balance`](crate::Node::balance) does.
+    ///
+    /// # Safety
+    ///
+    /// `root` must be in this tree.
+    unsafe fn rebuild(&mut self, mut root: NonNull<A::Value>) {
+        let mut size = 0;
+        let mut current = Some(root);
+        while let Some(ptr) = current {
+            if let Some(pivot) = unsafe { self.rotate(ptr, Side::Right) } {
+                if ptr == root {
+                    root = pivot;
+                }
+                current = Some(pivot);
+            } else {
+                size += 1;
+                current = unsafe { child::<A>(ptr, Side::Right) };
+            }
+        }
+
+        let full = (size + 1usize).next_power_of_two() / 2 - 1;
+        let mut compress = |tree: &mut Self, count: usize| {
+            let mut current = Some(root);
+            for i in 0..count {
+                let Some(pivot) = current.and_then(|ptr| unsafe { tree.rotate(ptr, Side::Left) })
+                else {
+                    break;
+                };
+                if i == 0 {
+                    root = pivot;
+                }
+                current = unsafe { child::<A>(pivot, Side::Right) };
+            }
+        };
+        compress(self, size - full);
+        let mut level = full;
+        while level > 1 {
+            level /= 2;
+            compress(self, level);
+        }
+    }
+}
+
+impl<'a, A: Adapter> IntrusiveBst<'a, A>
+where
+    A::Value: Ord,
+{
+    /// Links `value` into the tree, returning false and leaving it out if an equal value is
+    /// already in.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is already linked, in this tree or another.
+    pub fn insert(&mut self, value: &'a A::Value) -> bool {
+        assert!(!A::link(value).is_linked(), "the value is already linked");
+        let new = NonNull::from(value);
+        let (mut current, mut slot, mut depth) = (self.root, None, 1);
+        while let Some(ptr) = current {
+            // Safety: the values of the tree are borrowed for `'a`.
+            let side = match value.cmp(unsafe { ptr.as_ref() }) {
+                Ordering::Equal => return false,
+                Ordering::Less => Side::Left,
+                Ordering::Greater => Side::Right,
+            };
+            current = unsafe { child::<A>(ptr, side) };
+            slot = Some((ptr, side));
+            depth += 1;
+        }
+
+        if self.id == 0 {
+            self.id = TREES.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        }
+        A::link(value).tree.set(self.id);
+        match slot {
+            None => self.root = Some(new),
+            Some((parent, side)) => unsafe {
+                set_child::<A>(parent, side, Some(new));
+                set_parent::<A>(new, Some(parent));
+            },
+        }
+        self.len += 1;
+        self.max_len = self.max_len.max(self.len);
+        if depth > depth_limit(self.len) {
+            unsafe { self.rebuild_scapegoat(new) };
+        }
+        true
+    }
+
+    /// The value equal to `key`, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&'a A::Value>
+    where
+        A::Value: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.root;
+        while let Some(ptr) = current {
+            // Safety: the values of the tree are borrowed for `'a`.
+            let value = unsafe { ptr.as_ref() };
+            current = match key.cmp(value.borrow()) {
+                Ordering::Equal => return Some(value),
+                Ordering::Less => unsafe { child::<A>(ptr, Side::Left) },
+                Ordering::Greater => unsafe { child::<A>(ptr, Side::Right) },
+            };
+        }
+        None
+    }
+
+    /// Rebalances the subtree of the lowest ancestor of `new` with a child holding more than
+    /// `ALPHA` of its nodes, counting the sizes on the way up.
+    ///
+    /// # Safety
+    ///
+    /// `new` must be a leaf of this tree.
+    unsafe fn rebuild_scapegoat(&mut self, new: NonNull<A::Value>) {
+        let (mut child_ptr, mut child_size) = (new, 1);
+        while let Some(parent) = unsafe { parent::<A>(child_ptr) } {
+            let side = unsafe { side_of::<A>(parent, child_ptr) };
+            let sibling = unsafe { child::<A>(parent, opposite(side)) };
+            let size = child_size + 1 + sibling.map_or(0, |sibling| unsafe { count::<A>(sibling) });
+            if child_size as f64 > ALPHA * size as f64 {
+                unsafe { self.rebuild(parent) };
+                return;
+            }
+            (child_ptr, child_size) = (parent, size);
+        }
+    }
+}
+
+impl<A: Adapter> Default for IntrusiveBst<'_, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Adapter> Debug for IntrusiveBst<'_, A>
+where
+    A::Value: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<A: Adapter> Drop for IntrusiveBst<'_, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Iterates over the values of an [`IntrusiveBst`] in order.
+pub struct Iter<'t, A: Adapter> {
+    front: Link<A>,
+    back: Link<A>,
+    remaining: usize,
+    _marker: PhantomData<&'t A::Value>,
+}
+
+impl<'t, A: Adapter> Iterator for Iter<'t, A> {
+    type Item = &'t A::Value;
+
+    fn next(&mut self) -> Option<&'t A::Value> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ptr = self.front?;
+        self.remaining -= 1;
+        // Safety: the tree is borrowed for `'t`, so its links stay unchanged.
+        self.front = unsafe { step::<A>(ptr, Side::Right) };
+        Some(unsafe { ptr.as_ref() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'t, A: Adapter> DoubleEndedIterator for Iter<'t, A> {
+    fn next_back(&mut self) -> Option<&'t A::Value> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ptr = self.back?;
+        self.remaining -= 1;
+        self.back = unsafe { step::<A>(ptr, Side::Left) };
+        Some(unsafe { ptr.as_ref() })
+    }
+}
+
+impl<A: Adapter> ExactSizeIterator for Iter<'_, A> {}
+
+impl<A: Adapter> FusedIterator for Iter<'_, A> {}
+
+// The helpers below require the values they are handed, and the values linked to them, to be
+// alive and borrowed for as long as the caller uses them.
+
+fn opposite(side: Side) -> Side {
+    match side {
+        Side::Left => Side::Right,
+        Side::Right => Side::Left,
+    }
+}
+
+unsafe fn link<'v, A: Adapter>(ptr: NonNull<A::Value>) -> &'v TreeLink
+where
+    A::Value: 'v,
+{
+    A::link(unsafe { ptr.as_ref() })
+}
+
+unsafe fn child<A: Adapter>(ptr: NonNull<A::Value>, side: Side) -> Link<A> {
+    unsafe { link::<A>(ptr) }
+        .child(side)
+        .get()
+        .map(NonNull::cast)
+}
+
+unsafe fn set_child<A: Adapter>(ptr: NonNull<A::Value>, side: Side, child: Link<A>) {
+    unsafe { link::<A>(ptr) }
+        .child(side)
+        .set(child.map(NonNull::cast));
+}
+
+unsafe fn parent<A: Adapter>(ptr: NonNull<A::Value>) -> Link<A> {
+    unsafe { link::<A>(ptr) }.parent.get().map(NonNull::cast)
+}
+
+unsafe fn set_parent<A: Adapter>(ptr: NonNull<A::Value>, parent: Link<A>) {
+    unsafe { link::<A>(ptr) }
+        .parent
+        .set(parent.map(NonNull::cast));
+}
+
+/// Which child of `parent` `ptr` is.
+unsafe fn side_of<A: Adapter>(parent: NonNull<A::Value>, ptr: NonNull<A::Value>) -> Side {
+    if unsafe { child::<A>(parent, Side::Left) } == Some(ptr) {
+        Side::Left
+    } else {
+        Side::Right
+    }
+}
+
+/// The value reached by going to `side` from `ptr` for as long as possible.
+unsafe fn extreme<A: Adapter>(mut ptr: NonNull<A::Value>, side: Side) -> NonNull<A::Value> {
+    while let Some(next) = unsafe { child::<A>(ptr, side) } {
+        ptr = next;
+    }
+    ptr
+}
+
+/// The next value in order towards `side`: the successor to the right, the predecessor to the
+/// left.
+unsafe fn step<A: Adapter>(ptr: NonNull<A::Value>, side: Side) -> Link<A> {
+    if let Some(next) = unsafe { child::<A>(ptr, side) } {
+        return Some(unsafe { extreme::<A>(next, opposite(side)) });
+    }
+    let mut current = ptr;
+    loop {
+        let parent = unsafe { parent::<A>(current) }?;
+        if unsafe { side_of::<A>(parent, current) } != side {
+            return Some(parent);
+        }
+        current = parent;
+    }
+}
+
+/// The number of values in the subtree rooted at `root`, counted without allocating by walking
+/// it along the links, parent links included.
+unsafe fn count<A: Adapter>(root: NonNull<A::Value>) -> usize {
+    let mut size = 0;
+    // Where the walk came to `ptr` from: above, or the child on a side.
+    let (mut ptr, mut from) = (root, None);
+    loop {
+        let down = match from {
+            None => {
+                size += 1;
+                unsafe { child::<A>(ptr, Side::Left).or(child::<A>(ptr, Side::Right)) }
+            }
+            Some(Side::Left) => unsafe { child::<A>(ptr, Side::Right) },
+            Some(Side::Right) => None,
+        };
+        if let Some(down) = down {
+            (ptr, from) = (down, None);
+            continue;
+        }
+        if ptr == root {
+            return size;
+        }
+        let parent = unsafe { parent::<A>(ptr) }.expect("the walk stays below the root");
+        from = Some(unsafe { side_of::<A>(parent, ptr) });
+        ptr = parent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::vec::Vec;
+
+    #[derive(Debug)]
+    struct Job {
+        priority: u32,
+        link: TreeLink,
+        other: TreeLink,
+    }
+
+    impl PartialEq for Job {
+        fn eq(&self, other: &Self) -> bool {
+            self.priority == other.priority
+        }
+    }
+
+    impl Eq for Job {}
+
+    impl PartialOrd for Job {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Job {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.priority.cmp(&other.priority)
+        }
+    }
+
+    impl Borrow<u32> for Job {
+        fn borrow(&self) -> &u32 {
+            &self.priority
+        }
+    }
+
+    crate::intrusive_adapter!(ByLink = Job { link });
+    crate::intrusive_adapter!(ByOther = Job { other });
+
+    fn height<A: Adapter>(tree: &IntrusiveBst<'_, A>) -> usize {
+        let mut height = 0;
+        let mut stack: Vec<_> = tree.root.map(|root| (root, 1)).into_iter().collect();
+        while let Some((ptr, depth)) = stack.pop() {
+            height = height.max(depth);
+            for side in [Side::Left, Side::Right] {
+                if let Some(child) = unsafe { child::<A>(ptr, side) } {
+                    assert_eq!(unsafe { parent::<A>(child) }, Some(ptr));
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        height
+    }
+
+    #[test]
+    fn values_linked_in_place() {
+        let jobs: Vec<Job> = (0..2000)
+            .map(|n| Job {
+                priority: (n * 7919) % 2000,
+                link: TreeLink::new(),
+                other: TreeLink::new(),
+            })
+            .collect();
+        let twin = Job {
+            priority: 5,
+            link: TreeLink::new(),
+            other: TreeLink::new(),
+        };
+        let mut tree = IntrusiveBst::<ByLink>::new();
+        let mut model = BTreeSet::new();
+        // Sorted insertions would make a chain without the rebuilds.
+        let mut sorted: Vec<&Job> = jobs.iter().collect();
+        sorted.sort();
+        for job in &sorted {
+            assert!(tree.insert(job));
+            model.insert(job.priority);
+        }
+        assert!(height(&tree) <= depth_limit(tree.len()) + 1);
+        assert!(!tree.insert(&twin) && !twin.link.is_linked());
+
+        for job in jobs.iter().filter(|job| job.priority % 3 != 0) {
+            assert!(tree.remove(job));
+            assert!(!tree.remove(job));
+            model.remove(&job.priority);
+        }
+        assert!(tree
+            .iter()
+            .map(|job| job.priority)
+            .eq(model.iter().copied()));
+        assert!(tree
+            .iter()
+            .rev()
+            .map(|job| job.priority)
+            .eq(model.iter().rev().copied()));
+        assert_eq!(tree.iter().len(), model.len());
+        assert!(height(&tree) <= depth_limit(tree.max_len) + 1);
+        assert_eq!(tree.get(&9).map(|job| job.priority), Some(9));
+        assert!(tree.get(&10).is_none());
+
+        // The same values can be in a second tree at once, through their other link.
+        let mut evens = IntrusiveBst::<ByOther>::new();
+        for job in jobs.iter().filter(|job| job.priority % 2 == 0) {
+            evens.insert(job);
+        }
+        assert_eq!(evens.len(), 1000);
+        assert!(evens.contains_value(&jobs[0]) && !tree.contains_value(&jobs[1]));
+        drop(evens);
+        assert!(jobs.iter().all(|job| !job.other.is_linked()));
+        tree.clear();
+        assert!(tree.is_empty() && jobs.iter().all(|job| !job.link.is_linked()));
+    }
+
+    #[test]
+    #[should_panic(expected = "already linked")]
+    fn linking_twice() {
+        let job = Job {
+            priority: 1,
+            link: TreeLink::new(),
+            other: TreeLink::new(),
+        };
+        let mut first = IntrusiveBst::<ByLink>::new();
+        let mut second = IntrusiveBst::<ByLink>::new();
+        first.insert(&job);
+        second.insert(&job);
+    }
+}
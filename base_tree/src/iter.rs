@@ -0,0 +1,423 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use crate::tree::{leftmost, predecessor, rightmost, successor};
+use crate::{Augment, DataMut, Node, Order, SizeAug};
+
+type Queue<T, A> = VecDeque<(NonNull<Node<T, A>>, bool)>;
+type Ends<T, A> = (NonNull<Node<T, A>>, NonNull<Node<T, A>>);
+
+/// Storage for the bookkeeping of a traversal, which can be reused across traversals of any
+/// number of trees through [`Node::iter_in`], to allocate only once.
+pub struct TraversalBuffer<T, A: Augment<T> = ()> {
+    // Cleared at the start of every traversal.
+    queue: Queue<T, A>,
+}
+
+impl<T, A: Augment<T>> TraversalBuffer<T, A> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a buffer that traverses trees of up to about `capacity` nodes without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::with_capacity(capacity),
+        }
+    }
+}
+
+impl<T, A: Augment<T>> Default for TraversalBuffer<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Pending<'a, T, A: Augment<T>> {
+    Owned(Queue<T, A>),
+    Borrowed(&'a mut Queue<T, A>),
+}
+
+impl<T, A: Augment<T>> Deref for Pending<'_, T, A> {
+    type Target = Queue<T, A>;
+
+    fn deref(&self) -> &Queue<T, A> {
+        match self {
+            Pending::Owned(queue) => queue,
+            Pending::Borrowed(queue) => queue,
+        }
+    }
+}
+
+impl<T, A: Augment<T>> DerefMut for Pending<'_, T, A> {
+    fn deref_mut(&mut self) -> &mut Queue<T, A> {
+        match self {
+            Pending::Owned(queue) => queue,
+            Pending::Borrowed(queue) => queue,
+        }
+    }
+}
+
+/// An iterator over the nodes of a subtree, returned by [`Node::iter`] and [`Node::iter_in`].
+///
+/// Like every iterator of this crate, it keeps what it iterates over borrowed for as long as it
+/// lives, so the tree can't change underneath it, and it yields nothing more once it is done:
+///
+/// ```compile_fail,E0502
+/// use base_tree::{Node, Order};
+///
+/// let mut root = Node::new(1);
+/// for node in root.iter(Order::In) {
+///     *root.get_mut() += node.get();
+/// }
+/// ```
+pub struct Iter<'a, T, A: Augment<T> = ()> {
+    // Nodes still to visit, used as a stack for all but level order. Post-order also tracks
+    // whether a node's children have been queued; in-order queues a node together with its chain
+    // of left descendants.
+    pending: Pending<'a, T, A>,
+    order: Order,
+    // The number of nodes left, if `A` counts them.
+    remaining: Option<usize>,
+    _marker: PhantomData<&'a Node<T, A>>,
+}
+
+impl<'a, T, A: Augment<T>> Iter<'a, T, A> {
+    pub(crate) fn new(root: &'a Node<T, A>, order: Order) -> Self {
+        Self::start(Pending::Owned(VecDeque::new()), root, order)
+    }
+
+    pub(crate) fn new_in(
+        root: &'a Node<T, A>,
+        order: Order,
+        buffer: &'a mut TraversalBuffer<T, A>,
+    ) -> Self {
+        buffer.queue.clear();
+        Self::start(Pending::Borrowed(&mut buffer.queue), root, order)
+    }
+
+    fn start(pending: Pending<'a, T, A>, root: &'a Node<T, A>, order: Order) -> Self {
+        let mut iter = Self {
+            pending,
+            order,
+            remaining: A::subtree_size(&root.summary),
+            _marker: PhantomData,
+        };
+        match order {
+            Order::In => iter.descend(root),
+            _ => iter.queue(Some(root)),
+        }
+        iter
+    }
+
+    fn descend(&mut self, node: &'a Node<T, A>) {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            self.queue(Some(node));
+            current = node.left();
+        }
+    }
+
+    fn queue(&mut self, node: Option<&'a Node<T, A>>) {
+        self.pending.extend(node.map(|node| (node.into(), false)));
+    }
+}
+
+impl<'a, T, A: Augment<T>> Iterator for Iter<'a, T, A> {
+    type Item = &'a Node<T, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.step()?;
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Every queued node is yet to be yielded.
+        self.remaining
+            .map_or((self.pending.len(), None), |remaining| {
+                (remaining, Some(remaining))
+            })
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T, SizeAug> {}
+
+impl<T, A: Augment<T>> FusedIterator for Iter<'_, T, A> {}
+
+impl<'a, T, A: Augment<T>> Iter<'a, T, A> {
+    fn step(&mut self) -> Option<&'a Node<T, A>> {
+        // Safety: everything queued was borrowed for `'a`.
+        let get = |ptr: NonNull<Node<T, A>>| unsafe { ptr.as_ref() };
+        match self.order {
+            Order::Pre => {
+                let node = get(self.pending.pop_back()?.0);
+                self.queue(node.right());
+                self.queue(node.left());
+                Some(node)
+            }
+            Order::In => {
+                let node = get(self.pending.pop_back()?.0);
+                if let Some(right) = node.right() {
+                    self.descend(right);
+                }
+                Some(node)
+            }
+            Order::Post => loop {
+                let (ptr, expanded) = self.pending.back_mut()?;
+                let node = get(*ptr);
+                if *expanded {
+                    self.pending.pop_back();
+                    return Some(node);
+                }
+                *expanded = true;
+                self.queue(node.right());
+                self.queue(node.left());
+            },
+            Order::Level => {
+                let node = get(self.pending.pop_front()?.0);
+                self.queue(node.left());
+                self.queue(node.right());
+                Some(node)
+            }
+        }
+    }
+}
+
+/// An in-order iterator over a subtree that can also be run backwards, returned by
+/// [`Node::iter_inorder`]. It moves between neighbors through the links of the nodes, so takes no
+/// memory of its own.
+pub struct InOrder<'a, T, A: Augment<T> = ()> {
+    // The next nodes to yield from either end, or `None` once they have met.
+    ends: Option<Ends<T, A>>,
+    remaining: Option<usize>,
+    _marker: PhantomData<&'a Node<T, A>>,
+}
+
+impl<'a, T, A: Augment<T>> InOrder<'a, T, A> {
+    pub(crate) fn new(root: &'a Node<T, A>) -> Self {
+        let remaining = A::subtree_size(&root.summary);
+        let root = NonNull::from(root);
+        // Safety: the subtree is borrowed for `'a`.
+        let ends = unsafe { (leftmost(root), rightmost(root)) };
+        Self {
+            ends: Some(ends),
+            remaining,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Augment<T>> Clone for InOrder<'_, T, A> {
+    fn clone(&self) -> Self {
+        Self {
+            ends: self.ends,
+            remaining: self.remaining,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, A: Augment<T>> Iterator for InOrder<'a, T, A> {
+    type Item = &'a Node<T, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (front, back) = self.ends?;
+        // Safety: the node after `front` is still within the subtree, as `back` comes later.
+        self.ends = (front != back).then(|| (unsafe { successor(front) }.unwrap(), back));
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        Some(unsafe { front.as_ref() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = usize::from(self.ends.is_some());
+        self.remaining
+            .map_or((left, None), |remaining| (remaining, Some(remaining)))
+    }
+}
+
+impl<T, A: Augment<T>> DoubleEndedIterator for InOrder<'_, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (front, back) = self.ends?;
+        // Safety: as above.
+        self.ends = (front != back).then(|| (front, unsafe { predecessor(back) }.unwrap()));
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        Some(unsafe { back.as_ref() })
+    }
+}
+
+impl<T> ExactSizeIterator for InOrder<'_, T, SizeAug> {}
+
+impl<T, A: Augment<T>> FusedIterator for InOrder<'_, T, A> {}
+
+/// An iterator whose items borrow from the iterator itself, so that each has to be let go of
+/// before the next is asked for. Mutable access to the payloads of a tree is handed out this way,
+/// as the nodes are linked to each other.
+pub trait LendingIterator {
+    type Item<'a>
+    where
+        Self: 'a;
+
+    fn next(&mut self) -> Option<Self::Item<'_>>;
+}
+
+/// An in-order traversal of a subtree handing out its payloads mutably, returned by
+/// [`Node::iter_inorder_mut`]. Summaries are brought up to date as each payload is let go of.
+pub struct InOrderMut<'a, T, A: Augment<T> = ()> {
+    ends: Option<Ends<T, A>>,
+    _marker: PhantomData<&'a mut Node<T, A>>,
+}
+
+impl<'a, T, A: Augment<T>> InOrderMut<'a, T, A> {
+    pub(crate) fn new(root: &'a mut Node<T, A>) -> Self {
+        let root = NonNull::from(root);
+        // Safety: the subtree is borrowed mutably for `'a`.
+        let ends = unsafe { (leftmost(root), rightmost(root)) };
+        Self {
+            ends: Some(ends),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Augment<T>> LendingIterator for InOrderMut<'_, T, A> {
+    type Item<'b>
+        = DataMut<'b, T, A>
+    where
+        Self: 'b;
+
+    fn next(&mut self) -> Option<DataMut<'_, T, A>> {
+        let (front, back) = self.ends?;
+        // Safety: the next node is found before handing out this one, whose payload and summaries
+        // are all that can be changed through it, and only until the iterator is used again.
+        self.ends = (front != back).then(|| (unsafe { successor(front) }.unwrap(), back));
+        Some(DataMut {
+            node: unsafe { &mut *front.as_ptr() },
+        })
+    }
+}
+
+/// An iterator over the nodes strictly below a node in pre-order, returned by
+/// [`Node::descendants`].
+pub struct Descendants<'a, T, A: Augment<T> = ()>(Iter<'a, T, A>);
+
+impl<'a, T, A: Augment<T>> Descendants<'a, T, A> {
+    pub(crate) fn new(node: &'a Node<T, A>) -> Self {
+        let mut iter = Iter {
+            pending: Pending::Owned(VecDeque::new()),
+            order: Order::Pre,
+            remaining: A::subtree_size(&node.summary).map(|size| size - 1),
+            _marker: PhantomData,
+        };
+        iter.queue(node.right());
+        iter.queue(node.left());
+        Self(iter)
+    }
+}
+
+impl<'a, T, A: Augment<T>> Iterator for Descendants<'a, T, A> {
+    type Item = &'a Node<T, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for Descendants<'_, T, SizeAug> {}
+
+impl<T, A: Augment<T>> FusedIterator for Descendants<'_, T, A> {}
+
+/// A pre-order iterator over a subtree that gives up below a certain depth, or after a number of
+/// nodes, returned by [`Node::iter_limited`]. It yields each node along with its depth.
+pub struct Limited<'a, T, A: Augment<T> = ()> {
+    stack: Vec<(&'a Node<T, A>, usize)>,
+    max_depth: usize,
+    budget: usize,
+    truncated: bool,
+}
+
+impl<'a, T, A: Augment<T>> Limited<'a, T, A> {
+    pub(crate) fn new(root: &'a Node<T, A>, max_depth: usize, max_nodes: usize) -> Self {
+        Self {
+            stack: vec![(root, 0)],
+            max_depth,
+            budget: max_nodes,
+            truncated: false,
+        }
+    }
+
+    /// Whether any nodes were left out so far, because they were too deep or over budget.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<'a, T, A: Augment<T>> Iterator for Limited<'a, T, A> {
+    type Item = (&'a Node<T, A>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.budget == 0 {
+            self.truncated |= !self.stack.is_empty();
+            return None;
+        }
+        let (node, depth) = self.stack.pop()?;
+        self.budget -= 1;
+        let (left, right) = node.children();
+        if depth < self.max_depth {
+            self.stack.extend(right.map(|child| (child, depth + 1)));
+            self.stack.extend(left.map(|child| (child, depth + 1)));
+        } else {
+            self.truncated |= left.is_some() || right.is_some();
+        }
+        Some((node, depth))
+    }
+}
+
+impl<T, A: Augment<T>> FusedIterator for Limited<'_, T, A> {}
+
+/// Whether a pruned traversal should go on into the children of a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Descend {
+    Yes,
+    No,
+}
+
+/// A pre-order iterator over a subtree that skips the children of nodes it is told not to descend
+/// into, returned by [`Node::iter_pruned`].
+pub struct Pruned<'a, T, A: Augment<T>, F> {
+    stack: Vec<&'a Node<T, A>>,
+    descend: F,
+}
+
+impl<'a, T, A: Augment<T>, F: FnMut(&T) -> Descend> Pruned<'a, T, A, F> {
+    pub(crate) fn new(root: &'a Node<T, A>, descend: F) -> Self {
+        Self {
+            stack: vec![root],
+            descend,
+        }
+    }
+}
+
+impl<'a, T, A: Augment<T>, F: FnMut(&T) -> Descend> Iterator for Pruned<'a, T, A, F> {
+    type Item = &'a Node<T, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if (self.descend)(node.get()) == Descend::Yes {
+            self.stack.extend(node.right());
+            self.stack.extend(node.left());
+        }
+        Some(node)
+    }
+}
+
+impl<T, A: Augment<T>, F: FnMut(&T) -> Descend> FusedIterator for Pruned<'_, T, A, F> {}
@@ -0,0 +1,450 @@
+//! JSON import and export without serde. A tree is written as its root, and every node as
+//! `{"data": ..., "left": ..., "right": ...}`, where a missing child is `null`, and so is the
+//! empty tree. The keys are always written in that order; when reading, any order is accepted,
+//! and a missing `left` or `right` means no child there.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display, Write};
+use core::mem;
+
+use crate::{Node, Tree};
+
+/// Payloads that can be written as the `data` of a node, and read back.
+pub trait JsonData: Sized {
+    fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result;
+
+    /// Reads a payload from the text of a single JSON value, with no surrounding whitespace.
+    fn from_json(raw: &str) -> Option<Self>;
+}
+
+macro_rules! json_via_display {
+    ($($ty:ty),*) => {$(
+        impl JsonData for $ty {
+            fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+                write!(out, "{self}")
+            }
+
+            fn from_json(raw: &str) -> Option<Self> {
+                raw.parse().ok()
+            }
+        }
+    )*};
+}
+
+json_via_display!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+macro_rules! json_float {
+    ($($ty:ty),*) => {$(
+        /// Infinities and NaN, which JSON has no numbers for, are written as `null`, which is read
+        /// back as NaN.
+        impl JsonData for $ty {
+            fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+                if self.is_finite() {
+                    write!(out, "{self}")
+                } else {
+                    out.write_str("null")
+                }
+            }
+
+            fn from_json(raw: &str) -> Option<Self> {
+                if raw == "null" {
+                    return Some(<$ty>::NAN);
+                }
+                // Rust also accepts `inf`, `+1` and the like, which are not JSON numbers.
+                let digits = raw.strip_prefix('-').unwrap_or(raw);
+                digits.starts_with(|c: char| c.is_ascii_digit()).then(|| raw.parse().ok())?
+            }
+        }
+    )*};
+}
+
+json_float!(f32, f64);
+
+impl JsonData for String {
+    fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        out.write_char('"')?;
+        for c in self.chars() {
+            match c {
+                '"' => out.write_str("\\\"")?,
+                '\\' => out.write_str("\\\\")?,
+                '\n' => out.write_str("\\n")?,
+                '\r' => out.write_str("\\r")?,
+                '\t' => out.write_str("\\t")?,
+                c if c < ' ' => write!(out, "\\u{:04x}", c as u32)?,
+                c => out.write_char(c)?,
+            }
+        }
+        out.write_char('"')
+    }
+
+    fn from_json(raw: &str) -> Option<Self> {
+        let mut cursor = Cursor { input: raw, at: 0 };
+        let string = cursor.string().ok()?;
+        (cursor.at == raw.len()).then_some(string)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    /// A character in a place it can't be, at the given byte offset.
+    UnexpectedToken(usize),
+    /// The object starting at the given byte offset has a key other than `data`, `left` and
+    /// `right`, or one of them twice.
+    UnexpectedKey(usize),
+    /// The object starting at the given byte offset has no `data`.
+    MissingData(usize),
+    /// The `data` starting at the given byte offset was refused by [`JsonData::from_json`].
+    InvalidData(usize),
+    UnexpectedEnd,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::UnexpectedToken(at) => write!(f, "unexpected token at offset {at}"),
+            JsonError::UnexpectedKey(at) => write!(f, "unexpected key in node at offset {at}"),
+            JsonError::MissingData(at) => write!(f, "node at offset {at} has no data"),
+            JsonError::InvalidData(at) => write!(f, "invalid data at offset {at}"),
+            JsonError::UnexpectedEnd => write!(f, "unexpected end of JSON"),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+impl<T: JsonData> Tree<T> {
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a `String` can't fail.
+        let _ = self.write_json(&mut json);
+        json
+    }
+
+    /// Writes the tree piece by piece as it is walked, so nothing the size of the output is built
+    /// up in memory.
+    pub fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        enum Work<'a, T> {
+            Node(Option<&'a Node<T>>),
+            Text(&'static str),
+        }
+
+        let mut stack = vec![Work::Node(self.root())];
+        while let Some(work) = stack.pop() {
+            match work {
+                Work::Text(text) => out.write_str(text)?,
+                Work::Node(None) => out.write_str("null")?,
+                Work::Node(Some(node)) => {
+                    out.write_str("{\"data\":")?;
+                    node.get().write_json(out)?;
+                    stack.extend([
+                        Work::Text("}"),
+                        Work::Node(node.right()),
+                        Work::Text(",\"right\":"),
+                        Work::Node(node.left()),
+                        Work::Text(",\"left\":"),
+                    ]);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_json(input: &str) -> Result<Self, JsonError> {
+        struct Open<T> {
+            start: usize,
+            data: Option<T>,
+            left: Option<Tree<T>>,
+            right: Option<Tree<T>>,
+            // Whether no key has been read yet.
+            first: bool,
+            // Which child of its parent the node is, `None` for the root.
+            is_left: Option<bool>,
+        }
+
+        let mut cursor = Cursor { input, at: 0 };
+        let mut open: Vec<Open<T>> = Vec::new();
+        // Whether the next child to open is the left one, `None` for the root or when a key is due.
+        let mut opening = Some(None);
+        let tree = loop {
+            if let Some(is_left) = opening.take() {
+                let start = cursor.skip_whitespace();
+                if cursor.eat_word("null") {
+                    let tree = Tree::new();
+                    match is_left {
+                        None => break tree,
+                        Some(true) => open.last_mut().unwrap().left = Some(tree),
+                        Some(false) => open.last_mut().unwrap().right = Some(tree),
+                    }
+                } else {
+                    cursor.expect(b'{')?;
+                    open.push(Open {
+                        start,
+                        data: None,
+                        left: None,
+                        right: None,
+                        first: true,
+                        is_left,
+                    });
+                }
+            } else {
+                cursor.skip_whitespace();
+                if cursor.peek() == Some(b'}') {
+                    cursor.next();
+                    let node = open.pop().unwrap();
+                    let data = node.data.ok_or(JsonError::MissingData(node.start))?;
+                    let tree = Tree::join(
+                        node.left.unwrap_or_default(),
+                        data,
+                        node.right.unwrap_or_default(),
+                    );
+                    match node.is_left {
+                        None => break tree,
+                        Some(true) => open.last_mut().unwrap().left = Some(tree),
+                        Some(false) => open.last_mut().unwrap().right = Some(tree),
+                    }
+                    continue;
+                }
+                let node = open.last_mut().unwrap();
+                if !mem::take(&mut node.first) {
+                    cursor.expect(b',')?;
+                }
+                cursor.skip_whitespace();
+                let key = cursor.string()?;
+                cursor.skip_whitespace();
+                cursor.expect(b':')?;
+                match key.as_str() {
+                    "data" if node.data.is_none() => {
+                        let start = cursor.skip_whitespace();
+                        cursor.value()?;
+                        let raw = &input[start..cursor.at];
+                        node.data = Some(T::from_json(raw).ok_or(JsonError::InvalidData(start))?);
+                    }
+                    "left" if node.left.is_none() => opening = Some(Some(true)),
+                    "right" if node.right.is_none() => opening = Some(Some(false)),
+                    _ => return Err(JsonError::UnexpectedKey(node.start)),
+                }
+            }
+        };
+
+        match cursor.skip_whitespace() {
+            at if at == input.len() => Ok(tree),
+            at => Err(JsonError::UnexpectedToken(at)),
+        }
+    }
+}
+
+struct Cursor<'a> {
+    input: &'a str,
+    at: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.at += 1;
+        Some(byte)
+    }
+
+    fn error(&self) -> JsonError {
+        if self.at < self.input.len() {
+            JsonError::UnexpectedToken(self.at)
+        } else {
+            JsonError::UnexpectedEnd
+        }
+    }
+
+    /// Returns where the next token starts.
+    fn skip_whitespace(&mut self) -> usize {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.at += 1;
+        }
+        self.at
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error());
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.input[self.at..].starts_with(word);
+        if found {
+            self.at += word.len();
+        }
+        found
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut string = String::new();
+        loop {
+            let rest = &self.input[self.at..];
+            let Some(c) = rest.chars().next() else {
+                return Err(JsonError::UnexpectedEnd);
+            };
+            self.at += c.len_utf8();
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error()),
+                    };
+                    string.push(escaped);
+                }
+                c if c < ' ' => return Err(JsonError::UnexpectedToken(self.at - 1)),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Reads what follows `\u`, including the second half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let start = self.at;
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.eat_word("\\u") {
+                return Err(JsonError::UnexpectedToken(start));
+            }
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(JsonError::UnexpectedToken(start));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or(JsonError::UnexpectedToken(start))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .input
+            .get(self.at..self.at + 4)
+            .ok_or(JsonError::UnexpectedEnd)?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.at += 4;
+        Ok(code)
+    }
+
+    /// Skips over a value, checking only that strings and brackets are closed. What is inside
+    /// is left to [`JsonData::from_json`].
+    fn value(&mut self) -> Result<(), JsonError> {
+        let start = self.at;
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.string()?;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(b'{' | b'[') => {
+                    depth += 1;
+                    self.at += 1;
+                }
+                Some(b'}' | b']') if depth > 0 => {
+                    depth -= 1;
+                    self.at += 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r') | None if depth == 0 => {
+                    return match self.at > start {
+                        true => Ok(()),
+                        false => Err(self.error()),
+                    };
+                }
+                None => return Err(JsonError::UnexpectedEnd),
+                Some(_) => {
+                    self.at += self.input[self.at..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shape;
+
+    #[test]
+    fn round_trip() {
+        let tree = Tree::join(
+            Tree::with_root(String::from("a \"quoted\"\nline")),
+            String::from("root"),
+            Tree::join(
+                Tree::new(),
+                String::from("é\u{1}"),
+                Tree::with_root(String::new()),
+            ),
+        );
+        let json = tree.to_json();
+        assert_eq!(
+            json,
+            r#"{"data":"root","left":{"data":"a \"quoted\"\nline","left":null,"right":null},"right":{"data":"é\u0001","left":null,"right":{"data":"","left":null,"right":null}}}"#
+        );
+        assert!(Tree::<String>::from_json(&json).unwrap() == tree);
+
+        let spaced = r#" { "right" : null , "data" : "🌳\/" ,
+            "left" : { "data": "leaf" } } "#;
+        let tree = Tree::<String>::from_json(spaced).unwrap();
+        assert_eq!(tree.root().unwrap().get(), "🌳/");
+        assert_eq!(tree.root().unwrap().left().unwrap().get(), "leaf");
+        assert!(tree.root().unwrap().right().is_none());
+
+        let numbers = Tree::from_shape([-1.5, 2.0, f64::INFINITY], Shape::Balanced, |_| 0);
+        assert_eq!(
+            numbers.to_json(),
+            r#"{"data":2,"left":{"data":-1.5,"left":null,"right":null},"right":{"data":null,"left":null,"right":null}}"#
+        );
+        assert!(Tree::<f64>::from_json(&numbers.to_json())
+            .unwrap()
+            .root()
+            .unwrap()
+            .right()
+            .unwrap()
+            .get()
+            .is_nan());
+        assert!(Tree::<u8>::from_json(" null ").unwrap().is_empty());
+        assert_eq!(Tree::<u8>::new().to_json(), "null");
+
+        for (input, error) in [
+            ("", JsonError::UnexpectedEnd),
+            ("{}", JsonError::MissingData(0)),
+            (r#"{"left":null}"#, JsonError::MissingData(0)),
+            (r#"{"data":1,"data":2}"#, JsonError::UnexpectedKey(0)),
+            (r#"{"data":1,"up":2}"#, JsonError::UnexpectedKey(0)),
+            (r#"{"data":300}"#, JsonError::InvalidData(8)),
+            (r#"{"data":1 "left":null}"#, JsonError::UnexpectedToken(10)),
+            (r#"{"data":1,"left":{"data":2}"#, JsonError::UnexpectedEnd),
+            (r#"{"data":1} x"#, JsonError::UnexpectedToken(11)),
+        ] {
+            assert_eq!(Tree::<u8>::from_json(input).err(), Some(error), "{input}");
+        }
+    }
+}
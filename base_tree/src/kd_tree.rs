@@ -0,0 +1,188 @@
+//! k-d trees, splitting space along one coordinate axis per level.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+use crate::tree::alloc;
+use crate::{Node, Tree};
+
+/// A static set of `K`-dimensional points with a value each, supporting nearest neighbour and
+/// box queries. The splitting axis of a node follows its depth, so the root splits along the
+/// first coordinate, its children along the second, and so on.
+pub struct KdTree<const K: usize, T> {
+    // Everything in a node's left subtree is at most its coordinate along its axis, and
+    // everything in the right subtree at least that.
+    tree: Tree<([f64; K], T)>,
+    len: usize,
+}
+
+impl<const K: usize, T> KdTree<K, T> {
+    /// Builds a balanced tree by splitting at the median along each axis, in O(n log n).
+    pub fn from_points(points: impl IntoIterator<Item = ([f64; K], T)>) -> Self {
+        assert!(K > 0, "k-d trees need at least one dimension");
+        let mut points: Vec<_> = points.into_iter().collect();
+        let len = points.len();
+
+        // Partition the points in place, recording where each subtree's root ends up.
+        let mut links = Vec::with_capacity(len);
+        let mut stack = vec![(0, len, 0, None)];
+        while let Some((start, end, depth, parent)) = stack.pop() {
+            if start >= end {
+                continue;
+            }
+            let axis = depth % K;
+            let mid = start + (end - start) / 2;
+            points[start..end]
+                .select_nth_unstable_by(mid - start, |(a, _), (b, _): &([f64; K], T)| {
+                    a[axis].total_cmp(&b[axis])
+                });
+            links.push((mid, parent));
+            stack.push((start, mid, depth + 1, Some((mid, true))));
+            stack.push((mid + 1, end, depth + 1, Some((mid, false))));
+        }
+
+        let nodes: Vec<NonNull<Node<_>>> = points.into_iter().map(alloc).collect();
+        for &(index, parent) in &links {
+            let Some((parent, left)) = parent else {
+                continue;
+            };
+            let (node, parent) = (nodes[index], nodes[parent]);
+            // Safety: the nodes were just allocated, and each is linked below one parent.
+            unsafe {
+                (*node.as_ptr()).parent = Some(parent);
+                if left {
+                    (*parent.as_ptr()).left = Some(node);
+                } else {
+                    (*parent.as_ptr()).right = Some(node);
+                }
+            }
+        }
+        let root = links.first().map(|&(index, _)| nodes[index]);
+
+        Self {
+            tree: unsafe { Tree::from_root_ptr(root) },
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The point closest to `target` by Euclidean distance, with its value.
+    pub fn nearest(&self, target: &[f64; K]) -> Option<(&[f64; K], &T)> {
+        let mut best: Option<(f64, &([f64; K], T))> = None;
+        // Subtrees to search, with a lower bound on the squared distance of anything in them.
+        let mut stack = vec![(self.tree.root()?, 0, 0.0)];
+        while let Some((node, depth, bound)) = stack.pop() {
+            if best.is_some_and(|(distance, _)| bound >= distance) {
+                continue;
+            }
+            let entry = node.get();
+            let distance = squared_distance(&entry.0, target);
+            if best.is_none_or(|(best, _)| distance < best) {
+                best = Some((distance, entry));
+            }
+
+            let axis = depth % K;
+            let offset = target[axis] - entry.0[axis];
+            let (near, far) = if offset < 0.0 {
+                (node.left(), node.right())
+            } else {
+                (node.right(), node.left())
+            };
+            if let Some(far) = far {
+                stack.push((far, depth + 1, bound.max(offset * offset)));
+            }
+            if let Some(near) = near {
+                stack.push((near, depth + 1, bound));
+            }
+        }
+        best.map(|(_, (point, value))| (point, value))
+    }
+
+    /// All points within the axis aligned box from `min` to `max`, bounds included.
+    pub fn within(&self, min: &[f64; K], max: &[f64; K]) -> Vec<(&[f64; K], &T)> {
+        let mut found = Vec::new();
+        let mut stack: Vec<_> = self.tree.root().map(|root| (root, 0)).into_iter().collect();
+        while let Some((node, depth)) = stack.pop() {
+            let (point, value) = node.get();
+            if (0..K).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis]) {
+                found.push((point, value));
+            }
+            let axis = depth % K;
+            if let Some(left) = node.left().filter(|_| min[axis] <= point[axis]) {
+                stack.push((left, depth + 1));
+            }
+            if let Some(right) = node.right().filter(|_| point[axis] <= max[axis]) {
+                stack.push((right, depth + 1));
+            }
+        }
+        found
+    }
+}
+
+fn squared_distance<const K: usize>(a: &[f64; K], b: &[f64; K]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut lcg = crate::lcg(7);
+        let mut random = || lcg(1 << 24) as f64 / (1u64 << 24) as f64 * 100.0;
+        let mut points: Vec<_> = (0..500)
+            .map(|i| ([random(), random(), random()], i))
+            .collect();
+        // Plenty of ties along the first axis.
+        for point in points.iter_mut().step_by(3) {
+            point.0[0] = point.0[0].floor();
+        }
+        let tree = KdTree::from_points(points.clone());
+        assert_eq!(tree.len(), 500);
+
+        for _ in 0..100 {
+            let target = [random(), random(), random()];
+            let expected = points
+                .iter()
+                .min_by(|a, b| {
+                    squared_distance(&a.0, &target).total_cmp(&squared_distance(&b.0, &target))
+                })
+                .unwrap();
+            let (point, _) = tree.nearest(&target).unwrap();
+            assert_eq!(
+                squared_distance(point, &target),
+                squared_distance(&expected.0, &target)
+            );
+
+            let (low, high) = (random(), random());
+            let (min, max) = ([low.min(high); 3], [low.max(high), 100.0, 60.0]);
+            let mut found: Vec<_> = tree
+                .within(&min, &max)
+                .into_iter()
+                .map(|(_, &i)| i)
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<_> = points
+                .iter()
+                .filter(|(point, _)| {
+                    (0..3).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis])
+                })
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        assert!(KdTree::<2, ()>::from_points([])
+            .nearest(&[0.0, 0.0])
+            .is_none());
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Augment, Node};
+
+/// The outline of a subtree: the leftmost and rightmost x at every depth, deepest first, so a new
+/// root is a push. Every stored x is off by `shift`, so placing the subtree is a single addition.
+struct Contour {
+    levels: Vec<(f64, f64)>,
+    shift: f64,
+}
+
+impl Contour {
+    /// The x range at `depth` below the subtree's root, which must exist.
+    fn at(&self, depth: usize) -> (f64, f64) {
+        let (min, max) = self.levels[self.levels.len() - 1 - depth];
+        (min + self.shift, max + self.shift)
+    }
+
+    /// Adds a root at x = 0 above the subtree.
+    fn push_root(&mut self) {
+        self.levels.push((-self.shift, -self.shift));
+    }
+}
+
+/// See [`Node::layout`].
+///
+/// Outlines are kept for the deeper subtree and only the shallower one is merged in, which keeps
+/// this linear in the size of the tree.
+pub(crate) fn layout<T, A: Augment<T>>(root: &Node<T, A>) -> Vec<(&Node<T, A>, f64, u32)> {
+    // Pre-order, with the parent of each node and the x offset from it, filled in below.
+    let mut nodes = Vec::new();
+    let mut children: Vec<(Option<usize>, Option<usize>)> = Vec::new();
+    let mut stack = vec![(root, None, false, 0)];
+    while let Some((node, parent, is_left, depth)) = stack.pop() {
+        let index = nodes.len();
+        if let Some(parent) = parent {
+            let (left, right) = &mut children[parent];
+            *if is_left { left } else { right } = Some(index);
+        }
+        nodes.push((node, parent, 0.0_f64, depth));
+        children.push((None, None));
+        if let Some(right) = node.right() {
+            stack.push((right, Some(index), false, depth + 1));
+        }
+        if let Some(left) = node.left() {
+            stack.push((left, Some(index), true, depth + 1));
+        }
+    }
+
+    // Children come after their parents in pre-order, so going backwards sees them first.
+    let mut contours: Vec<Option<Contour>> = (0..nodes.len()).map(|_| None).collect();
+    for index in (0..nodes.len()).rev() {
+        let mut take = |child: Option<usize>| child.map(|child| (child, contours[child].take()));
+        let contour = match (take(children[index].0), take(children[index].1)) {
+            (None, None) => Contour {
+                levels: vec![(0.0, 0.0)],
+                shift: 0.0,
+            },
+            (Some((child, contour)), None) | (None, Some((child, contour))) => {
+                let mut contour = contour.unwrap();
+                let offset = if children[index].0.is_some() {
+                    -0.5
+                } else {
+                    0.5
+                };
+                nodes[child].2 = offset;
+                contour.shift += offset;
+                contour.push_root();
+                contour
+            }
+            (Some((left, left_contour)), Some((right, right_contour))) => {
+                let (mut left_contour, mut right_contour) =
+                    (left_contour.unwrap(), right_contour.unwrap());
+                let common = left_contour.levels.len().min(right_contour.levels.len());
+                let gap = (0..common)
+                    .map(|depth| left_contour.at(depth).1 - right_contour.at(depth).0 + 1.0)
+                    .fold(1.0, f64::max);
+                nodes[left].2 = -gap / 2.0;
+                nodes[right].2 = gap / 2.0;
+                left_contour.shift -= gap / 2.0;
+                right_contour.shift += gap / 2.0;
+
+                let (mut deep, shallow) = if left_contour.levels.len() >= right_contour.levels.len()
+                {
+                    (left_contour, right_contour)
+                } else {
+                    (right_contour, left_contour)
+                };
+                let len = deep.levels.len();
+                for depth in 0..common {
+                    let (min, max) = shallow.at(depth);
+                    let level = &mut deep.levels[len - 1 - depth];
+                    level.0 = level.0.min(min - deep.shift);
+                    level.1 = level.1.max(max - deep.shift);
+                }
+                deep.push_root();
+                deep
+            }
+        };
+        contours[index] = Some(contour);
+    }
+
+    // Turn offsets into positions, parents again coming first.
+    let mut xs: Vec<f64> = Vec::with_capacity(nodes.len());
+    for &(_, parent, offset, _) in &nodes {
+        xs.push(parent.map_or(0.0, |parent| xs[parent]) + offset);
+    }
+    let leftmost = xs.iter().copied().fold(f64::INFINITY, f64::min);
+    nodes
+        .into_iter()
+        .zip(xs)
+        .map(|((node, _, _, depth), x)| (node, x - leftmost, depth))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tree;
+
+    #[test]
+    fn tidy_coordinates() {
+        let tree = Tree::join(
+            Tree::join(
+                Tree::with_root(0),
+                1,
+                Tree::join(Tree::new(), 2, Tree::with_root(3)),
+            ),
+            4,
+            Tree::join(Tree::new(), 5, Tree::with_root(6)),
+        );
+        let root = tree.root().unwrap();
+        let placed: Vec<(i32, f64, u32)> = root
+            .layout()
+            .map(|(node, x, depth)| (*node.get(), x, depth))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                (4, 1.0, 0),
+                (1, 0.5, 1),
+                (0, 0.0, 2),
+                (2, 1.0, 2),
+                (3, 1.5, 3),
+                (5, 1.5, 1),
+                (6, 2.0, 2),
+            ]
+        );
+
+        // Nodes on a level keep their order, a unit or more apart.
+        for level in 0..4 {
+            let xs: Vec<f64> = placed
+                .iter()
+                .filter(|p| p.2 == level)
+                .map(|p| p.1)
+                .collect();
+            assert!(xs.windows(2).all(|pair| pair[1] - pair[0] >= 1.0), "{xs:?}");
+        }
+        assert_eq!(
+            Tree::with_root(())
+                .root()
+                .unwrap()
+                .layout()
+                .next()
+                .unwrap()
+                .1,
+            0.0
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! An ordered map whose removals leave tombstones behind, to be cleared out in bulk.
+
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+
+use crate::bst::{BstMap, Entry};
+
+/// An ordered map, kept as a [`BstMap`], where [`LazyBstMap::remove`] only marks the entry as
+/// removed. The tree is never restructured by a removal, which makes delete-heavy workloads
+/// cheaper, and the tombstones are dropped together by [`LazyBstMap::compact`] in O(n).
+/// Inserting under the key of a tombstone brings the entry back to life in place.
+pub struct LazyBstMap<K, V> {
+    map: BstMap<K, Option<V>>,
+    tombstones: usize,
+}
+
+impl<K, V> LazyBstMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            map: BstMap::new(),
+            tombstones: 0,
+        }
+    }
+
+    /// The number of live entries.
+    pub fn len(&self) -> usize {
+        self.map.len() - self.tombstones
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of removed entries still taking up a node.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones
+    }
+
+    /// Iterates over the live entries in key order, stepping over the tombstones.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        self.map
+            .iter()
+            .filter_map(|(key, value)| Some((key, value.as_ref()?)))
+    }
+
+    /// Rebuilds the tree without its tombstones, perfectly balanced.
+    pub fn compact(&mut self) {
+        self.map.rebuild_keeping(|_, value| value.is_some());
+        self.tombstones = 0;
+    }
+}
+
+impl<K: Ord, V> LazyBstMap<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key)?.as_ref()
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get_mut(key)?.as_mut()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `value` under `key`, returning the live value it replaces, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let old = entry.get_mut().replace(value);
+                if old.is_none() {
+                    self.tombstones -= 1;
+                }
+                old
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Some(value));
+                None
+            }
+        }
+    }
+
+    /// Takes the value out from under `key`, leaving a tombstone.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let value = self.map.get_mut(key)?.take()?;
+        self.tombstones += 1;
+        Some(value)
+    }
+}
+
+impl<K, V> Default for LazyBstMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for LazyBstMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for LazyBstMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for LazyBstMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = Self::new();
+        map.extend(entries);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::random_updates;
+
+    #[test]
+    fn tombstones_and_compaction() {
+        let mut map = LazyBstMap::new();
+        let model = random_updates(&mut map, 5, 4000, 400, |_| ());
+        assert!(map.tombstones() > 0);
+        assert!(map.iter().eq(model.iter()));
+        assert!(map.iter().rev().eq(model.iter().rev()));
+
+        let rebuilds = map.map.rebuilds();
+        map.compact();
+        assert_eq!(map.map.rebuilds(), rebuilds + 1);
+        assert_eq!((map.tombstones(), map.map.len()), (0, model.len()));
+        assert!(map.iter().eq(model.iter()));
+        for key in 0..400 {
+            assert_eq!(map.get(&key), model.get(&key));
+        }
+        let levels = map.map.stats().levels.len();
+        assert_eq!(levels, (usize::BITS - model.len().leading_zeros()) as usize);
+    }
+}
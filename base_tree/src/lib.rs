@@ -1,39 +1,420 @@
-use std::ptr::{self, NonNull};
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
-pub struct Node<T> {
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+use core::ptr::{self, NonNull};
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{self, AtomicU64};
+
+#[cfg(feature = "bst")]
+pub mod aa_tree;
+mod augment;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod bit_trie;
+#[cfg(feature = "branded")]
+mod branded;
+#[cfg(feature = "bst")]
+pub mod bst;
+#[cfg(feature = "compressed")]
+pub mod compressed;
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+#[cfg(feature = "bst")]
+mod counter;
+pub mod cow_tree;
+#[cfg(feature = "std")]
+pub mod dedup;
+mod display;
+mod error;
+pub mod expr;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod huffman;
+#[cfg(feature = "bst")]
+pub mod intrusive;
+mod iter;
+pub mod json;
+pub mod kd_tree;
+mod layout;
+#[cfg(feature = "bst")]
+pub mod lazy_map;
+pub mod link_cut;
+mod memory;
+pub mod model;
+#[cfg(feature = "bst")]
+pub mod multimap;
+pub mod newick;
+#[cfg(feature = "ids")]
+mod overlay;
+mod pinned;
+pub mod priority_search_tree;
+pub mod rope;
+mod scope;
+pub mod segment_tree;
+mod slab;
+mod static_node;
+mod stats;
+#[cfg(feature = "svg")]
+pub mod svg;
+#[cfg(feature = "sync")]
+pub mod sync_tree;
+#[cfg(feature = "tracing")]
+pub mod trace;
+mod tree;
+#[cfg(feature = "bst")]
+pub mod tree_set;
+pub mod xml;
+
+pub use augment::{Augment, DataMut, EulerAug, EulerNumbers, SizeAug};
+#[cfg(feature = "branded")]
+pub use branded::{BrandedNode, TreeToken};
+#[cfg(feature = "bst")]
+pub use bst::{BstMap, WeightBalancedMap};
+#[cfg(feature = "bst")]
+pub use counter::BstCounter;
+pub use display::Breadcrumbs;
+pub use error::{EdgeError, TreeError};
+pub use iter::{
+    Descend, Descendants, InOrder, InOrderMut, Iter, LendingIterator, Limited, Pruned,
+    TraversalBuffer,
+};
+#[cfg(feature = "bst")]
+pub use lazy_map::LazyBstMap;
+pub use memory::{HeapSize, MemoryReport};
+#[cfg(feature = "bst")]
+pub use multimap::BstMultiMap;
+#[cfg(feature = "ids")]
+pub use overlay::{NodeId, NodeRef, Overlay};
+pub use pinned::PinnedNode;
+pub use scope::{Scope, ScopedNode};
+pub use slab::NodeSlab;
+pub use static_node::StaticNode;
+pub use stats::TreeStats;
+pub use tree::{Drain, Mismatch, Order, Shape, Tree};
+#[cfg(feature = "bst")]
+pub use tree_set::TreeSet;
+
+// Trees may be arbitrarily deep, so nothing here walks one recursively: traversals keep an
+// explicit stack or follow parent pointers, and `tree::tests::degenerate_chains` runs them over
+// chains of a million nodes.
+/// A node of a binary tree, linked to its children and its parent by address.
+///
+/// A linked node must stay where it is, which borrowing alone only ensures while the borrow lasts:
+/// afterwards, moving the node (say with `mem::swap`) leaves its neighbors pointing at the old
+/// place, and nothing here stops that. Trees whose links the compiler keeps valid are built from
+/// [`PinnedNode`]s instead.
+pub struct Node<T, A: Augment<T> = ()> {
     data: T,
+    summary: A::Summary,
+    // Zero until `id` is first called.
+    #[cfg(feature = "ids")]
+    id: AtomicU64,
 
     // These three ptr::NonNull _always_ has to be dereferentiable, and must not be accessible from
     // outside the structure (aka, be created from mutable references)
-    left: Option<ptr::NonNull<Node<T>>>,
-    right: Option<ptr::NonNull<Node<T>>>,
-    parent: Option<ptr::NonNull<Node<T>>>,
+    left: Option<ptr::NonNull<Node<T, A>>>,
+    right: Option<ptr::NonNull<Node<T, A>>>,
+    parent: Option<ptr::NonNull<Node<T, A>>>,
 }
 
 // This implementation keeps the invariant that a mutable reference to a node, means exclusive access to its children and parent, if present.
 impl<T> Node<T> {
-    pub fn new(data: T) -> Self {
+    pub const fn new(data: T) -> Self {
         Self {
             data,
+            summary: (),
+            #[cfg(feature = "ids")]
+            id: AtomicU64::new(0),
             parent: None,
             left: None,
             right: None,
         }
     }
 
+    /// Mutable references to the payloads of the two nodes at `a` and `b` below this one, unless
+    /// either path leads nowhere, or one of the nodes is an ancestor of (or the same as) the other.
+    /// Only payloads are handed out, as references to the nodes themselves would reach each other
+    /// through their parents.
+    pub fn get_disjoint_mut(&mut self, a: &[Side], b: &[Side]) -> Option<(&mut T, &mut T)> {
+        // Distinct nodes are at distinct paths, and ancestors at their prefixes.
+        if a.starts_with(b) || b.starts_with(a) {
+            return None;
+        }
+        let root = NonNull::from(self);
+        let (a, b) = (Self::at_path_raw(root, a)?, Self::at_path_raw(root, b)?);
+        // Safety: the nodes are distinct parts of the subtree we have exclusive access to.
+        unsafe { Some((&mut (*a.as_ptr()).data, &mut (*b.as_ptr()).data)) }
+    }
+}
+
+impl<T, A: Augment<T>> Node<T, A> {
+    /// Creates a node keeping the bookkeeping described by `A`, as in
+    /// `Node::<_, SizeAug>::augmented(data)`.
+    pub fn augmented(data: T) -> Self {
+        Self {
+            summary: A::combine(None, &data, None),
+            data,
+            #[cfg(feature = "ids")]
+            id: AtomicU64::new(0),
+            parent: None,
+            left: None,
+            right: None,
+        }
+    }
+
+    pub fn summary(&self) -> &A::Summary {
+        &self.summary
+    }
+
+    /// The identity of this node, which no other node shares.
+    #[cfg(feature = "ids")]
+    pub fn id(&self) -> NodeId {
+        NodeId::of(&self.id)
+    }
+
+    /// A handle to this node that does not borrow it.
+    #[cfg(feature = "ids")]
+    pub fn handle(&self) -> NodeRef<T> {
+        NodeRef::new(self.id())
+    }
+
+    /// Hangs `new_child` below this node on the right, returning the child it replaces, cut
+    /// loose. A `new_child` still below a parent is detached from it first.
+    ///
+    /// # Panics
+    ///
+    /// If `new_child` is this node or one of its ancestors, which would make the tree a cycle.
     pub fn replace_right<'a>(
         &'a mut self,
         new_child: Option<&'a mut Self>,
     ) -> Option<&'a mut Self> {
+        self.assert_acyclic(&new_child);
+        unsafe { self.replace_right_unchecked(new_child) }
+    }
+
+    /// # Panics
+    ///
+    /// See [`Node::replace_right`].
+    pub fn replace_left<'a>(&'a mut self, new_child: Option<&'a mut Self>) -> Option<&'a mut Self> {
+        self.assert_acyclic(&new_child);
+        unsafe { self.replace_left_unchecked(new_child) }
+    }
+
+    /// [`Node::replace_right`] without walking up the ancestors to look for `new_child`.
+    ///
+    /// # Safety
+    ///
+    /// `new_child` must not be this node or one of its ancestors.
+    pub unsafe fn replace_right_unchecked<'a>(
+        &'a mut self,
+        mut new_child: Option<&'a mut Self>,
+    ) -> Option<&'a mut Self> {
+        if let Some(child) = &mut new_child {
+            child.detach_from_parent();
+        }
+        #[cfg(feature = "tracing")]
+        if let Some(old_child) = self.right {
+            trace::emit(old_child, trace::Mutation::Detach);
+        }
         let self_ref = self.into();
         let child = &mut self.right;
-        unsafe { Self::replace_child_helper(self_ref, child, new_child) }
+        let old_child = unsafe { Self::replace_child_helper(self_ref, child, new_child) };
+        #[cfg(feature = "tracing")]
+        if let Some(new_child) = self.right {
+            trace::emit(new_child, trace::Mutation::Attach);
+        }
+        unsafe { Self::refresh_upwards(self_ref) };
+        old_child
     }
 
-    pub fn replace_left<'a>(&'a mut self, new_child: Option<&'a mut Self>) -> Option<&'a mut Self> {
+    /// [`Node::replace_left`] without walking up the ancestors to look for `new_child`.
+    ///
+    /// # Safety
+    ///
+    /// See [`Node::replace_right_unchecked`].
+    pub unsafe fn replace_left_unchecked<'a>(
+        &'a mut self,
+        mut new_child: Option<&'a mut Self>,
+    ) -> Option<&'a mut Self> {
+        if let Some(child) = &mut new_child {
+            child.detach_from_parent();
+        }
+        #[cfg(feature = "tracing")]
+        if let Some(old_child) = self.left {
+            trace::emit(old_child, trace::Mutation::Detach);
+        }
         let self_ref = self.into();
         let child = &mut self.left;
-        unsafe { Self::replace_child_helper(self_ref, child, new_child) }
+        let old_child = unsafe { Self::replace_child_helper(self_ref, child, new_child) };
+        #[cfg(feature = "tracing")]
+        if let Some(new_child) = self.left {
+            trace::emit(new_child, trace::Mutation::Attach);
+        }
+        unsafe { Self::refresh_upwards(self_ref) };
+        old_child
+    }
+
+    /// Hangs `new_child` below this node on `side` like [`Node::replace_right`] does, returning
+    /// the child it replaces, but refuses a `new_child` that is still below a parent rather than
+    /// detaching it.
+    pub fn try_replace<'a>(
+        &'a mut self,
+        side: Side,
+        new_child: Option<&'a mut Self>,
+    ) -> Result<Option<&'a mut Self>, TreeError> {
+        if let Some(child) = &new_child {
+            if child.parent.is_some() {
+                return Err(TreeError::DifferentTrees);
+            }
+            if self.has_ancestor_or_self(child) {
+                return Err(TreeError::WouldCreateCycle);
+            }
+        }
+        // Safety: just checked.
+        Ok(unsafe {
+            match side {
+                Side::Left => self.replace_left_unchecked(new_child),
+                Side::Right => self.replace_right_unchecked(new_child),
+            }
+        })
+    }
+
+    /// Takes this node out of the slot of its parent, if it has one, so that no other node
+    /// refers to it once it is linked elsewhere.
+    fn detach_from_parent(&mut self) {
+        let Some(parent) = self.parent.take() else {
+            return;
+        };
+        let ptr = NonNull::from(&*self);
+        #[cfg(feature = "tracing")]
+        trace::emit(ptr, trace::Mutation::Detach);
+        // Safety: a mutable reference to a node means exclusive access to its parent, and the
+        // reference to this node is not used to reach it.
+        unsafe {
+            let parent_node = &mut *parent.as_ptr();
+            if parent_node.left == Some(ptr) {
+                parent_node.left = None;
+            } else {
+                parent_node.right = None;
+            }
+            Self::refresh_upwards(parent);
+        }
+    }
+
+    fn assert_acyclic(&self, new_child: &Option<&mut Self>) {
+        if let Some(child) = new_child {
+            assert!(
+                !self.has_ancestor_or_self(child),
+                "attaching an ancestor of a node below it would create a cycle"
+            );
+        }
+    }
+
+    fn has_ancestor_or_self(&self, node: &Self) -> bool {
+        let mut current = Some(self);
+        while let Some(ancestor) = current {
+            if ptr::eq(ancestor, node) {
+                return true;
+            }
+            current = ancestor.parent();
+        }
+        false
+    }
+
+    /// Hangs `child`, the root of a tree of its own, below this node on `side`.
+    pub fn try_attach<'a>(&'a mut self, side: Side, child: &'a mut Self) -> Result<(), TreeError> {
+        if self.link(side).is_some() {
+            return Err(TreeError::SlotOccupied);
+        }
+        if child.parent.is_some() {
+            return Err(TreeError::DifferentTrees);
+        }
+        if self.has_ancestor_or_self(child) {
+            return Err(TreeError::WouldCreateCycle);
+        }
+        // Safety: just checked.
+        unsafe {
+            match side {
+                Side::Left => self.replace_left_unchecked(Some(child)),
+                Side::Right => self.replace_right_unchecked(Some(child)),
+            };
+        }
+        Ok(())
+    }
+
+    /// Attaches `subtree` at `path` below this node, as with [`Node::try_attach`] on the parent
+    /// of the empty slot the path leads to.
+    pub fn try_graft<'a>(
+        &'a mut self,
+        path: &[Side],
+        subtree: &'a mut Self,
+    ) -> Result<(), TreeError> {
+        let (&side, above) = path.split_last().ok_or(TreeError::SlotOccupied)?;
+        let mut parent = Self::at_path_raw(self.into(), above).ok_or(TreeError::PathOutOfBounds)?;
+        // Safety: the parent is part of the subtree we have exclusive access to.
+        unsafe { parent.as_mut() }.try_attach(side, subtree)
+    }
+
+    /// Detaches the subtree at `path` below this node, returning its root.
+    pub fn try_detach(&mut self, path: &[Side]) -> Result<&mut Self, TreeError> {
+        let (&side, above) = path.split_last().ok_or(TreeError::NotAChild)?;
+        let mut parent = Self::at_path_raw(self.into(), above).ok_or(TreeError::PathOutOfBounds)?;
+        // Safety: as above.
+        let parent = unsafe { parent.as_mut() };
+        let child = match side {
+            Side::Left => parent.replace_left(None),
+            Side::Right => parent.replace_right(None),
+        };
+        child.ok_or(TreeError::NotAChild)
+    }
+
+    /// Swaps the subtrees at paths `a` and `b` below this node, neither of which may lie within
+    /// the other.
+    pub fn try_swap(&mut self, a: &[Side], b: &[Side]) -> Result<(), TreeError> {
+        if a.starts_with(b) || b.starts_with(a) {
+            return Err(TreeError::WouldCreateCycle);
+        }
+        let root = NonNull::from(self);
+        let find = |path: &[Side]| {
+            let (&side, above) = path.split_last().ok_or(TreeError::NotAChild)?;
+            let parent = Self::at_path_raw(root, above).ok_or(TreeError::PathOutOfBounds)?;
+            let child = unsafe { parent.as_ref() }
+                .link(side)
+                .ok_or(TreeError::NotAChild)?;
+            Ok((parent, side, child))
+        };
+        let ((parent_a, side_a, a), (parent_b, side_b, b)) = (find(a)?, find(b)?);
+        // Safety: the subtrees are disjoint parts of the subtree we have exclusive access to.
+        unsafe {
+            *(*parent_a.as_ptr()).link_mut(side_a) = Some(b);
+            *(*parent_b.as_ptr()).link_mut(side_b) = Some(a);
+            (*a.as_ptr()).parent = Some(parent_b);
+            (*b.as_ptr()).parent = Some(parent_a);
+            Self::refresh_upwards(parent_a);
+            Self::refresh_upwards(parent_b);
+        }
+        Ok(())
+    }
+
+    fn link(&self, side: Side) -> Option<NonNull<Self>> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    fn link_mut(&mut self, side: Side) -> &mut Option<NonNull<Self>> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
     }
 
     /// # Safety
@@ -42,7 +423,7 @@ impl<T> Node<T> {
     unsafe fn replace_child_helper<'a>(
         parent: NonNull<Self>,
         old_child_ref: &mut Option<ptr::NonNull<Self>>,
-        new_child: Option<&'a mut Self>,
+        mut new_child: Option<&'a mut Self>,
     ) -> Option<&'a mut Self> {
         // Clear parent
         let old_child = old_child_ref.take().map(|mut ptr| {
@@ -53,10 +434,9 @@ impl<T> Node<T> {
         });
 
         // Miri does not like this for some reason
-        let new_child = new_child.map(|nc| {
+        if let Some(nc) = &mut new_child {
             nc.parent = Some(parent);
-            nc
-        });
+        }
 
         *old_child_ref = new_child.map(|ptr| ptr.into());
 
@@ -67,8 +447,67 @@ impl<T> Node<T> {
         &self.data
     }
 
-    pub fn get_mut(&mut self) -> &mut T {
-        &mut self.data
+    pub fn get_mut(&mut self) -> DataMut<'_, T, A> {
+        DataMut { node: self }
+    }
+
+    /// Replaces the payload, returning the old one.
+    pub fn replace_data(&mut self, data: T) -> T {
+        mem::replace(&mut *self.get_mut(), data)
+    }
+
+    /// Moves the payload out, leaving `T::default()` in its place.
+    pub fn take_data(&mut self) -> T
+    where
+        T: Default,
+    {
+        self.replace_data(T::default())
+    }
+
+    /// Replaces every payload in the subtree rooted at this node with what `f` makes of it, in
+    /// pre-order, keeping the nodes where they are. Summaries are brought up to date afterwards.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the process is aborted, since the payload it was handed is gone and nothing
+    /// can be left in its place.
+    pub fn map_in_place(&mut self, mut f: impl FnMut(T) -> T) {
+        struct Abort;
+        impl Drop for Abort {
+            fn drop(&mut self) {
+                // Panicking again while unwinding aborts.
+                panic!("map_in_place: the mapping panicked");
+            }
+        }
+
+        let root = NonNull::from(&mut *self);
+        let mut stack = vec![root];
+        let guard = Abort;
+        while let Some(ptr) = stack.pop() {
+            // Safety: we have exclusive access to the subtree, and the payload read out is written
+            // back before anything else can see it, or never if `f` unwinds past the guard.
+            unsafe {
+                let data = &mut (*ptr.as_ptr()).data;
+                ptr::write(data, f(ptr::read(data)));
+                let node = ptr.as_ref();
+                stack.extend(node.right);
+                stack.extend(node.left);
+            }
+        }
+        mem::forget(guard);
+        unsafe { Self::refresh_subtree(root) };
+    }
+
+    /// Swaps payloads with `other`, which may be part of another tree, like the subtrees handed
+    /// out by [`Node::split_mut`].
+    pub fn swap_data(&mut self, other: &mut Self) {
+        mem::swap(&mut self.data, &mut other.data);
+        // Safety: both references are exclusive, so they are to distinct nodes, and cover their
+        // ancestors.
+        unsafe {
+            Self::refresh_upwards(self.into());
+            Self::refresh_upwards(other.into());
+        }
     }
 
     pub fn left(&self) -> Option<&Self> {
@@ -79,6 +518,154 @@ impl<T> Node<T> {
         self.right.map(|ptr| unsafe { ptr.as_ref() })
     }
 
+    /// Iterates over this node and all of its descendants in `order`.
+    pub fn iter(&self, order: Order) -> Iter<'_, T, A> {
+        Iter::new(self, order)
+    }
+
+    /// Like [`Node::iter`], keeping track of the traversal in `buffer` rather than allocating.
+    pub fn iter_in<'a>(
+        &'a self,
+        order: Order,
+        buffer: &'a mut TraversalBuffer<T, A>,
+    ) -> Iter<'a, T, A> {
+        Iter::new_in(self, order, buffer)
+    }
+
+    /// Iterates over this subtree in in-order, keeping track of the traversal in `buffer`.
+    pub fn iter_inorder_in<'a>(&'a self, buffer: &'a mut TraversalBuffer<T, A>) -> Iter<'a, T, A> {
+        self.iter_in(Order::In, buffer)
+    }
+
+    /// Iterates over this subtree in in-order, from either end, without allocating: `.rev()`
+    /// starts from the rightmost node.
+    pub fn iter_inorder(&self) -> InOrder<'_, T, A> {
+        InOrder::new(self)
+    }
+
+    /// Visits the payloads of this subtree in in-order, handing out each mutably in turn:
+    ///
+    /// ```text
+    /// let mut payloads = root.iter_inorder_mut();
+    /// while let Some(mut payload) = payloads.next() {
+    ///     *payload += 1;
+    /// }
+    /// ```
+    pub fn iter_inorder_mut(&mut self) -> InOrderMut<'_, T, A> {
+        InOrderMut::new(self)
+    }
+
+    /// Iterates over this subtree in pre-order, along with the depth of each node below this one,
+    /// yielding nothing deeper than `max_depth`, and no more than `max_nodes` nodes. Whether
+    /// anything was left out can then be asked of the iterator.
+    pub fn iter_limited(&self, max_depth: usize, max_nodes: usize) -> Limited<'_, T, A> {
+        Limited::new(self, max_depth, max_nodes)
+    }
+
+    /// Iterates over this subtree in pre-order, skipping everything below the nodes for which
+    /// `descend` says not to go on. Those nodes themselves are still yielded.
+    pub fn iter_pruned<F: FnMut(&T) -> Descend>(&self, descend: F) -> Pruned<'_, T, A, F> {
+        Pruned::new(self, descend)
+    }
+
+    /// Clones the payloads of this subtree into a `Vec` in `order`, allocated once if `A` keeps
+    /// count of the nodes.
+    pub fn to_vec(&self, order: Order) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut payloads = Vec::with_capacity(A::subtree_size(&self.summary).unwrap_or(0));
+        payloads.extend(self.iter(order).map(|node| node.data.clone()));
+        payloads
+    }
+
+    /// Like [`Node::to_vec`], collecting references to the payloads.
+    pub fn to_ref_vec(&self, order: Order) -> Vec<&T> {
+        let mut payloads = Vec::with_capacity(A::subtree_size(&self.summary).unwrap_or(0));
+        payloads.extend(self.iter(order).map(Node::get));
+        payloads
+    }
+
+    /// Iterates over the descendants of this node, excluding itself, in pre-order.
+    pub fn descendants(&self) -> Descendants<'_, T, A> {
+        Descendants::new(self)
+    }
+
+    /// Both children at once, left first.
+    pub fn children(&self) -> (Option<&Self>, Option<&Self>) {
+        (self.left(), self.right())
+    }
+
+    /// The node reached by following `path` down from this one.
+    pub fn at_path(&self, path: &[Side]) -> Option<&Self> {
+        Self::at_path_raw(self.into(), path).map(|ptr| unsafe { ptr.as_ref() })
+    }
+
+    pub fn at_path_mut(&mut self, path: &[Side]) -> Option<&mut Self> {
+        Self::at_path_raw(self.into(), path).map(|mut ptr| unsafe { ptr.as_mut() })
+    }
+
+    fn at_path_raw(mut ptr: NonNull<Self>, path: &[Side]) -> Option<NonNull<Self>> {
+        for side in path {
+            let node = unsafe { ptr.as_ref() };
+            ptr = match side {
+                Side::Left => node.left?,
+                Side::Right => node.right?,
+            };
+        }
+        Some(ptr)
+    }
+
+    /// Deep-copies the payloads of the subtree rooted at this node into a new tree of the same
+    /// shape.
+    pub fn clone_subtree(&self) -> Tree<T>
+    where
+        T: Clone,
+    {
+        let mut root = None;
+        let mut stack = vec![(self, None::<(NonNull<Node<T>>, Side)>)];
+        while let Some((node, parent)) = stack.pop() {
+            let ptr = tree::alloc(node.data.clone());
+            // Safety: the new nodes belong to nobody else until handed to the tree.
+            match parent {
+                None => root = Some(ptr),
+                Some((parent, side)) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            stack.extend(node.right().map(|right| (right, Some((ptr, Side::Right)))));
+            stack.extend(node.left().map(|left| (left, Some((ptr, Side::Left)))));
+        }
+        unsafe { Tree::from_root_ptr(root) }
+    }
+
+    /// Grafts a copy of the subtree rooted at this node into `dest` at `path`, as with
+    /// [`Tree::graft`].
+    pub fn clone_subtree_into(&self, dest: &mut Tree<T>, path: &[Side]) -> Result<(), TreeError>
+    where
+        T: Clone,
+    {
+        dest.graft(path, self.clone_subtree())
+    }
+
+    /// Displays the path from the root down to this node, with `format` writing each payload, as
+    /// in `node.breadcrumbs(|data, f| write!(f, "{}", data.name))`.
+    pub fn breadcrumbs<F>(&self, format: F) -> Breadcrumbs<'_, T, A, F>
+    where
+        F: Fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+    {
+        Breadcrumbs::new(self, format)
+    }
+
+    /// Computes a tidy drawing of the tree below this node, in the manner of Reingold and Tilford:
+    /// every parent is centred over its children, a lone child hangs half a unit off to its side,
+    /// and siblings' subtrees are pushed apart until they are at least one unit apart on every
+    /// level. Yields the nodes in pre-order with their x, the leftmost being 0, and their depth.
+    pub fn layout(&self) -> impl Iterator<Item = (&Self, f64, u32)> {
+        layout::layout(self).into_iter()
+    }
+
     pub fn parent(&self) -> Option<&Self> {
         self.parent.map(|ptr| unsafe { ptr.as_ref() })
     }
@@ -107,9 +694,457 @@ impl<T> Node<T> {
 
         let left = self.left.take().map(remove_parent);
         let right = self.right.take().map(remove_parent);
+        unsafe { Self::refresh_upwards(self.into()) };
 
         (left, self, right)
     }
+
+    /// Rotates the subtree rooted at this node to the left, making the right child its new root.
+    /// Returns the new subtree root, or `None` (leaving the tree untouched) if there is no right
+    /// child. If this node has a parent, the parent is relinked to the new subtree root.
+    pub fn rotate_left(&mut self) -> Option<&mut Self> {
+        unsafe { Self::rotate_left_raw(self.into()).map(|mut ptr| ptr.as_mut()) }
+    }
+
+    /// Mirror image of [`Node::rotate_left`], making the left child the new subtree root.
+    pub fn rotate_right(&mut self) -> Option<&mut Self> {
+        unsafe { Self::rotate_right_raw(self.into()).map(|mut ptr| ptr.as_mut()) }
+    }
+
+    /// Rebuilds the subtree rooted at this node into a perfectly balanced shape using the
+    /// Day-Stout-Warren algorithm, keeping the in-order sequence of nodes. Everything is done in
+    /// place through rotations, and a parent of this node is relinked to the new subtree root,
+    /// which is returned.
+    pub fn balance(&mut self) -> &mut Self {
+        #[cfg(feature = "tracing")]
+        trace::emit(
+            NonNull::from(&*self),
+            trace::Mutation::Rebalance(self.descendants().count() + 1),
+        );
+        let mut root = NonNull::from(self);
+
+        // Turn the subtree into a vine leaning to the right.
+        let mut size = 0;
+        let mut current = Some(root);
+        while let Some(ptr) = current {
+            // Safety: we have exclusive access to the subtree, and through the root to its parent.
+            if let Some(pivot) = unsafe { Self::rotate_right_raw(ptr) } {
+                if ptr == root {
+                    root = pivot;
+                }
+                current = Some(pivot);
+            } else {
+                size += 1;
+                current = unsafe { ptr.as_ref() }.right;
+            }
+        }
+
+        // Fold the vine back up, one level per pass, starting with the leftover bottom level.
+        let full = (size + 1usize).next_power_of_two() / 2 - 1;
+        let mut compress = |count: usize| {
+            let mut current = Some(root);
+            for i in 0..count {
+                let Some(pivot) = current.and_then(|ptr| unsafe { Self::rotate_left_raw(ptr) })
+                else {
+                    break;
+                };
+                if i == 0 {
+                    root = pivot;
+                }
+                current = unsafe { pivot.as_ref() }.right;
+            }
+        };
+        compress(size - full);
+        let mut level = full;
+        while level > 1 {
+            level /= 2;
+            compress(level);
+        }
+
+        unsafe { root.as_mut() }
+    }
+
+    /// Rewires the subtree rooted at this node into a chain of right children, in pre-order.
+    pub fn flatten_preorder(&mut self) {
+        let root = NonNull::from(self);
+        let mut current = Some(root);
+        while let Some(ptr) = current {
+            // Safety: we have exclusive access to the subtree, and only ever hold a reference to
+            // one node of it at a time.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            if let Some(left) = node.left.take() {
+                // Hang the right subtree below the last pre-order node of the left one.
+                let mut last = left;
+                while let Some(right) = unsafe { last.as_ref() }.right {
+                    last = right;
+                }
+                if let Some(right) = node.right {
+                    unsafe { (*right.as_ptr()).parent = Some(last) };
+                }
+                unsafe { (*last.as_ptr()).right = node.right };
+                node.right = Some(left);
+            }
+            current = node.right;
+        }
+        unsafe { Self::refresh_subtree(root) };
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be dereferentiable, and the caller must have exclusive access to it, its parent
+    /// and its subtree.
+    unsafe fn rotate_left_raw(ptr: NonNull<Self>) -> Option<NonNull<Self>> {
+        #[cfg(feature = "tracing")]
+        if unsafe { ptr.as_ref() }.right.is_some() {
+            trace::emit(ptr, trace::Mutation::Rotate(Side::Left));
+        }
+        let node = unsafe { &mut *ptr.as_ptr() };
+        let pivot_ptr = node.right?;
+        let pivot = unsafe { &mut *pivot_ptr.as_ptr() };
+
+        node.right = pivot.left;
+        if let Some(inner) = pivot.left {
+            unsafe { (*inner.as_ptr()).parent = Some(ptr) };
+        }
+        pivot.parent = node.parent;
+        if let Some(parent) = node.parent {
+            unsafe { Self::relink_child(parent, ptr, pivot_ptr) };
+        }
+        pivot.left = Some(ptr);
+        node.parent = Some(pivot_ptr);
+        unsafe {
+            Self::refresh(ptr);
+            Self::refresh(pivot_ptr);
+        }
+
+        Some(pivot_ptr)
+    }
+
+    /// # Safety
+    ///
+    /// See [`Node::rotate_left_raw`].
+    unsafe fn rotate_right_raw(ptr: NonNull<Self>) -> Option<NonNull<Self>> {
+        #[cfg(feature = "tracing")]
+        if unsafe { ptr.as_ref() }.left.is_some() {
+            trace::emit(ptr, trace::Mutation::Rotate(Side::Right));
+        }
+        let node = unsafe { &mut *ptr.as_ptr() };
+        let pivot_ptr = node.left?;
+        let pivot = unsafe { &mut *pivot_ptr.as_ptr() };
+
+        node.left = pivot.right;
+        if let Some(inner) = pivot.right {
+            unsafe { (*inner.as_ptr()).parent = Some(ptr) };
+        }
+        pivot.parent = node.parent;
+        if let Some(parent) = node.parent {
+            unsafe { Self::relink_child(parent, ptr, pivot_ptr) };
+        }
+        pivot.right = Some(ptr);
+        node.parent = Some(pivot_ptr);
+        unsafe {
+            Self::refresh(ptr);
+            Self::refresh(pivot_ptr);
+        }
+
+        Some(pivot_ptr)
+    }
+
+    /// Points whichever child slot of `parent` refers to `old` at `new` instead. The parent
+    /// pointer of `new` is left for the caller to set. If `old` is not a child of `parent` at all,
+    /// as with the path-parents of link-cut trees, `parent` is left untouched.
+    ///
+    /// # Safety
+    ///
+    /// `parent` must be dereferentiable and exclusively accessible by the caller.
+    unsafe fn relink_child(parent: NonNull<Self>, old: NonNull<Self>, new: NonNull<Self>) {
+        let parent = unsafe { &mut *parent.as_ptr() };
+        if parent.left == Some(old) {
+            parent.left = Some(new);
+        } else if parent.right == Some(old) {
+            parent.right = Some(new);
+        }
+    }
+
+    /// Recomputes the summary of `ptr` from its payload and the summaries of its children.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be dereferentiable and exclusively accessible by the caller, and so must its
+    /// children.
+    unsafe fn refresh(ptr: NonNull<Self>) {
+        let node = unsafe { &mut *ptr.as_ptr() };
+        let left = node.left.map(|child| unsafe { &(*child.as_ptr()).summary });
+        let right = node
+            .right
+            .map(|child| unsafe { &(*child.as_ptr()).summary });
+        node.summary = A::combine(left, &node.data, right);
+    }
+
+    /// Recomputes the summaries of `ptr` and all of its ancestors.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Node::refresh`], for `ptr` and each of its ancestors.
+    unsafe fn refresh_upwards(ptr: NonNull<Self>) {
+        // A zero sized summary carries no information, so there is nothing to recompute.
+        if mem::size_of::<A::Summary>() == 0 {
+            return;
+        }
+        let mut current = Some(ptr);
+        while let Some(ptr) = current {
+            unsafe { Self::refresh(ptr) };
+            current = unsafe { ptr.as_ref() }.parent;
+        }
+    }
+
+    /// Recomputes the summaries of the whole subtree rooted at `ptr`, and of its ancestors.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Node::refresh`], for every node of the subtree and each ancestor of `ptr`.
+    unsafe fn refresh_subtree(ptr: NonNull<Self>) {
+        if mem::size_of::<A::Summary>() == 0 {
+            return;
+        }
+        // Reversing a pre-order puts every node after all of its descendants.
+        let mut order = Vec::new();
+        let mut stack = vec![ptr];
+        while let Some(ptr) = stack.pop() {
+            order.push(ptr);
+            let node = unsafe { ptr.as_ref() };
+            stack.extend(node.left);
+            stack.extend(node.right);
+        }
+        for ptr in order.into_iter().rev() {
+            unsafe { Self::refresh(ptr) };
+        }
+        if let Some(parent) = unsafe { ptr.as_ref() }.parent {
+            unsafe { Self::refresh_upwards(parent) };
+        }
+    }
+
+    /// Detaches everything deeper than `max_depth` below this node (which is at depth 0), and
+    /// returns the roots of the pruned subtrees.
+    pub fn truncate_depth(&mut self, max_depth: usize) -> Vec<&mut Self> {
+        let root = NonNull::from(&mut *self);
+        let remove_parent = |mut ptr: ptr::NonNull<Self>| {
+            let ptr = unsafe { ptr.as_mut() };
+            ptr.parent = None;
+            ptr
+        };
+
+        let mut pruned = Vec::new();
+        let mut stack = vec![(root, 0)];
+        while let Some((mut ptr, depth)) = stack.pop() {
+            // Safety: each node is visited once, and we have exclusive access to the subtree.
+            let node = unsafe { ptr.as_mut() };
+            if depth == max_depth {
+                pruned.extend(node.left.take().map(remove_parent));
+                pruned.extend(node.right.take().map(remove_parent));
+            } else {
+                stack.extend(node.right.map(|child| (child, depth + 1)));
+                stack.extend(node.left.map(|child| (child, depth + 1)));
+            }
+        }
+        unsafe { Self::refresh_subtree(root) };
+        pruned
+    }
+
+    /// Merges every node that has exactly one child with that child, folding the child's payload
+    /// into its parent's through `merge`, until no such node remains in the subtree. The absorbed
+    /// nodes are left unlinked. Returns the number of nodes merged away.
+    pub fn collapse_unary(&mut self, mut merge: impl FnMut(&mut T, &mut T)) -> usize {
+        let root = NonNull::from(self);
+        let mut merged = 0;
+        let mut stack = vec![root];
+        while let Some(mut ptr) = stack.pop() {
+            // Safety: every pointer on the stack is a distinct node of the subtree we have
+            // exclusive access to, and nothing else refers to it while it is being processed.
+            let node = unsafe { ptr.as_mut() };
+            loop {
+                let mut child = match (node.left, node.right) {
+                    (Some(child), None) => {
+                        node.left = None;
+                        child
+                    }
+                    (None, Some(child)) => {
+                        node.right = None;
+                        child
+                    }
+                    _ => break,
+                };
+                let child = unsafe { child.as_mut() };
+                merge(&mut node.data, &mut child.data);
+                child.parent = None;
+                node.left = child.left.take();
+                node.right = child.right.take();
+                for grandchild in [node.left, node.right].into_iter().flatten() {
+                    unsafe { (*grandchild.as_ptr()).parent = Some(ptr) };
+                }
+                merged += 1;
+            }
+            stack.extend(node.left);
+            stack.extend(node.right);
+        }
+        unsafe { Self::refresh_subtree(root) };
+        merged
+    }
+
+    /// Picks `k` distinct nodes of this subtree uniformly at random, in a single pass over it, or
+    /// all of them if there are fewer. `rng` should return uniformly distributed bits. The nodes
+    /// are returned in no particular order.
+    pub fn sample(&self, mut rng: impl FnMut() -> u64, k: usize) -> Vec<&Self> {
+        let mut sample = Vec::with_capacity(k);
+        let mut stack = vec![self];
+        let mut seen = 0;
+        while let Some(node) = stack.pop() {
+            // The `seen`th node takes a slot with probability `k / (seen + 1)`.
+            if seen < k {
+                sample.push(node);
+            } else {
+                let slot = random_below(rng(), seen + 1);
+                if slot < k {
+                    sample[slot] = node;
+                }
+            }
+            seen += 1;
+            stack.extend(node.right());
+            stack.extend(node.left());
+        }
+        sample
+    }
+}
+
+impl<T> Node<T, EulerAug> {
+    /// Numbers the nodes of this subtree, so that [`Node::is_ancestor_of`] can answer in O(1)
+    /// until the subtree changes again. Calls are told apart by a 64-bit atomic counter, so this
+    /// only exists on targets that have one.
+    #[cfg(target_has_atomic = "64")]
+    pub fn index(&mut self) {
+        static GENERATION: AtomicU64 = AtomicU64::new(0);
+        let generation = GENERATION.fetch_add(1, atomic::Ordering::Relaxed);
+        let mut clock = 0;
+        let mut stack = vec![(NonNull::from(self), false)];
+        while let Some((ptr, left)) = stack.pop() {
+            // Safety: we have exclusive access to the subtree, and only summaries are written.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            if left {
+                if let Some(numbers) = &mut node.summary {
+                    numbers.exit = clock;
+                }
+            } else {
+                node.summary = Some(EulerNumbers {
+                    entry: clock,
+                    exit: clock,
+                    generation,
+                });
+                stack.push((ptr, true));
+                stack.extend(node.right.map(|right| (right, false)));
+                stack.extend(node.left.map(|left| (left, false)));
+            }
+            clock += 1;
+        }
+    }
+
+    /// The numbers given to this node by the last [`Node::index`], unless its subtree has changed
+    /// since.
+    pub fn euler_numbers(&self) -> Option<EulerNumbers> {
+        self.summary
+    }
+
+    /// Whether this node is a proper ancestor of `other`. This takes O(1) if both were numbered by
+    /// the same call to [`Node::index`], and walks up from `other` otherwise.
+    pub fn is_ancestor_of(&self, other: &Self) -> bool {
+        match (self.summary, other.summary) {
+            // Whatever would change how the two are related also wipes the numbers of one of
+            // them, so numbers from the same pass still tell.
+            (Some(this), Some(that)) if this.generation == that.generation => {
+                this.entry < that.entry && that.exit < this.exit
+            }
+            _ => other
+                .parent()
+                .is_some_and(|parent| parent.has_ancestor_or_self(self)),
+        }
+    }
+}
+
+impl<T> Node<T, SizeAug> {
+    /// Number of nodes in the subtree rooted at this node.
+    pub fn size(&self) -> usize {
+        self.summary
+    }
+
+    /// The `index`th node of this subtree in in-order, found in O(depth).
+    pub fn select(&self, index: usize) -> Option<&Self> {
+        Self::select_raw(self.into(), index).map(|ptr| unsafe { ptr.as_ref() })
+    }
+
+    pub fn select_mut(&mut self, index: usize) -> Option<&mut Self> {
+        Self::select_raw(self.into(), index).map(|mut ptr| unsafe { ptr.as_mut() })
+    }
+
+    fn select_raw(mut ptr: NonNull<Self>, mut index: usize) -> Option<NonNull<Self>> {
+        loop {
+            let node = unsafe { ptr.as_ref() };
+            let left = node.left().map_or(0, Node::size);
+            if index < left {
+                ptr = node.left?;
+            } else if index == left {
+                return Some(ptr);
+            } else {
+                index -= left + 1;
+                ptr = node.right?;
+            }
+        }
+    }
+}
+
+/// A step from a node down to one of its children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Scales random `bits` down to `0..n`.
+pub(crate) fn random_below(bits: u64, n: usize) -> usize {
+    ((u128::from(bits) * n as u128) >> 64) as usize
+}
+
+/// The random words the randomized tests and the benchmarks draw from: a linear congruential
+/// generator started at `seed`, whose high bits are the random ones.
+#[cfg(any(test, feature = "bench"))]
+pub(crate) fn lcg_words(seed: u64) -> impl FnMut() -> u64 {
+    let mut state = seed;
+    move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        state
+    }
+}
+
+/// Numbers below the one asked for, from the words of [`lcg_words`].
+#[cfg(any(test, feature = "bench"))]
+pub(crate) fn lcg(seed: u64) -> impl FnMut(u64) -> u64 {
+    let mut words = lcg_words(seed);
+    move |below| (words() >> 33) % below
+}
+
+/// Resolves `range` over `0..len` into its start and (exclusive) end, which are not clamped.
+pub(crate) fn bounds(range: &impl RangeBounds<usize>, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.saturating_add(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    (start, end)
 }
 
 #[cfg(test)]
@@ -155,4 +1190,613 @@ mod tests {
         };
         assert_eq!(n4.get(), "4");
     }
+
+    #[test]
+    fn breadcrumbs_from_the_root() {
+        let (node2, node3, node4) = (&mut Node::new(2), &mut Node::new(3), &mut Node::new(4));
+        node3.replace_right(Some(node4));
+        node2.replace_right(Some(node3));
+
+        let n4 = node2.right().and_then(Node::right).unwrap();
+        let show = |data: &i32, f: &mut fmt::Formatter<'_>| write!(f, "<{data}>");
+        assert_eq!(n4.breadcrumbs(show).to_string(), "<2> > <3> > <4>");
+        assert_eq!(node2.breadcrumbs(show).to_string(), "<2>");
+    }
+
+    #[test]
+    fn paths_and_disjoint_payloads() {
+        use Side::{Left, Right};
+        let (node0, node1, node2, node3, node4) = (
+            &mut Node::new(0),
+            &mut Node::new(1),
+            &mut Node::new(2),
+            &mut Node::new(3),
+            &mut Node::new(4),
+        );
+        node0.replace_right(Some(node1));
+        node3.replace_right(Some(node4));
+        node2.replace_left(Some(node0));
+        node2.replace_right(Some(node3));
+
+        assert_eq!(node2.at_path(&[Right, Right]).map(Node::get), Some(&4));
+        assert!(node2.at_path(&[Left, Left]).is_none());
+        let (one, three) = node2.get_disjoint_mut(&[Left, Right], &[Right]).unwrap();
+        mem::swap(one, three);
+        assert_eq!(node2.at_path(&[Right]).map(Node::get), Some(&1));
+        assert!(node2.get_disjoint_mut(&[Left], &[Left, Right]).is_none());
+        assert!(node2.get_disjoint_mut(&[Right], &[Right]).is_none());
+        assert!(node2.get_disjoint_mut(&[Left], &[Left, Left]).is_none());
+    }
+
+    #[test]
+    fn relinking_a_linked_node() {
+        let (a, b, c) = (&mut Node::new(0), &mut Node::new(1), &mut Node::new(2));
+        a.replace_left(Some(c));
+        // `c` still hangs below `a`: it is moved below `b` rather than shared.
+        b.replace_right(Some(c));
+        assert!(a.left().is_none());
+        assert!(std::ptr::eq(c.parent().unwrap(), b));
+        assert_eq!(
+            a.try_replace(Side::Left, Some(c)).err(),
+            Some(TreeError::DifferentTrees)
+        );
+        assert_eq!(
+            c.try_replace(Side::Left, Some(b)).err(),
+            Some(TreeError::WouldCreateCycle)
+        );
+        assert!(a.try_replace(Side::Left, Some(b)).unwrap().is_none());
+        assert_eq!(
+            a.iter(Order::Pre)
+                .map(|node| *node.get())
+                .collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+
+        // A node moved to the other side of its own parent.
+        let (d, e) = (
+            &mut Node::<_, SizeAug>::augmented(3),
+            &mut Node::augmented(4),
+        );
+        d.replace_left(Some(e));
+        d.replace_right(Some(e));
+        assert!(d.left().is_none() && d.right().is_some());
+        assert_eq!(d.size(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn attaching_an_ancestor_panics() {
+        let (a, b, c) = (&mut Node::new(0), &mut Node::new(1), &mut Node::new(2));
+        b.replace_left(Some(c));
+        a.replace_left(Some(b));
+        c.replace_right(Some(a));
+    }
+
+    #[test]
+    fn map_payloads_in_place() {
+        let mut tree = Tree::from_shape((1..=7).map(|n| n.to_string()), Shape::Balanced, |_| 0);
+        let mut visited = Vec::new();
+        // Safety: only payloads are changed.
+        unsafe { tree.root_mut() }.unwrap().map_in_place(|mut s| {
+            visited.push(s.clone());
+            s.push('!');
+            s
+        });
+        assert_eq!(visited, ["4", "2", "1", "3", "6", "5", "7"]);
+        let root = tree.root().unwrap();
+        assert!(root
+            .iter(Order::In)
+            .map(Node::get)
+            .eq(["1!", "2!", "3!", "4!", "5!", "6!", "7!"]));
+
+        let mut nodes: Vec<Node<i32, SizeAug>> = (0..3).map(Node::augmented).collect();
+        let (a, rest) = nodes.split_first_mut().unwrap();
+        a.replace_right(rest.first_mut());
+        a.right_mut().unwrap().map_in_place(|n| n * 10);
+        assert_eq!((a.size(), *a.right().unwrap().get()), (2, 10));
+    }
+
+    #[test]
+    fn fallible_structural_changes() {
+        use Side::{Left, Right};
+        let mut nodes: Vec<_> = (0..5).map(Node::new).collect();
+        let [node0, node1, node2, node3, node4] = &mut nodes[..] else {
+            unreachable!()
+        };
+
+        assert_eq!(node0.try_attach(Left, node1), Ok(()));
+        assert_eq!(node0.try_graft(&[Left, Right], node2), Ok(()));
+        assert_eq!(
+            node0.try_graft(&[Left, Right], node3),
+            Err(TreeError::SlotOccupied)
+        );
+        assert_eq!(
+            node0.try_graft(&[Right, Left], node3),
+            Err(TreeError::PathOutOfBounds)
+        );
+        assert_eq!(
+            node3.try_attach(Left, node2),
+            Err(TreeError::DifferentTrees)
+        );
+        assert_eq!(node3.try_attach(Right, node4), Ok(()));
+        assert_eq!(
+            node4.try_attach(Left, node3),
+            Err(TreeError::WouldCreateCycle)
+        );
+        assert_eq!(node0.try_attach(Right, node3), Ok(()));
+
+        //      0
+        //    /   \
+        //   1     3
+        //    \     \
+        //     2     4
+        assert_eq!(
+            node0.try_swap(&[Left], &[Left, Right]),
+            Err(TreeError::WouldCreateCycle)
+        );
+        assert_eq!(
+            node0.try_swap(&[Left, Left], &[Right]),
+            Err(TreeError::NotAChild)
+        );
+        assert_eq!(node0.try_swap(&[Left, Right], &[Right, Right]), Ok(()));
+        assert_eq!(node0.try_swap(&[Left], &[Right]), Ok(()));
+        let mut order = Vec::new();
+        in_order(Some(node0), &mut order);
+        assert_eq!(order, [3, 2, 0, 1, 4]);
+        let three = node0.at_path(&[Left]).unwrap();
+        assert!(std::ptr::eq(
+            three.right().unwrap().parent().unwrap(),
+            three
+        ));
+
+        assert_eq!(
+            node0.try_detach(&[Right, Left]).err(),
+            Some(TreeError::NotAChild)
+        );
+        assert_eq!(node0.try_detach(&[Right]).map(|node| *node.get()), Ok(1));
+        assert!(node0.right().is_none());
+    }
+
+    #[test]
+    fn collapse_unary_chains() {
+        let a = &mut Node::new(String::from("a"));
+        let b = &mut Node::new(String::from("b"));
+        let c = &mut Node::new(String::from("c"));
+        let d = &mut Node::new(String::from("d"));
+        let e = &mut Node::new(String::from("e"));
+        let f = &mut Node::new(String::from("f"));
+
+        //      a
+        //     /
+        //    b
+        //     \
+        //      c
+        //     / \
+        //    d   e
+        //         \
+        //          f
+        e.replace_right(Some(f));
+        c.replace_left(Some(d));
+        c.replace_right(Some(e));
+        b.replace_right(Some(c));
+        a.replace_left(Some(b));
+
+        let merged = a.collapse_unary(|parent, child| parent.push_str(child));
+        assert_eq!(merged, 3);
+        assert_eq!(a.get(), "abc");
+        let (Some(d), Some(ef)) = (a.left(), a.right()) else {
+            panic!("Expected the collapsed root to keep both children")
+        };
+        assert_eq!(d.get(), "d");
+        assert_eq!(ef.get(), "ef");
+        assert!(ef.left().is_none() && ef.right().is_none());
+        assert_eq!(ef.parent().map(Node::get).map(String::as_str), Some("abc"));
+    }
+
+    #[test]
+    fn truncate_to_depth() {
+        let node0 = &mut Node::new(0);
+        let node1 = &mut Node::new(1);
+        let node2 = &mut Node::new(2);
+        let node3 = &mut Node::new(3);
+        let node4 = &mut Node::new(4);
+
+        //      2
+        //    /   \
+        //   0     3
+        //  / \   / \
+        // -   1 -   4
+        node0.replace_right(Some(node1));
+        node3.replace_right(Some(node4));
+        node2.replace_left(Some(node0));
+        node2.replace_right(Some(node3));
+
+        let pruned = node2.truncate_depth(1);
+        let pruned: Vec<_> = pruned
+            .iter()
+            .map(|node| (*node.get(), node.parent().is_none()))
+            .collect();
+        assert_eq!(pruned, [(1, true), (4, true)]);
+        assert!(node2.left().is_some_and(|node| node.right().is_none()));
+        assert!(node2.right().is_some_and(|node| node.right().is_none()));
+
+        let pruned = node2.truncate_depth(0);
+        assert_eq!(pruned.len(), 2);
+        assert!(node2.left().is_none() && node2.right().is_none());
+    }
+
+    fn right_chain<T>(nodes: &mut [Node<T>]) -> Option<&mut Node<T>> {
+        let (first, rest) = nodes.split_first_mut()?;
+        first.replace_right(right_chain(rest));
+        Some(first)
+    }
+
+    fn height<T>(node: Option<&Node<T>>) -> usize {
+        node.map_or(0, |node| 1 + height(node.left()).max(height(node.right())))
+    }
+
+    fn in_order<T: Copy>(node: Option<&Node<T>>, out: &mut Vec<T>) {
+        if let Some(node) = node {
+            in_order(node.left(), out);
+            out.push(*node.get());
+            in_order(node.right(), out);
+        }
+    }
+
+    #[test]
+    fn rotations_relink_parent() {
+        let node0 = &mut Node::new(0);
+        let node1 = &mut Node::new(1);
+        let node2 = &mut Node::new(2);
+        let node3 = &mut Node::new(3);
+
+        // 0 - 1 - 2 - 3, all to the right
+        node2.replace_right(Some(node3));
+        node1.replace_right(Some(node2));
+        node0.replace_right(Some(node1));
+
+        let Some(node1) = node0.right_mut() else {
+            panic!("Expected node 1 to be present")
+        };
+        let Some(node2) = node1.rotate_left() else {
+            panic!("Expected node 1 to have a right child")
+        };
+        assert_eq!(*node2.get(), 2);
+        assert_eq!(node2.left().map(Node::get), Some(&1));
+        assert_eq!(node2.parent().map(Node::get), Some(&0));
+        assert!(node2.rotate_left().is_some());
+        assert_eq!(node0.right().map(Node::get), Some(&3));
+
+        let node3 = node0.right_mut().unwrap();
+        assert!(node3.rotate_right().is_some());
+        assert_eq!(node0.right().map(Node::get), Some(&2));
+        let (left, right) = node0.right().unwrap().children();
+        assert_eq!(
+            (left.map(Node::get), right.map(Node::get)),
+            (Some(&1), Some(&3))
+        );
+        let mut order = Vec::new();
+        in_order(Some(node0), &mut order);
+        assert_eq!(order, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn iterate_in_every_order() {
+        let tree = Tree::from_shape(0..7, Shape::Balanced, |_| unreachable!());
+        let root = tree.root().unwrap();
+        for (order, expected) in [
+            (Order::Pre, [3, 1, 0, 2, 5, 4, 6]),
+            (Order::In, [0, 1, 2, 3, 4, 5, 6]),
+            (Order::Post, [0, 2, 1, 4, 6, 5, 3]),
+            (Order::Level, [3, 1, 5, 0, 2, 4, 6]),
+        ] {
+            assert!(root.iter(order).map(Node::get).eq(&expected));
+            assert_eq!(tree.to_vec(order), expected);
+            assert!(root.to_ref_vec(order).into_iter().eq(&expected));
+        }
+        assert!(root.descendants().map(Node::get).eq(&[1, 0, 2, 5, 4, 6]));
+        let mut buffer = TraversalBuffer::with_capacity(8);
+        for order in [Order::Pre, Order::In, Order::Post, Order::Level] {
+            let nodes = root.iter_in(order, &mut buffer).map(Node::get);
+            assert!(nodes.eq(root.iter(order).map(Node::get)));
+            // Abandoning a traversal midway leaves nothing behind for the next one.
+            assert_eq!(root.iter_in(order, &mut buffer).take(3).count(), 3);
+        }
+        assert!(root
+            .iter_inorder_in(&mut buffer)
+            .map(Node::get)
+            .eq(&[0, 1, 2, 3, 4, 5, 6]));
+        let mut limited = root.iter_limited(1, usize::MAX);
+        let nodes: Vec<_> = limited
+            .by_ref()
+            .map(|(node, depth)| (*node.get(), depth))
+            .collect();
+        assert_eq!(nodes, [(3, 0), (1, 1), (5, 1)]);
+        assert!(limited.truncated());
+        let mut limited = root.iter_limited(usize::MAX, 4);
+        assert_eq!(limited.by_ref().count(), 4);
+        assert!(limited.truncated());
+        let mut limited = root.iter_limited(2, 7);
+        assert_eq!(limited.by_ref().count(), 7);
+        assert!(!limited.truncated());
+        let pruned: Vec<_> = root
+            .iter_pruned(|&value| {
+                if value == 1 {
+                    Descend::No
+                } else {
+                    Descend::Yes
+                }
+            })
+            .map(|node| *node.get())
+            .collect();
+        assert_eq!(pruned, [3, 1, 5, 4, 6]);
+
+        assert!(root
+            .iter_inorder()
+            .rev()
+            .map(Node::get)
+            .eq(&[6, 5, 4, 3, 2, 1, 0]));
+        let mut both_ends = root.iter_inorder().map(|node| *node.get());
+        let ends = [
+            both_ends.next(),
+            both_ends.next_back(),
+            both_ends.next_back(),
+        ];
+        assert_eq!(ends, [Some(0), Some(6), Some(5)]);
+        assert!(both_ends.eq(1..5));
+        let subtree = root.right().unwrap().iter_inorder().map(Node::get);
+        assert!(subtree.rev().eq(&[6, 5, 4]));
+
+        let leaf = root.left().and_then(Node::left).unwrap();
+        assert_eq!(leaf.descendants().count(), 0);
+        assert_eq!(leaf.iter_inorder().rev().count(), 1);
+        let mut nodes = root.iter(Order::Level);
+        assert_eq!(nodes.by_ref().count(), 7);
+        assert!(nodes.next().is_none() && nodes.next().is_none());
+        assert_eq!(leaf.iter(Order::Post).count(), 1);
+    }
+
+    #[test]
+    fn balance_degenerate_chain() {
+        for size in 1..=20 {
+            let mut nodes: Vec<_> = (0..size).map(Node::new).collect();
+            let root = right_chain(&mut nodes).unwrap();
+            assert_eq!(height(Some(root)), size);
+
+            let root = root.balance();
+            assert_eq!(
+                height(Some(root)),
+                (size + 1).next_power_of_two().ilog2() as usize
+            );
+            assert!(root.parent().is_none());
+            let mut order = Vec::new();
+            in_order(Some(root), &mut order);
+            assert_eq!(order, (0..size).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn flatten_to_right_spine() {
+        let mut nodes: Vec<_> = (0..6).map(Node::new).collect();
+        let [node0, node1, node2, node3, node4, node5] = &mut nodes[..] else {
+            unreachable!()
+        };
+
+        //      0
+        //    /   \
+        //   1     4
+        //  / \     \
+        // 2   3     5
+        node1.replace_left(Some(node2));
+        node1.replace_right(Some(node3));
+        node4.replace_right(Some(node5));
+        node0.replace_left(Some(node1));
+        node0.replace_right(Some(node4));
+
+        node0.flatten_preorder();
+        let mut order = Vec::new();
+        let mut current = Some(&*node0);
+        while let Some(node) = current {
+            assert!(node.left().is_none());
+            if let Some(right) = node.right() {
+                assert_eq!(right.parent().map(Node::get), Some(node.get()));
+            }
+            order.push(*node.get());
+            current = node.right();
+        }
+        assert_eq!(order, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn sample_uniformly() {
+        let mut nodes: Vec<_> = (0..10).map(Node::new).collect();
+        let root = right_chain(&mut nodes).unwrap();
+        let mut rng = crate::lcg_words(17);
+
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            let sample = root.sample(&mut rng, 3);
+            assert_eq!(sample.len(), 3);
+            for node in sample {
+                counts[*node.get()] += 1;
+            }
+        }
+        // Each node is expected 3000 times.
+        assert!(counts.iter().all(|&count| (2700..3300).contains(&count)));
+        assert_eq!(root.sample(&mut rng, 20).len(), 10);
+        assert!(root.sample(rng, 0).is_empty());
+    }
+
+    #[test]
+    #[cfg(target_has_atomic = "64")]
+    fn euler_ancestors() {
+        let mut nodes: Vec<_> = (0..5).map(Node::<_, EulerAug>::augmented).collect();
+        let [node0, node1, node2, node3, node4] = &mut nodes[..] else {
+            unreachable!()
+        };
+        node1.replace_left(Some(node0));
+        node1.replace_right(Some(node2));
+        node3.replace_left(Some(node1));
+        node3.replace_right(Some(node4));
+        assert!(node3.euler_numbers().is_none());
+        node3.index();
+        assert_eq!(
+            node3.euler_numbers().map(|n| (n.entry, n.exit)),
+            Some((0, 9))
+        );
+
+        let check = |root: &Node<i32, EulerAug>| {
+            let all: Vec<_> = root.iter(Order::Pre).collect();
+            for a in &all {
+                for b in &all {
+                    let walked = b.parent().is_some_and(|p| p.has_ancestor_or_self(a));
+                    assert_eq!(a.is_ancestor_of(b), walked, "{} {}", a.get(), b.get());
+                }
+            }
+        };
+        check(node3);
+
+        // A rotation wipes the numbers of the two nodes it swaps, which fall back to walking, and
+        // leaves those of the rest, which are still right.
+        let node1 = node3.left_mut().unwrap();
+        node1.rotate_left().unwrap();
+        let node2 = node3.left().unwrap();
+        assert_eq!(*node2.get(), 2);
+        assert!(node2.euler_numbers().is_none());
+        assert!(node2.left().unwrap().euler_numbers().is_none());
+        assert!(node3.euler_numbers().is_some());
+        check(node3);
+        node3.index();
+        assert!(node3.iter(Order::Pre).all(|n| n.euler_numbers().is_some()));
+        check(node3);
+    }
+
+    #[test]
+    fn size_augmentation() {
+        let mut nodes: Vec<_> = (0..7).map(Node::<_, SizeAug>::augmented).collect();
+        let [node0, node1, node2, node3, node4, node5, node6] = &mut nodes[..] else {
+            unreachable!()
+        };
+
+        node1.replace_left(Some(node0));
+        node5.replace_right(Some(node6));
+        node4.replace_right(Some(node5));
+        node3.replace_left(Some(node1));
+        assert_eq!(node3.size(), 3);
+        node3.replace_right(Some(node4));
+        assert_eq!(node3.size(), 6);
+        assert_eq!(node3.select(4).map(Node::get), Some(&5));
+        assert!(node3.select(6).is_none());
+
+        // Sizes further up are kept current when linking deep in the tree.
+        let node4 = node3.right_mut().unwrap();
+        node4.replace_left(Some(node2));
+        assert_eq!(node3.size(), 7);
+        let node4 = node3.right_mut().unwrap();
+        assert_eq!(node4.size(), 4);
+        assert_eq!(node4.rotate_left().map(|node| node.size()), Some(4));
+        assert_eq!(node3.select(4).map(Node::get), Some(&4));
+
+        let root = node3.balance();
+        assert_eq!(root.size(), 7);
+        assert_eq!(root.left().map(Node::size), Some(3));
+        for index in 0..7 {
+            assert_eq!(
+                root.select(index).map(Node::get),
+                Some(&[0, 1, 3, 2, 4, 5, 6][index])
+            );
+        }
+        let payloads = root.to_vec(Order::Post);
+        assert_eq!((payloads.len(), payloads.capacity()), (7, 7));
+        for order in [Order::Pre, Order::In, Order::Post, Order::Level] {
+            let mut nodes = root.iter(order);
+            assert_eq!(nodes.len(), 7);
+            nodes.nth(2);
+            assert_eq!((nodes.len(), nodes.count()), (4, 4));
+        }
+        let mut nodes = root.iter_inorder();
+        nodes.next_back();
+        assert_eq!(nodes.len(), 6);
+        assert_eq!(root.descendants().len(), 6);
+        // Without a count, the hint goes by what is queued.
+        let plain = Node::new(());
+        assert_eq!(plain.iter(Order::Pre).size_hint(), (1, None));
+        *root.select_mut(0).unwrap().get_mut() = 10;
+
+        let (left, root, _) = root.split_mut();
+        assert_eq!(left.map(|node| node.size()), Some(3));
+        assert_eq!(root.size(), 1);
+    }
+
+    struct Sum;
+
+    impl Augment<i32> for Sum {
+        type Summary = i32;
+
+        fn combine(left: Option<&i32>, data: &i32, right: Option<&i32>) -> i32 {
+            left.unwrap_or(&0) + data + right.unwrap_or(&0)
+        }
+    }
+
+    #[test]
+    fn custom_augmentation() {
+        let mut nodes: Vec<_> = (1..=4).map(Node::<_, Sum>::augmented).collect();
+        let [node1, node2, node3, node4] = &mut nodes[..] else {
+            unreachable!()
+        };
+
+        node3.replace_right(Some(node4));
+        node2.replace_left(Some(node1));
+        node2.replace_right(Some(node3));
+        assert_eq!(*node2.summary(), 10);
+
+        let node4 = node2.right_mut().and_then(Node::right_mut).unwrap();
+        *node4.get_mut() += 10;
+        assert_eq!(*node4.summary(), 14);
+        assert_eq!(*node2.summary(), 20);
+
+        node2.rotate_left();
+        let node3 = node2.parent_mut().unwrap();
+        assert_eq!(*node3.summary(), 20);
+        assert_eq!(node3.left().map(Node::summary), Some(&3));
+
+        let node1 = node3.left_mut().and_then(Node::left_mut).unwrap();
+        assert_eq!(node1.replace_data(5), 1);
+        assert_eq!(node1.take_data(), 5);
+        assert_eq!(*node1.get(), 0);
+        assert_eq!(*node1.parent().unwrap().parent().unwrap().summary(), 19);
+
+        let mut other = Node::<_, Sum>::augmented(100);
+        node1.swap_data(&mut other);
+        assert_eq!(*other.get(), 0);
+        let (Some(node2), node3, Some(node4)) = node1
+            .parent_mut()
+            .unwrap()
+            .parent_mut()
+            .unwrap()
+            .split_mut()
+        else {
+            unreachable!()
+        };
+        node2.swap_data(node4);
+        assert_eq!((*node2.get(), *node4.get()), (14, 2));
+        assert_eq!(*node2.summary(), 114);
+        assert_eq!(*node3.summary(), 3);
+
+        let mut nodes: Vec<_> = (1..=3).map(Node::<_, Sum>::augmented).collect();
+        let [node1, root, node3] = &mut nodes[..] else {
+            unreachable!()
+        };
+        root.replace_left(Some(node1));
+        root.replace_right(Some(node3));
+        let mut payloads = root.iter_inorder_mut();
+        let mut seen = Vec::new();
+        while let Some(mut payload) = payloads.next() {
+            seen.push(*payload);
+            *payload *= 2;
+        }
+        assert_eq!(seen, [1, 2, 3]);
+        assert_eq!(*root.summary(), 12);
+        assert_eq!(root.right().map(Node::summary), Some(&6));
+    }
 }
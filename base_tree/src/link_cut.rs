@@ -0,0 +1,312 @@
+//! Link-cut trees, maintaining a forest of rooted trees under linking and cutting.
+
+use alloc::boxed::Box;
+use core::ptr::{self, NonNull};
+
+use crate::segment_tree::{Fold, RangeOp};
+use crate::{Augment, Node};
+
+type PathNode<T, Op> = Node<T, Fold<Op>>;
+
+/// A forest over the vertices `0..len`, each holding a value, where an edge from a root to any
+/// vertex of another tree can be added, and the edge from any vertex to its parent removed. Both,
+/// as well as finding a vertex's root and folding the values on the path to it with `Op`, take
+/// O(log n) amortized.
+///
+/// The forest is split into paths, each kept as a splay tree ordered from the root end down.
+/// The root of each splay tree keeps a parent pointer to where its path hangs off, without being
+/// the child of that node.
+pub struct LinkCutForest<T: Clone, Op: RangeOp<T>> {
+    // One node per vertex, in order. The allocation is only ever accessed through this pointer,
+    // and freed on drop.
+    nodes: NonNull<PathNode<T, Op>>,
+    len: usize,
+}
+
+impl<T: Clone, Op: RangeOp<T>> LinkCutForest<T, Op> {
+    /// Creates a forest of single vertex trees, one per value.
+    pub fn new(values: &[T]) -> Self {
+        let nodes: Box<[_]> = values
+            .iter()
+            .cloned()
+            .map(PathNode::<T, Op>::augmented)
+            .collect();
+        let len = nodes.len();
+        // Safety: `Box::into_raw` never returns null.
+        let nodes = unsafe { NonNull::new_unchecked(Box::into_raw(nodes).cast()) };
+        Self { nodes, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, vertex: usize) -> Option<&T> {
+        (vertex < self.len).then(|| unsafe { self.nodes.add(vertex).as_ref() }.get())
+    }
+
+    /// Replaces the value of `vertex`, returning the old one, or `None` if out of bounds.
+    pub fn set(&mut self, vertex: usize, value: T) -> Option<T> {
+        let ptr = self.node(vertex)?;
+        // The node is the root of its splay tree after an access, so refreshing it alone keeps
+        // every summary up to date.
+        unsafe {
+            self.access(ptr);
+            let old = core::mem::replace(&mut (*ptr.as_ptr()).data, value);
+            Node::refresh(ptr);
+            Some(old)
+        }
+    }
+
+    /// Makes `parent` the parent of `child`, if `child` is the root of its tree and `parent` is
+    /// in another one. Returns whether the edge was added.
+    ///
+    /// # Panics
+    ///
+    /// If either vertex is out of bounds.
+    pub fn link(&mut self, child: usize, parent: usize) -> bool {
+        let (child, parent) = (self.vertex(child), self.vertex(parent));
+        unsafe {
+            if self.find_root_raw(child) != child || self.find_root_raw(parent) == child {
+                return false;
+            }
+            // As a root with nothing below it on its path, `child` is alone in its splay tree.
+            self.access(child);
+            (*child.as_ptr()).parent = Some(parent);
+        }
+        true
+    }
+
+    /// Removes the edge from `vertex` to its parent, making it the root of a new tree. Returns
+    /// whether there was such an edge.
+    ///
+    /// # Panics
+    ///
+    /// If `vertex` is out of bounds.
+    pub fn cut(&mut self, vertex: usize) -> bool {
+        let ptr = self.vertex(vertex);
+        unsafe {
+            self.access(ptr);
+            let node = &mut *ptr.as_ptr();
+            let Some(ancestors) = node.left.take() else {
+                return false;
+            };
+            (*ancestors.as_ptr()).parent = None;
+            Node::refresh(ptr);
+        }
+        true
+    }
+
+    /// The root of the tree containing `vertex`.
+    ///
+    /// # Panics
+    ///
+    /// If `vertex` is out of bounds.
+    pub fn find_root(&mut self, vertex: usize) -> usize {
+        let ptr = self.vertex(vertex);
+        let root = unsafe { self.find_root_raw(ptr) };
+        self.index(root)
+    }
+
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find_root(a) == self.find_root(b)
+    }
+
+    /// Folds the values on the path from the root of the tree containing `vertex` down to it,
+    /// in that order, with `Op`.
+    ///
+    /// # Panics
+    ///
+    /// If `vertex` is out of bounds.
+    pub fn query_path(&mut self, vertex: usize) -> T {
+        let ptr = self.vertex(vertex);
+        unsafe {
+            self.access(ptr);
+            ptr.as_ref().summary().clone()
+        }
+    }
+
+    fn node(&self, vertex: usize) -> Option<NonNull<PathNode<T, Op>>> {
+        (vertex < self.len).then(|| unsafe { self.nodes.add(vertex) })
+    }
+
+    fn vertex(&self, vertex: usize) -> NonNull<PathNode<T, Op>> {
+        let len = self.len;
+        self.node(vertex)
+            .unwrap_or_else(|| panic!("vertex {vertex} out of bounds for a forest of {len}"))
+    }
+
+    fn index(&self, ptr: NonNull<PathNode<T, Op>>) -> usize {
+        unsafe { ptr.offset_from(self.nodes) as usize }
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be a node of this forest, which the caller has exclusive access to.
+    unsafe fn find_root_raw(&mut self, ptr: NonNull<PathNode<T, Op>>) -> NonNull<PathNode<T, Op>> {
+        unsafe {
+            self.access(ptr);
+            let mut root = ptr;
+            while let Some(left) = root.as_ref().left {
+                root = left;
+            }
+            // Splaying what was walked to pays for the walk.
+            splay(root);
+            root
+        }
+    }
+
+    /// Makes the path from the root of its tree down to `ptr` a single splay tree, with `ptr` at
+    /// its root, and nothing below `ptr` on the path.
+    ///
+    /// # Safety
+    ///
+    /// See [`LinkCutForest::find_root_raw`].
+    unsafe fn access(&mut self, ptr: NonNull<PathNode<T, Op>>) {
+        unsafe {
+            splay(ptr);
+            // Whatever was below on the path keeps its parent pointer, which now leads to where
+            // it hangs off.
+            (*ptr.as_ptr()).right = None;
+            Node::refresh(ptr);
+            while let Some(above) = ptr.as_ref().parent {
+                splay(above);
+                (*above.as_ptr()).right = Some(ptr);
+                Node::refresh(above);
+                splay(ptr);
+            }
+        }
+    }
+}
+
+/// Whether `ptr` is the root of its splay tree, its parent pointer (if any) being a path-parent.
+///
+/// # Safety
+///
+/// `ptr` and its parent must be dereferentiable.
+unsafe fn is_splay_root<T, A: Augment<T>>(ptr: NonNull<Node<T, A>>) -> bool {
+    match unsafe { ptr.as_ref() }.parent {
+        None => true,
+        Some(parent) => {
+            let parent = unsafe { parent.as_ref() };
+            parent.left != Some(ptr) && parent.right != Some(ptr)
+        }
+    }
+}
+
+/// Rotates `ptr` above its parent within its splay tree.
+///
+/// # Safety
+///
+/// The caller must have exclusive access to `ptr`, its parent and grandparent, and `ptr` must not
+/// be a splay root.
+unsafe fn rotate_up<T, A: Augment<T>>(ptr: NonNull<Node<T, A>>) {
+    unsafe {
+        let parent = ptr.as_ref().parent.unwrap();
+        if parent.as_ref().left == Some(ptr) {
+            Node::rotate_right_raw(parent);
+        } else {
+            Node::rotate_left_raw(parent);
+        }
+    }
+}
+
+/// Moves `ptr` to the root of its splay tree.
+///
+/// # Safety
+///
+/// The caller must have exclusive access to the splay tree holding `ptr`, and to the node its
+/// root hangs off.
+unsafe fn splay<T, A: Augment<T>>(ptr: NonNull<Node<T, A>>) {
+    unsafe {
+        while !is_splay_root(ptr) {
+            let parent = ptr.as_ref().parent.unwrap();
+            if !is_splay_root(parent) {
+                let grandparent = parent.as_ref().parent.unwrap();
+                let zig_zig = (parent.as_ref().left == Some(ptr))
+                    == (grandparent.as_ref().left == Some(parent));
+                rotate_up(if zig_zig { parent } else { ptr });
+            }
+            rotate_up(ptr);
+        }
+    }
+}
+
+impl<T: Clone, Op: RangeOp<T>> Drop for LinkCutForest<T, Op> {
+    fn drop(&mut self) {
+        let nodes = ptr::slice_from_raw_parts_mut(self.nodes.as_ptr(), self.len);
+        // Safety: this is the allocation made in `new`, and no references into it remain.
+        drop(unsafe { Box::from_raw(nodes) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Concatenation, which tells the order values were folded in.
+    struct Concat;
+
+    impl RangeOp<String> for Concat {
+        fn combine(left: &String, right: &String) -> String {
+            format!("{left}{right}")
+        }
+    }
+
+    #[test]
+    fn matches_parent_array() {
+        let len = 30;
+        let names: Vec<_> = (0..len)
+            .map(|i| format!("{}", (b'a' + i as u8) as char))
+            .collect();
+        let mut forest = LinkCutForest::<String, Concat>::new(&names);
+        let mut parents: Vec<Option<usize>> = vec![None; len];
+        let root = |parents: &[Option<usize>], mut vertex: usize| {
+            let mut path = vec![vertex];
+            while let Some(parent) = parents[vertex] {
+                vertex = parent;
+                path.push(vertex);
+            }
+            path
+        };
+
+        let mut lcg = crate::lcg(5);
+        let mut random = |below: usize| lcg(below as u64) as usize;
+        for round in 0..3000 {
+            let (a, b) = (random(len), random(len));
+            if round % 3 == 0 {
+                assert_eq!(forest.cut(a), parents[a].take().is_some());
+            } else {
+                let linkable = parents[a].is_none() && root(&parents, b).last() != Some(&a);
+                assert_eq!(forest.link(a, b), linkable);
+                if linkable {
+                    parents[a] = Some(b);
+                }
+            }
+
+            let path = root(&parents, b);
+            assert_eq!(forest.find_root(b), *path.last().unwrap());
+            let folded: String = path
+                .iter()
+                .rev()
+                .map(|&vertex| names[vertex].as_str())
+                .collect();
+            assert_eq!(forest.query_path(b), folded);
+            assert_eq!(
+                forest.connected(a, b),
+                root(&parents, a).last() == path.last()
+            );
+        }
+
+        forest.set(3, "!".to_string());
+        for vertex in 0..len {
+            forest.cut(vertex);
+        }
+        assert!(forest.link(3, 4) && forest.link(4, 5));
+        assert_eq!(forest.query_path(3), "fe!");
+    }
+}
@@ -0,0 +1,253 @@
+//! Reference models of the ordered maps, simple enough to be obviously right, for tests to
+//! check the maps against after random sequences of operations.
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::mem;
+
+/// An ordered map kept as a sorted `Vec`, with the interface of [`BstMap`](crate::BstMap) and
+/// [`WeightBalancedMap`](crate::WeightBalancedMap). Updates take O(n).
+#[derive(Clone, PartialEq, Eq)]
+pub struct VecMap<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+impl<K, V> VecMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over the entries in key order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K: Ord, V> VecMap<K, V> {
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries
+            .binary_search_by(|(probe, _)| probe.borrow().cmp(key))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.search(key).ok()?;
+        Some(&self.entries[index].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.search(key).ok()?;
+        Some(&mut self.entries[index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).is_ok()
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaces, if any. The key already in
+    /// the map is kept in that case, as the maps do.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.search(&key) {
+            Ok(index) => Some(mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.entries.insert(index, (key, value));
+                None
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.search(key).ok()?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Splits the map into the entries with keys less than `key`, the entry with `key` if there
+    /// is one, and the entries with greater keys.
+    pub fn split<Q>(mut self, key: &Q) -> (Self, Option<(K, V)>, Self)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (greater, entry) = match self.search(key) {
+            Ok(index) => {
+                let mut greater = self.entries.split_off(index);
+                let entry = greater.remove(0);
+                (greater, Some(entry))
+            }
+            Err(index) => (self.entries.split_off(index), None),
+        };
+        (self, entry, Self { entries: greater })
+    }
+
+    /// Joins `left`, the entry `key`, `value`, and `right` into one map.
+    ///
+    /// # Panics
+    ///
+    /// If the keys of `left` are not all less than `key`, or those of `right` not all greater.
+    pub fn join(mut left: Self, key: K, value: V, right: Self) -> Self {
+        assert!(left.entries.last().is_none_or(|(last, _)| *last < key));
+        assert!(right.entries.first().is_none_or(|(first, _)| key < *first));
+        left.entries.push((key, value));
+        left.entries.extend(right.entries);
+        left
+    }
+}
+
+impl<K, V> Default for VecMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for VecMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for VecMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for VecMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = Self::new();
+        map.extend(entries);
+        map
+    }
+}
+
+/// The maps [`random_updates`] drives, keyed by `u64` and holding the round each entry was
+/// inserted in.
+#[cfg(all(test, feature = "bst"))]
+pub(crate) trait UpdatableMap {
+    fn insert(&mut self, key: u64, value: usize) -> Option<usize>;
+    fn remove(&mut self, key: &u64) -> Option<usize>;
+    fn len(&self) -> usize;
+}
+
+#[cfg(all(test, feature = "bst"))]
+macro_rules! updatable_map {
+    ($($map:ty),*) => {$(
+        impl UpdatableMap for $map {
+            fn insert(&mut self, key: u64, value: usize) -> Option<usize> {
+                <$map>::insert(self, key, value)
+            }
+
+            fn remove(&mut self, key: &u64) -> Option<usize> {
+                <$map>::remove(self, key)
+            }
+
+            fn len(&self) -> usize {
+                <$map>::len(self)
+            }
+        }
+    )*};
+}
+
+#[cfg(all(test, feature = "bst"))]
+updatable_map!(
+    crate::BstMap<u64, usize>,
+    crate::WeightBalancedMap<u64, usize>,
+    crate::aa_tree::AaTreeMap<u64, usize>,
+    crate::lazy_map::LazyBstMap<u64, usize>
+);
+
+/// Makes `rounds` random updates of keys below `keys`, every third a removal, to both `map` and a
+/// [`VecMap`], checking that they agree and handing the map to `check` after each. Returns the
+/// model for the caller to compare further.
+#[cfg(all(test, feature = "bst"))]
+pub(crate) fn random_updates<M: UpdatableMap>(
+    map: &mut M,
+    seed: u64,
+    rounds: usize,
+    keys: u64,
+    mut check: impl FnMut(&M),
+) -> VecMap<u64, usize> {
+    let mut random = crate::lcg(seed);
+    let mut model = VecMap::new();
+    for round in 0..rounds {
+        let key = random(keys);
+        if round % 3 == 2 {
+            assert_eq!(map.remove(&key), model.remove(&key), "removing {key}");
+        } else {
+            assert_eq!(
+                map.insert(key, round),
+                model.insert(key, round),
+                "inserting {key}"
+            );
+        }
+        assert_eq!(map.len(), model.len());
+        check(map);
+    }
+    model
+}
+
+#[cfg(all(test, feature = "bst"))]
+mod tests {
+    use super::*;
+    use crate::WeightBalancedMap;
+
+    #[test]
+    fn agrees_with_the_maps() {
+        let mut map = WeightBalancedMap::new();
+        let model = random_updates(&mut map, 5, 3000, 400, |_| ());
+        assert!(model.iter().eq(map.iter()));
+        assert!(model.iter().rev().eq(map.iter().rev()));
+
+        let (less, entry, greater) = model.clone().split(&200);
+        let (map_less, map_entry, map_greater) = map.split(&200);
+        assert_eq!(entry, map_entry);
+        assert!(less.iter().eq(map_less.iter()));
+        assert!(greater.iter().eq(map_greater.iter()));
+        let (key, value) = entry.unwrap_or((200, 0));
+        let joined = VecMap::join(less, key, value, greater);
+        assert_eq!(
+            joined.len(),
+            model.len() + usize::from(!model.contains_key(&200))
+        );
+        assert_eq!(joined.get(&200), Some(&value));
+    }
+}
@@ -0,0 +1,165 @@
+//! An ordered map allowing many values under a key, kept as a [`BstMap`] of queues.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+
+use crate::BstMap;
+
+/// An ordered map from keys to any number of values each, kept in the order they were inserted
+/// in.
+pub struct BstMultiMap<K, V> {
+    map: BstMap<K, VecDeque<V>>,
+    len: usize,
+}
+
+impl<K, V> BstMultiMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            map: BstMap::new(),
+            len: 0,
+        }
+    }
+
+    /// The number of values, counting every value under a key.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of distinct keys.
+    pub fn keys_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Iterates over all entries in key order, the values under a key in insertion order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        self.map
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+    }
+}
+
+impl<K: Ord, V> BstMultiMap<K, V> {
+    /// Adds `value` under `key`, after any values already there.
+    pub fn insert(&mut self, key: K, value: V) {
+        match self.map.get_mut(&key) {
+            Some(values) => values.push_back(value),
+            None => {
+                self.map.insert(key, VecDeque::from([value]));
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Iterates over the values under `key` in insertion order.
+    pub fn get_all<Q>(&self, key: &Q) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key).map(VecDeque::iter).unwrap_or_default()
+    }
+
+    /// The number of values under `key`.
+    pub fn count<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get(key).map_or(0, VecDeque::len)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Removes the value inserted first of those under `key`.
+    pub fn remove_one<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let values = self.map.get_mut(key)?;
+        let value = values.pop_front();
+        if values.is_empty() {
+            self.map.remove(key);
+        }
+        self.len -= 1;
+        value
+    }
+
+    /// Removes all values under `key`, returning them in insertion order.
+    pub fn remove_all<Q>(&mut self, key: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let values: Vec<V> = self.map.remove(key).map(Vec::from).unwrap_or_default();
+        self.len -= values.len();
+        values
+    }
+}
+
+impl<K, V> Default for BstMultiMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for BstMultiMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.map.iter()).finish()
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for BstMultiMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for BstMultiMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = Self::new();
+        map.extend(entries);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn duplicate_keys() {
+        let mut map: BstMultiMap<_, _> = [(2, 'a'), (1, 'b'), (2, 'c'), (3, 'd'), (2, 'e')]
+            .into_iter()
+            .collect();
+        assert_eq!((map.len(), map.keys_len()), (5, 3));
+        assert!(map.get_all(&2).eq(&['a', 'c', 'e']));
+        assert_eq!(map.get_all(&2).next_back(), Some(&'e'));
+        assert_eq!(map.count(&4), 0);
+        let entries: Vec<_> = map.iter().map(|(&key, &value)| (key, value)).collect();
+        assert_eq!(entries, [(1, 'b'), (2, 'a'), (2, 'c'), (2, 'e'), (3, 'd')]);
+
+        assert_eq!(map.remove_one(&2), Some('a'));
+        assert_eq!(map.remove_one(&1), Some('b'));
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.remove_one(&1), None);
+        assert_eq!(map.remove_all(&2), vec!['c', 'e']);
+        assert!(map.remove_all(&2).is_empty());
+        assert_eq!((map.len(), map.keys_len()), (1, 1));
+    }
+}
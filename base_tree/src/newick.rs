@@ -0,0 +1,268 @@
+//! The Newick format for phylogenetic trees, such as `((A:0.1,B:0.2)AB:0.3,C);`, restricted to
+//! binary trees.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display, Write};
+use core::iter::Peekable;
+use core::str::CharIndices;
+
+use crate::{Node, Tree};
+
+/// The payload of a Newick tree: a (possibly empty) name, and the length of the branch up to the
+/// parent, if given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Clade {
+    pub name: String,
+    pub length: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A character in a place it can't be, at the given byte offset.
+    UnexpectedToken(usize),
+    /// The branch length starting at the given byte offset could not be parsed.
+    InvalidLength(usize),
+    /// The parenthesis at the given byte offset has no partner.
+    UnmatchedParenthesis(usize),
+    /// The clade closed at the given byte offset has more than two children.
+    TooManyChildren(usize),
+    UnexpectedEnd,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken(at) => write!(f, "unexpected token at offset {at}"),
+            ParseError::InvalidLength(at) => write!(f, "invalid branch length at offset {at}"),
+            ParseError::UnmatchedParenthesis(at) => {
+                write!(f, "unmatched parenthesis at offset {at}")
+            }
+            ParseError::TooManyChildren(at) => {
+                write!(f, "clade closed at offset {at} has more than two children")
+            }
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of tree"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Characters that end an unquoted name.
+const DELIMITERS: &str = "()[]':;,";
+
+/// Parses a tree in Newick format. Names may be quoted, as in `'a name'`, with `''` standing for a
+/// quote; comments in brackets are not supported. A clade with a single child gets it on the
+/// left. The closing `;` is optional.
+pub fn parse(input: &str) -> Result<Tree<Clade>, ParseError> {
+    let mut chars = input.char_indices().peekable();
+    skip_whitespace(&mut chars);
+    if chars.peek().is_none() {
+        return Err(ParseError::UnexpectedEnd);
+    }
+
+    // The children read so far of each clade still open, with the offset of its parenthesis.
+    let mut open: Vec<(usize, Vec<Tree<Clade>>)> = Vec::new();
+    let tree = 'parse: loop {
+        skip_whitespace(&mut chars);
+        if let Some(&(at, '(')) = chars.peek() {
+            chars.next();
+            open.push((at, Vec::new()));
+            continue;
+        }
+        let mut subtree = Tree::with_root(clade(input, &mut chars)?);
+
+        // Close every clade that ends right after this subtree.
+        loop {
+            skip_whitespace(&mut chars);
+            match chars.peek().copied() {
+                Some((at, ',')) => {
+                    let (_, children) = open.last_mut().ok_or(ParseError::UnexpectedToken(at))?;
+                    if !children.is_empty() {
+                        return Err(ParseError::TooManyChildren(at));
+                    }
+                    children.push(subtree);
+                    chars.next();
+                    continue 'parse;
+                }
+                Some((at, ')')) => {
+                    let (_, mut children) =
+                        open.pop().ok_or(ParseError::UnmatchedParenthesis(at))?;
+                    chars.next();
+                    children.push(subtree);
+                    let right = if children.len() == 2 {
+                        children.pop()
+                    } else {
+                        None
+                    };
+                    let left = children.pop().unwrap_or_default();
+                    subtree =
+                        Tree::join(left, clade(input, &mut chars)?, right.unwrap_or_default());
+                }
+                _ => break 'parse subtree,
+            }
+        }
+    };
+
+    if let Some((at, _)) = open.last() {
+        return Err(ParseError::UnmatchedParenthesis(*at));
+    }
+    if let Some(&(_, ';')) = chars.peek() {
+        chars.next();
+    }
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        Some((at, _)) => Err(ParseError::UnexpectedToken(at)),
+        None => Ok(tree),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<CharIndices<'_>>) {
+    while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+}
+
+/// Reads the name and branch length making up the label of a clade, either of which may be
+/// missing.
+fn clade(input: &str, chars: &mut Peekable<CharIndices<'_>>) -> Result<Clade, ParseError> {
+    skip_whitespace(chars);
+    let mut name = String::new();
+    if let Some(&(start, '\'')) = chars.peek() {
+        chars.next();
+        loop {
+            match chars.next() {
+                Some((_, '\'')) if chars.next_if(|&(_, c)| c == '\'').is_some() => name.push('\''),
+                Some((_, '\'')) => break,
+                Some((_, c)) => name.push(c),
+                None => return Err(ParseError::UnexpectedToken(start)),
+            }
+        }
+    } else {
+        while let Some((_, c)) =
+            chars.next_if(|&(_, c)| !c.is_whitespace() && !DELIMITERS.contains(c))
+        {
+            name.push(c);
+        }
+    }
+
+    skip_whitespace(chars);
+    let mut length = None;
+    if chars.next_if(|&(_, c)| c == ':').is_some() {
+        skip_whitespace(chars);
+        let start = chars.peek().map_or(input.len(), |&(at, _)| at);
+        let mut end = start;
+        while let Some((at, c)) =
+            chars.next_if(|&(_, c)| !c.is_whitespace() && !DELIMITERS.contains(c))
+        {
+            end = at + c.len_utf8();
+        }
+        let parsed = input[start..end]
+            .parse()
+            .map_err(|_| ParseError::InvalidLength(start))?;
+        length = Some(parsed);
+    }
+    Ok(Clade { name, length })
+}
+
+/// Prints the tree rooted at `root` in Newick format, ending with `;`. Names are quoted where
+/// needed. A lone child is printed as the only member of its clade, whichever side it is on.
+pub fn to_newick(root: &Node<Clade>) -> String {
+    enum Work<'a> {
+        Node(&'a Node<Clade>),
+        Text(&'static str),
+        Label(&'a Clade),
+    }
+
+    let mut out = String::new();
+    let mut stack = vec![Work::Text(";"), Work::Node(root)];
+    while let Some(work) = stack.pop() {
+        let node = match work {
+            Work::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Work::Label(clade) => {
+                write_label(&mut out, clade);
+                continue;
+            }
+            Work::Node(node) => node,
+        };
+        match node.children() {
+            (None, None) => write_label(&mut out, node.get()),
+            (Some(only), None) | (None, Some(only)) => {
+                stack.extend([Work::Label(node.get()), Work::Text(")"), Work::Node(only)]);
+                out.push('(');
+            }
+            (Some(left), Some(right)) => {
+                stack.extend([
+                    Work::Label(node.get()),
+                    Work::Text(")"),
+                    Work::Node(right),
+                    Work::Text(","),
+                    Work::Node(left),
+                ]);
+                out.push('(');
+            }
+        }
+    }
+    out
+}
+
+fn write_label(out: &mut String, clade: &Clade) {
+    let plain = !clade
+        .name
+        .chars()
+        .any(|c| c.is_whitespace() || DELIMITERS.contains(c));
+    if plain {
+        out.push_str(&clade.name);
+    } else {
+        out.push('\'');
+        out.push_str(&clade.name.replace('\'', "''"));
+        out.push('\'');
+    }
+    if let Some(length) = clade.length {
+        let _ = write!(out, ":{length}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tree: &Tree<Clade>) -> Vec<String> {
+        let root = tree.root().unwrap();
+        root.iter(crate::Order::Pre)
+            .map(|node| node.get().name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parse_and_print() {
+        let tree = parse(" ((A:0.1, 'B c''d':2)AB:0.3,(C))root;\n").unwrap();
+        assert_eq!(names(&tree), ["root", "AB", "A", "B c'd", "", "C"]);
+        let root = tree.root().unwrap();
+        let ab = root.left().unwrap();
+        assert_eq!(ab.get().length, Some(0.3));
+        assert_eq!(ab.right().unwrap().get().length, Some(2.0));
+        assert!(root.right().unwrap().right().is_none());
+
+        let printed = to_newick(root);
+        assert_eq!(printed, "((A:0.1,'B c''d':2)AB:0.3,(C))root;");
+        assert!(parse(&printed).unwrap() == tree);
+        assert_eq!(to_newick(parse("(,);").unwrap().root().unwrap()), "(,);");
+
+        for (input, error) in [
+            ("", ParseError::UnexpectedEnd),
+            ("(A,B,C);", ParseError::TooManyChildren(4)),
+            ("((A,B);", ParseError::UnmatchedParenthesis(0)),
+            ("(A,B));", ParseError::UnmatchedParenthesis(5)),
+            ("(A:x,B);", ParseError::InvalidLength(3)),
+            ("A,B", ParseError::UnexpectedToken(1)),
+            ("(A,B) C D;", ParseError::UnexpectedToken(8)),
+            ("'A", ParseError::UnexpectedToken(0)),
+        ] {
+            assert_eq!(parse(input).err(), Some(error), "{input}");
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::num::NonZeroU64;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Augment, Node, Tree};
+
+// The last identity handed out. Identities are only minted when first asked for, so that nodes
+// can still be made in constants.
+static MINTED: AtomicU64 = AtomicU64::new(0);
+
+/// The identity of a node, as given by [`Node::id`]. It stays the same as long as the node
+/// exists, wherever it is moved in its tree, and is never given to another node, even after
+/// this one is freed. It belongs to the node and not to its payload, which operations like
+/// [`Node::swap_data`] move between nodes.
+///
+/// Identities are ordered, but not by when their nodes were created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(NonZeroU64);
+
+impl NodeId {
+    /// The identity kept in `slot`, minting one if it is still 0.
+    pub(crate) fn of(slot: &AtomicU64) -> Self {
+        let mut id = slot.load(Ordering::Relaxed);
+        if id == 0 {
+            let minted = MINTED.fetch_add(1, Ordering::Relaxed) + 1;
+            id = match slot.compare_exchange(0, minted, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => minted,
+                // Another thread asked first, and the identity it minted goes unused.
+                Err(existing) => existing,
+            };
+        }
+        Self(NonZeroU64::new(id).expect("node identities ran out"))
+    }
+}
+
+/// A handle to a node, as given by [`Node::handle`], that can be kept without borrowing the tree
+/// and later looked up in it again. Looking it up finds nothing once the node is freed or moved
+/// to another tree.
+pub struct NodeRef<T> {
+    id: NodeId,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> NodeRef<T> {
+    pub(crate) fn new(id: NodeId) -> Self {
+        Self {
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(self) -> NodeId {
+        self.id
+    }
+
+    /// Finds the node in `tree`. This searches the whole tree, so it takes O(n).
+    pub fn resolve(self, tree: &Tree<T>) -> Option<&Node<T>> {
+        self.resolve_in(tree.root()?)
+    }
+
+    /// Finds the node in the subtree rooted at `root`, taking time linear in its size.
+    pub fn resolve_in<A: Augment<T>>(self, root: &Node<T, A>) -> Option<&Node<T, A>> {
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if node.id() == self.id {
+                return Some(node);
+            }
+            stack.extend(node.right());
+            stack.extend(node.left());
+        }
+        None
+    }
+}
+
+impl<T> Clone for NodeRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeRef<T> {}
+
+impl<T> PartialEq for NodeRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for NodeRef<T> {}
+
+impl<T> Hash for NodeRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> Debug for NodeRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NodeRef").field(&self.id).finish()
+    }
+}
+
+/// Data attached to nodes from the outside, by their [`NodeId`], leaving their payloads and the
+/// tree as they are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlay<V> {
+    entries: BTreeMap<NodeId, V>,
+}
+
+impl<V> Overlay<V> {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&V> {
+        self.entries.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut V> {
+        self.entries.get_mut(&id)
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    /// Attaches `value` to the node `id`, returning what was attached to it before.
+    pub fn insert(&mut self, id: NodeId, value: V) -> Option<V> {
+        self.entries.insert(id, value)
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<V> {
+        self.entries.remove(&id)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (NodeId, &V)> + ExactSizeIterator {
+        self.entries.iter().map(|(&id, value)| (id, value))
+    }
+
+    /// Drops the data of every node that is not in the subtree rooted at `root`, such as nodes
+    /// that have been removed from the tree since.
+    pub fn retain_subtree<T, A: Augment<T>>(&mut self, root: &Node<T, A>) {
+        let mut kept = BTreeMap::new();
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if let Some((id, value)) = self.entries.remove_entry(&node.id()) {
+                kept.insert(id, value);
+            }
+            stack.extend(node.left());
+            stack.extend(node.right());
+        }
+        self.entries = kept;
+    }
+}
+
+impl<V> Default for Overlay<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Order, Shape, Side, Tree};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn identities_and_overlay() {
+        let mut tree = Tree::from_shape(0..7, Shape::Balanced, |_| 0);
+        let ids: Vec<NodeId> = tree.root().unwrap().iter(Order::In).map(Node::id).collect();
+        let mut distinct = ids.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 7);
+
+        let mut overlay = Overlay::new();
+        for (id, n) in ids.iter().zip(0..) {
+            overlay.insert(*id, n * 100);
+        }
+        // Safety: rotations only move nodes around within the tree.
+        unsafe { tree.root_mut() }.unwrap().rotate_right().unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(*root.get(), 1);
+        let moved: Vec<NodeId> = root.iter(Order::In).map(Node::id).collect();
+        assert_eq!(moved, ids);
+        assert_eq!(overlay.get(root.id()), Some(&100));
+
+        // Safety: the detached subtree is freed by a tree of its own.
+        let detached = unsafe { tree.root_mut() }
+            .unwrap()
+            .try_detach(&[Side::Left])
+            .unwrap();
+        drop(unsafe { Tree::from_root_ptr(Some(core::ptr::NonNull::from(detached))) });
+        overlay.retain_subtree(tree.root().unwrap());
+        assert_eq!(overlay.len(), 6);
+        assert!(!overlay.contains(ids[0]));
+
+        // A handle outlives the borrow it was taken from, and stops resolving with the node.
+        let handle = tree.root().unwrap().right().unwrap().handle();
+        assert_eq!(handle.resolve(&tree).map(Node::get), Some(&3));
+        // Safety: as above.
+        let detached = unsafe { tree.root_mut() }
+            .unwrap()
+            .try_detach(&[Side::Right])
+            .unwrap();
+        let subtree = unsafe { Tree::from_root_ptr(Some(core::ptr::NonNull::from(detached))) };
+        assert!(handle.resolve(&tree).is_none());
+        assert_eq!(handle.resolve(&subtree).map(Node::get), Some(&3));
+
+        // Identities are never handed out twice, even where memory is reused.
+        let reused = Tree::with_root(0);
+        assert!(!ids.contains(&reused.root().unwrap().id()));
+        #[allow(clippy::declare_interior_mutable_const)]
+        const NODE: Node<u8> = Node::new(1);
+        let node = NODE;
+        assert_eq!(node.id(), node.id());
+    }
+}
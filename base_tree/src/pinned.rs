@@ -0,0 +1,146 @@
+use core::marker::PhantomPinned;
+use core::ops::Deref;
+use core::pin::Pin;
+
+use crate::{Augment, DataMut, Node, Side, TreeError};
+
+/// A [`Node`] for linking in place: once pinned it can't be moved, and dropping it unlinks it from
+/// its parent and children, so the links between pinned nodes always lead to live nodes. It reads
+/// as the node it holds, and is only linked to other pinned nodes.
+pub struct PinnedNode<T, A: Augment<T> = ()> {
+    node: Node<T, A>,
+    _pinned: PhantomPinned,
+}
+
+impl<T> PinnedNode<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            node: Node::new(data),
+            _pinned: PhantomPinned,
+        }
+    }
+}
+
+impl<T, A: Augment<T>> PinnedNode<T, A> {
+    /// Creates a node keeping the bookkeeping described by `A`, as [`Node::augmented`] does.
+    pub fn augmented(data: T) -> Self {
+        Self {
+            node: Node::augmented(data),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Hangs `child` below this node on `side`, refusing as [`Node::try_attach`] does. The link
+    /// lasts until either node is detached or dropped.
+    pub fn attach(
+        self: Pin<&mut Self>,
+        side: Side,
+        child: Pin<&mut Self>,
+    ) -> Result<(), TreeError> {
+        // Safety: linking moves neither node.
+        let (parent, child) = unsafe { (self.get_unchecked_mut(), child.get_unchecked_mut()) };
+        parent.node.try_attach(side, &mut child.node)
+    }
+
+    /// Cuts off the child of this node on `side`, returning whether there was one. The child stays
+    /// pinned where it is, with whoever pinned it.
+    pub fn detach(self: Pin<&mut Self>, side: Side) -> bool {
+        // Safety: unlinking moves no node.
+        let node = &mut unsafe { self.get_unchecked_mut() }.node;
+        match side {
+            Side::Left => node.replace_left(None),
+            Side::Right => node.replace_right(None),
+        }
+        .is_some()
+    }
+
+    /// The payload of this node, which is not pinned itself, as [`Node::get_mut`] gives it.
+    pub fn get_pinned_mut(self: Pin<&mut Self>) -> DataMut<'_, T, A> {
+        // Safety: the payload is never treated as pinned, and handing it out moves no node.
+        unsafe { self.get_unchecked_mut() }.node.get_mut()
+    }
+}
+
+impl<T, A: Augment<T>> Deref for PinnedNode<T, A> {
+    type Target = Node<T, A>;
+
+    fn deref(&self) -> &Node<T, A> {
+        &self.node
+    }
+}
+
+impl<T, A: Augment<T>> Drop for PinnedNode<T, A> {
+    fn drop(&mut self) {
+        // Every neighbor is a pinned node as well, which would have unlinked itself had it been
+        // dropped first, so the links all still lead to live nodes.
+        self.node.detach_from_parent();
+        for child in [self.node.left.take(), self.node.right.take()]
+            .into_iter()
+            .flatten()
+        {
+            // Safety: as above, and a mutable reference to a node means exclusive access to its
+            // children.
+            unsafe { (*child.as_ptr()).parent = None };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Order, SizeAug};
+    use alloc::vec::Vec;
+    use core::pin::pin;
+
+    #[test]
+    fn linked_in_place() {
+        let mut root = pin!(PinnedNode::<_, SizeAug>::augmented(0));
+        let mut left = pin!(PinnedNode::augmented(1));
+        let mut grandchild = pin!(PinnedNode::augmented(2));
+        left.as_mut()
+            .attach(Side::Right, grandchild.as_mut())
+            .unwrap();
+        root.as_mut().attach(Side::Left, left.as_mut()).unwrap();
+        assert_eq!(
+            root.as_mut().attach(Side::Left, grandchild.as_mut()),
+            Err(TreeError::SlotOccupied)
+        );
+        *grandchild.as_mut().get_pinned_mut() = 20;
+        let order: Vec<_> = root.iter(Order::In).map(|node| *node.get()).collect();
+        assert_eq!(order, [1, 20, 0]);
+        assert_eq!(root.size(), 3);
+
+        assert!(root.as_mut().detach(Side::Left));
+        assert!(!root.as_mut().detach(Side::Left));
+        assert_eq!(root.size(), 1);
+        assert!(left.parent().is_none());
+    }
+
+    #[test]
+    fn dropping_unlinks() {
+        let mut root = pin!(PinnedNode::<_, SizeAug>::augmented(0));
+        {
+            let mut child = pin!(PinnedNode::augmented(1));
+            let mut grandchild = pin!(PinnedNode::augmented(2));
+            child
+                .as_mut()
+                .attach(Side::Right, grandchild.as_mut())
+                .unwrap();
+            root.as_mut().attach(Side::Left, child.as_mut()).unwrap();
+            assert_eq!(root.size(), 3);
+        }
+        assert!(root.left().is_none());
+        assert_eq!(root.size(), 1);
+
+        // A parent going first leaves its child a root.
+        let mut child = pin!(PinnedNode::<_, SizeAug>::augmented(1));
+        {
+            let mut parent = pin!(PinnedNode::augmented(0));
+            parent.as_mut().attach(Side::Right, child.as_mut()).unwrap();
+            assert_eq!(child.parent().map(|node| *node.get()), Some(0));
+        }
+        assert!(child.parent().is_none());
+        root.as_mut().attach(Side::Right, child.as_mut()).unwrap();
+        assert_eq!(root.size(), 2);
+    }
+}
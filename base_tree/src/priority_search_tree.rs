@@ -0,0 +1,147 @@
+//! Priority search trees, answering three sided range queries over (key, priority) points.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
+use core::ptr::NonNull;
+
+use crate::tree::alloc;
+use crate::{Node, Tree};
+
+struct Entry<K, P, V> {
+    key: K,
+    priority: P,
+    value: V,
+    // Everything in the left subtree has a key of at most this, everything in the right subtree
+    // at least this.
+    split: K,
+}
+
+/// A static set of points, each a key and a priority with a value attached, able to report every
+/// point with its key in a range and its priority above a threshold in O(log n + k). The tree is
+/// a heap by priority, and a search tree by key: each node holds the point of highest priority
+/// among its subtree, and the remaining points are split evenly by key between its children.
+pub struct PrioritySearchTree<K, P, V> {
+    tree: Tree<Entry<K, P, V>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone, P: Ord, V> PrioritySearchTree<K, P, V> {
+    /// Builds the tree in O(n log n).
+    pub fn from_points(points: impl IntoIterator<Item = (K, P, V)>) -> Self {
+        let mut points: Vec<_> = points.into_iter().collect();
+        points.sort_by(|a, b| a.0.cmp(&b.0));
+        let len = points.len();
+
+        let mut root = None;
+        let mut stack = vec![(points, None::<(NonNull<Node<_>>, bool)>)];
+        while let Some((mut points, parent)) = stack.pop() {
+            let Some(top) = (0..points.len()).max_by_key(|&i| &points[i].1) else {
+                continue;
+            };
+            let (key, priority, value) = points.remove(top);
+            let right = points.split_off(points.len().div_ceil(2));
+            let split = points
+                .last()
+                .map_or_else(|| key.clone(), |point| point.0.clone());
+
+            let ptr = alloc(Entry {
+                key,
+                priority,
+                value,
+                split,
+            });
+            // Safety: the node was just allocated, and is linked below a single parent.
+            match parent {
+                None => root = Some(ptr),
+                Some((parent, true)) => unsafe { (*parent.as_ptr()).left = Some(ptr) },
+                Some((parent, false)) => unsafe { (*parent.as_ptr()).right = Some(ptr) },
+            }
+            unsafe { (*ptr.as_ptr()).parent = parent.map(|(parent, _)| parent) };
+            stack.push((points, Some((ptr, true))));
+            stack.push((right, Some((ptr, false))));
+        }
+
+        Self {
+            tree: unsafe { Tree::from_root_ptr(root) },
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// All points with a key in `keys` and a priority of at least `min_priority`.
+    pub fn query(&self, keys: impl RangeBounds<K>, min_priority: &P) -> Vec<(&K, &P, &V)> {
+        let mut found = Vec::new();
+        let mut stack: Vec<_> = self.tree.root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let entry = node.get();
+            // Priorities only decrease going down, so nothing below qualifies either.
+            if entry.priority < *min_priority {
+                continue;
+            }
+            if keys.contains(&entry.key) {
+                found.push((&entry.key, &entry.priority, &entry.value));
+            }
+            let go_left = match keys.start_bound() {
+                Bound::Included(start) => *start <= entry.split,
+                Bound::Excluded(start) => *start < entry.split,
+                Bound::Unbounded => true,
+            };
+            let go_right = match keys.end_bound() {
+                Bound::Included(end) => entry.split <= *end,
+                Bound::Excluded(end) => entry.split < *end,
+                Bound::Unbounded => true,
+            };
+            stack.extend(node.left().filter(|_| go_left));
+            stack.extend(node.right().filter(|_| go_right));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut random = crate::lcg(11);
+        // Few distinct keys, so that plenty of them are tied.
+        let points: Vec<_> = (0..400).map(|i| (random(100), random(1000), i)).collect();
+        let tree = PrioritySearchTree::from_points(points.clone());
+        assert_eq!(tree.len(), 400);
+
+        for _ in 0..200 {
+            let (a, b, min) = (random(110), random(110), random(1000));
+            let (low, high) = (a.min(b), a.max(b));
+            let mut found: Vec<_> = tree
+                .query(low..high, &min)
+                .into_iter()
+                .map(|(_, _, &i)| i)
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<_> = points
+                .iter()
+                .filter(|&&(key, priority, _)| (low..high).contains(&key) && priority >= min)
+                .map(|&(_, _, i)| i)
+                .collect();
+            assert_eq!(found, expected);
+
+            let count = points
+                .iter()
+                .filter(|&&(key, priority, _)| key >= low && priority >= min)
+                .count();
+            assert_eq!(tree.query(low.., &min).len(), count);
+        }
+
+        let empty = PrioritySearchTree::<i32, i32, ()>::from_points([]);
+        assert!(empty.query(.., &0).is_empty());
+    }
+}
@@ -0,0 +1,504 @@
+//! A text rope: a string kept as a balanced tree of small chunks, so edits anywhere in it take
+//! O(log n) instead of moving the whole text.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use core::ops::RangeBounds;
+use core::ptr::NonNull;
+
+use crate::tree::{alloc, free_subtree, leftmost, successor, unlink};
+use crate::{bounds, Augment, Node};
+
+/// Chunks are split up once they would grow beyond this many bytes.
+const MAX_CHUNK: usize = 64;
+
+#[derive(Clone, Copy, Default)]
+struct Lengths {
+    bytes: usize,
+    chars: usize,
+    height: usize,
+}
+
+/// Keeps the lengths of the text below every node, and its height, for AVL balancing.
+struct TextLengths;
+
+impl Augment<String> for TextLengths {
+    type Summary = Lengths;
+
+    fn combine(left: Option<&Lengths>, data: &String, right: Option<&Lengths>) -> Lengths {
+        let left = left.copied().unwrap_or_default();
+        let right = right.copied().unwrap_or_default();
+        Lengths {
+            bytes: left.bytes + data.len() + right.bytes,
+            chars: left.chars + data.chars().count() + right.chars,
+            height: 1 + left.height.max(right.height),
+        }
+    }
+}
+
+type Chunk = Node<String, TextLengths>;
+
+#[derive(Default)]
+pub struct Rope {
+    // The in-order concatenation of the chunks is the text. No chunk is empty.
+    root: Option<NonNull<Chunk>>,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        lengths(self.root).bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        lengths(self.root).chars
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn char(&self, char_idx: usize) -> Option<char> {
+        let (ptr, offset) = self.find_char(char_idx)?;
+        unsafe { ptr.as_ref() }.get().chars().nth(offset)
+    }
+
+    pub fn byte(&self, byte_idx: usize) -> Option<u8> {
+        let (ptr, offset) = self.find_byte(byte_idx)?;
+        unsafe { ptr.as_ref() }
+            .get()
+            .as_bytes()
+            .get(offset)
+            .copied()
+    }
+
+    /// The byte offset of the character at `char_idx`, which may be one past the end.
+    pub fn char_to_byte(&self, char_idx: usize) -> Option<usize> {
+        if char_idx == self.len_chars() {
+            return Some(self.len_bytes());
+        }
+        let (ptr, offset) = self.find_char(char_idx)?;
+        let chunk = unsafe { ptr.as_ref() }.get();
+        Some(self.offset_of(ptr, |lengths| lengths.bytes, String::len) + byte_offset(chunk, offset))
+    }
+
+    /// The index of the character containing the byte at `byte_idx`, which may be one past the
+    /// end.
+    pub fn byte_to_char(&self, byte_idx: usize) -> Option<usize> {
+        if byte_idx == self.len_bytes() {
+            return Some(self.len_chars());
+        }
+        let (ptr, offset) = self.find_byte(byte_idx)?;
+        let chunk = unsafe { ptr.as_ref() }.get();
+        let chars = chunk
+            .char_indices()
+            .take_while(|&(at, _)| at <= offset)
+            .count()
+            - 1;
+        Some(self.offset_of(ptr, |lengths| lengths.chars, |chunk| chunk.chars().count()) + chars)
+    }
+
+    /// Inserts `text` before the character at `char_idx`.
+    ///
+    /// # Panics
+    ///
+    /// If `char_idx` is beyond the end of the rope.
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        assert!(char_idx <= self.len_chars(), "char index out of bounds");
+        if text.is_empty() {
+            return;
+        }
+        let mut pieces = split_chunks(text).into_iter();
+
+        let Some((ptr, offset)) = self.locate_insertion(char_idx) else {
+            let mut prev = None;
+            for piece in pieces {
+                prev = Some(unsafe { self.insert_after(prev, piece) });
+            }
+            return;
+        };
+
+        // Safety: we have exclusive access to every chunk through `&mut self`, and the reference
+        // ends before the tree is restructured.
+        let chunk = unsafe { &mut (*ptr.as_ptr()).data };
+        let at = byte_offset(chunk, offset);
+        if chunk.len() + text.len() <= MAX_CHUNK {
+            chunk.insert_str(at, text);
+            unsafe { Node::refresh_upwards(ptr) };
+            return;
+        }
+        let tail = chunk.split_off(at);
+        if chunk.is_empty() {
+            *chunk = pieces.next().unwrap_or_default();
+        }
+        unsafe { Node::refresh_upwards(ptr) };
+
+        let mut prev = ptr;
+        for piece in pieces.chain((!tail.is_empty()).then_some(tail)) {
+            prev = unsafe { self.insert_after(Some(prev), piece) };
+        }
+    }
+
+    /// Removes the characters in `range`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is decreasing, or ends beyond the end of the rope.
+    pub fn remove(&mut self, range: impl RangeBounds<usize>) {
+        let (start, end) = bounds(&range, self.len_chars());
+        assert!(start <= end, "range start is after its end");
+        assert!(end <= self.len_chars(), "range end out of bounds");
+
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let Some((ptr, offset)) = self.find_char(start) else {
+                break;
+            };
+            let chunk = unsafe { &mut (*ptr.as_ptr()).data };
+            let taken = remaining.min(chunk.chars().count() - offset);
+            let from = byte_offset(chunk, offset);
+            let to = from + byte_offset(&chunk[from..], taken);
+            chunk.replace_range(from..to, "");
+            remaining -= taken;
+            if chunk.is_empty() {
+                unsafe { self.delete(ptr) };
+            } else {
+                unsafe { Node::refresh_upwards(ptr) };
+            }
+        }
+    }
+
+    /// Copies out the characters in `range`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is decreasing, or ends beyond the end of the rope.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> String {
+        let (start, end) = bounds(&range, self.len_chars());
+        assert!(start <= end, "range start is after its end");
+        assert!(end <= self.len_chars(), "range end out of bounds");
+
+        let mut out = String::new();
+        let mut remaining = end - start;
+        let mut current = self.find_char(start);
+        while let (Some((ptr, offset)), true) = (current, remaining > 0) {
+            let chunk = unsafe { ptr.as_ref() }.get();
+            let piece: String = chunk.chars().skip(offset).take(remaining).collect();
+            remaining -= piece.chars().count();
+            out.push_str(&piece);
+            current = unsafe { successor(ptr) }.map(|next| (next, 0));
+        }
+        out
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = &str> {
+        let first = self.root.map(|root| unsafe { leftmost(root) });
+        core::iter::successors(first, |&ptr| unsafe { successor(ptr) })
+            .map(|ptr| unsafe { ptr.as_ref() }.get().as_str())
+    }
+
+    fn find_char(&self, char_idx: usize) -> Option<(NonNull<Chunk>, usize)> {
+        self.find(
+            char_idx,
+            |lengths| lengths.chars,
+            |chunk| chunk.chars().count(),
+        )
+    }
+
+    fn find_byte(&self, byte_idx: usize) -> Option<(NonNull<Chunk>, usize)> {
+        self.find(byte_idx, |lengths| lengths.bytes, String::len)
+    }
+
+    /// The chunk holding unit `index` of the metric, and the offset of the unit within it.
+    fn find(
+        &self,
+        mut index: usize,
+        metric: impl Fn(&Lengths) -> usize,
+        own: impl Fn(&String) -> usize,
+    ) -> Option<(NonNull<Chunk>, usize)> {
+        let mut ptr = self.root?;
+        loop {
+            let node = unsafe { ptr.as_ref() };
+            let left = node.left().map_or(0, |left| metric(left.summary()));
+            let here = own(node.get());
+            if index < left {
+                ptr = node.left?;
+            } else if index < left + here {
+                return Some((ptr, index - left));
+            } else {
+                index -= left + here;
+                ptr = node.right?;
+            }
+        }
+    }
+
+    /// Where to insert at `char_idx`: the end of the preceding chunk where there is one, so
+    /// appending grows existing chunks.
+    fn locate_insertion(&self, mut char_idx: usize) -> Option<(NonNull<Chunk>, usize)> {
+        let mut ptr = self.root?;
+        loop {
+            let node = unsafe { ptr.as_ref() };
+            let left = node.left().map_or(0, |left| left.summary().chars);
+            let here = node.get().chars().count();
+            if char_idx < left || (char_idx == left && left > 0) {
+                ptr = node.left?;
+            } else if char_idx <= left + here {
+                return Some((ptr, char_idx - left));
+            } else {
+                char_idx -= left + here;
+                ptr = node.right?;
+            }
+        }
+    }
+
+    /// How many units of the metric precede the chunk at `ptr`.
+    fn offset_of(
+        &self,
+        ptr: NonNull<Chunk>,
+        metric: impl Fn(&Lengths) -> usize,
+        own: impl Fn(&String) -> usize,
+    ) -> usize {
+        let node = unsafe { ptr.as_ref() };
+        let mut offset = node.left().map_or(0, |left| metric(left.summary()));
+        let mut child = node;
+        while let Some(parent) = child.parent() {
+            if parent
+                .right
+                .is_some_and(|right| core::ptr::eq(right.as_ptr(), child))
+            {
+                offset +=
+                    parent.left().map_or(0, |left| metric(left.summary())) + own(parent.get());
+            }
+            child = parent;
+        }
+        offset
+    }
+
+    /// Links a new chunk right after `prev` in the text, or first if `prev` is `None`, and
+    /// rebalances.
+    ///
+    /// # Safety
+    ///
+    /// `prev` must be a chunk of this rope.
+    unsafe fn insert_after(
+        &mut self,
+        prev: Option<NonNull<Chunk>>,
+        chunk: String,
+    ) -> NonNull<Chunk> {
+        let new = alloc(chunk);
+        let (parent, left) = match prev {
+            None => match self.root {
+                None => {
+                    self.root = Some(new);
+                    return new;
+                }
+                Some(root) => (unsafe { leftmost(root) }, true),
+            },
+            Some(prev) => match unsafe { prev.as_ref() }.right {
+                None => (prev, false),
+                Some(right) => (unsafe { leftmost(right) }, true),
+            },
+        };
+        unsafe {
+            if left {
+                (*parent.as_ptr()).left = Some(new);
+            } else {
+                (*parent.as_ptr()).right = Some(new);
+            }
+            (*new.as_ptr()).parent = Some(parent);
+            self.rebalance_from(Some(parent));
+        }
+        new
+    }
+
+    /// Unlinks and frees the chunk at `ptr`, and rebalances.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a chunk of this rope.
+    unsafe fn delete(&mut self, ptr: NonNull<Chunk>) {
+        unsafe {
+            let (_, parent) = unlink(&mut self.root, ptr);
+            self.rebalance_from(parent);
+        }
+    }
+
+    /// Restores the AVL balance and the summaries of `start` and all of its ancestors.
+    ///
+    /// # Safety
+    ///
+    /// `start` must be a chunk of this rope.
+    unsafe fn rebalance_from(&mut self, start: Option<NonNull<Chunk>>) {
+        let mut current = start;
+        while let Some(ptr) = current {
+            unsafe { Node::refresh(ptr) };
+            let node = unsafe { ptr.as_ref() };
+            let (left, right) = (height(node.left), height(node.right));
+            let mut top = ptr;
+            unsafe {
+                if left > right + 1 {
+                    let child = node.left.unwrap_or(ptr);
+                    if height(child.as_ref().left) < height(child.as_ref().right) {
+                        Node::rotate_left_raw(child);
+                    }
+                    top = Node::rotate_right_raw(ptr).unwrap_or(ptr);
+                } else if right > left + 1 {
+                    let child = node.right.unwrap_or(ptr);
+                    if height(child.as_ref().right) < height(child.as_ref().left) {
+                        Node::rotate_right_raw(child);
+                    }
+                    top = Node::rotate_left_raw(ptr).unwrap_or(ptr);
+                }
+            }
+            current = unsafe { top.as_ref() }.parent;
+            if current.is_none() {
+                self.root = Some(top);
+            }
+        }
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        let mut rope = Self::new();
+        rope.insert(0, text);
+        rope
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl Drop for Rope {
+    fn drop(&mut self) {
+        if let Some(root) = self.root {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+fn lengths(ptr: Option<NonNull<Chunk>>) -> Lengths {
+    ptr.map(|ptr| *unsafe { ptr.as_ref() }.summary())
+        .unwrap_or_default()
+}
+
+fn height(ptr: Option<NonNull<Chunk>>) -> usize {
+    lengths(ptr).height
+}
+
+/// The byte offset of character `char_idx` of `text`, which may be one past the end.
+fn byte_offset(text: &str, char_idx: usize) -> usize {
+    text.char_indices()
+        .nth(char_idx)
+        .map_or(text.len(), |(at, _)| at)
+}
+
+/// Cuts `text` into pieces of at most `MAX_CHUNK` bytes, along character boundaries.
+fn split_chunks(mut text: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    while !text.is_empty() {
+        let mut at = text.len().min(MAX_CHUNK);
+        while !text.is_char_boundary(at) {
+            at -= 1;
+        }
+        let (piece, rest) = text.split_at(at);
+        pieces.push(piece.to_owned());
+        text = rest;
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(rope: &Rope, model: &str) {
+        assert_eq!(rope.to_string(), model);
+        assert_eq!(rope.len_bytes(), model.len());
+        assert_eq!(rope.len_chars(), model.chars().count());
+        // AVL trees are never more than about 1.44 log2(n) high.
+        let chunks = rope.chunks().count();
+        assert!(height(rope.root) as f64 <= 1.45 * ((chunks + 2) as f64).log2());
+        assert!(rope.chunks().all(|chunk| !chunk.is_empty()));
+    }
+
+    #[test]
+    fn edits_match_string() {
+        let mut rope = Rope::new();
+        let mut model = String::new();
+        let mut lcg = crate::lcg(12345);
+        let (short, long) = ("abc", "long text ".repeat(20));
+        let mut random = |below: usize| lcg(below.max(1) as u64) as usize;
+
+        for round in 0..400 {
+            let chars = model.chars().count();
+            if round % 3 == 2 && chars > 0 {
+                let start = random(chars);
+                let end = start + random(chars - start + 1).min(150);
+                rope.remove(start..end);
+                let from = model
+                    .char_indices()
+                    .nth(start)
+                    .map_or(model.len(), |(at, _)| at);
+                let to = model
+                    .char_indices()
+                    .nth(end)
+                    .map_or(model.len(), |(at, _)| at);
+                model.replace_range(from..to, "");
+            } else {
+                let at = random(chars + 1);
+                let text = [short, "ünïcödé ", "x", &long][random(4)];
+                rope.insert(at, text);
+                let byte = model
+                    .char_indices()
+                    .nth(at)
+                    .map_or(model.len(), |(at, _)| at);
+                model.insert_str(byte, text);
+            }
+            check(&rope, &model);
+        }
+
+        let chars = model.chars().count();
+        for char_idx in (0..chars).step_by(7) {
+            assert_eq!(rope.char(char_idx), model.chars().nth(char_idx));
+            let byte = rope.char_to_byte(char_idx).unwrap();
+            assert_eq!(
+                Some(byte),
+                model.char_indices().nth(char_idx).map(|(at, _)| at)
+            );
+            assert_eq!(rope.byte_to_char(byte), Some(char_idx));
+            assert_eq!(rope.byte(byte), model.as_bytes().get(byte).copied());
+            let end = (char_idx + 100).min(chars);
+            assert_eq!(
+                rope.slice(char_idx..end),
+                model
+                    .chars()
+                    .skip(char_idx)
+                    .take(end - char_idx)
+                    .collect::<String>()
+            );
+        }
+        assert_eq!(rope.char(chars), None);
+        assert_eq!(rope.char_to_byte(chars), Some(model.len()));
+
+        rope.remove(..);
+        assert!(rope.is_empty());
+    }
+
+    #[test]
+    fn from_str() {
+        let rope = Rope::from("hello, wörld");
+        assert_eq!(rope.slice(7..), "wörld");
+        assert_eq!(rope.byte_to_char(8), Some(8));
+        assert_eq!(rope.byte_to_char(9), Some(8));
+        assert_eq!(rope.byte_to_char(10), Some(9));
+    }
+}
@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::{Augment, DataMut, Node, Side, TreeError};
+
+/// Borrowed nodes linked together for the length of a call to [`Node::scope`], which unlinks
+/// them all again before it returns, even if the closure panics. The nodes are only reached
+/// through their [`ScopedNode`] handles meanwhile, so none can be moved or dropped while another
+/// still points at it.
+pub struct Scope<'scope, 'env: 'scope, T, A: Augment<T> = ()> {
+    nodes: Vec<NonNull<Node<T, A>>>,
+    _marker: PhantomData<(&'scope mut &'env (), &'scope mut Node<T, A>)>,
+}
+
+/// A handle to a node lent to a [`Scope`], valid in that scope only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScopedNode<'scope> {
+    index: usize,
+    // Invariant, so that handles of one scope can't be given to another.
+    _marker: PhantomData<fn(&'scope ()) -> &'scope ()>,
+}
+
+impl<T, A: Augment<T>> Node<T, A> {
+    /// Calls `f` with a [`Scope`] to lend nodes to and link them in, and unlinks every node lent
+    /// to it once `f` returns.
+    pub fn scope<'env, R>(f: impl for<'scope> FnOnce(&mut Scope<'scope, 'env, T, A>) -> R) -> R {
+        let mut scope = Scope {
+            nodes: Vec::new(),
+            _marker: PhantomData,
+        };
+        f(&mut scope)
+    }
+}
+
+impl<'scope, T, A: Augment<T>> Scope<'scope, '_, T, A> {
+    /// Lends `node` to the scope for the rest of it, refusing one that is linked to any other.
+    pub fn add(&mut self, node: &'scope mut Node<T, A>) -> Result<ScopedNode<'scope>, TreeError> {
+        if node.parent.is_some() {
+            return Err(TreeError::DifferentTrees);
+        }
+        if node.left.is_some() || node.right.is_some() {
+            return Err(TreeError::SlotOccupied);
+        }
+        self.nodes.push(NonNull::from(node));
+        Ok(ScopedNode {
+            index: self.nodes.len() - 1,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Hangs `child` below `parent` on `side`, as [`Node::try_attach`] does.
+    pub fn attach(
+        &mut self,
+        parent: ScopedNode<'scope>,
+        side: Side,
+        child: ScopedNode<'scope>,
+    ) -> Result<(), TreeError> {
+        let (parent, child) = (self.nodes[parent.index], self.nodes[child.index]);
+        if parent == child {
+            return Err(TreeError::WouldCreateCycle);
+        }
+        // Safety: the scope has the only access to its nodes, which are distinct.
+        unsafe { (*parent.as_ptr()).try_attach(side, &mut *child.as_ptr()) }
+    }
+
+    /// Cuts off the child of `parent` on `side`, returning whether there was one.
+    pub fn detach(&mut self, parent: ScopedNode<'scope>, side: Side) -> bool {
+        // Safety: as above.
+        let node = unsafe { &mut *self.nodes[parent.index].as_ptr() };
+        match side {
+            Side::Left => node.replace_left(None),
+            Side::Right => node.replace_right(None),
+        }
+        .is_some()
+    }
+
+    pub fn get(&self, node: ScopedNode<'scope>) -> &Node<T, A> {
+        // Safety: the scope has the only access to its nodes, and is borrowed.
+        unsafe { self.nodes[node.index].as_ref() }
+    }
+
+    /// The payload of `node`, as [`Node::get_mut`] gives it.
+    pub fn get_mut(&mut self, node: ScopedNode<'scope>) -> DataMut<'_, T, A> {
+        // Safety: the scope is borrowed mutably, and links nothing to the nodes from outside.
+        unsafe { &mut *self.nodes[node.index].as_ptr() }.get_mut()
+    }
+}
+
+impl<T, A: Augment<T>> Drop for Scope<'_, '_, T, A> {
+    fn drop(&mut self) {
+        for &ptr in &self.nodes {
+            // Safety: the nodes are only linked to each other, and all unlinked together.
+            let node = unsafe { &mut *ptr.as_ptr() };
+            (node.left, node.right, node.parent) = (None, None, None);
+            node.summary = A::combine(None, &node.data, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Order, SizeAug};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::vec::Vec;
+
+    #[test]
+    fn links_end_with_the_scope() {
+        let mut nodes: Vec<Node<i32, SizeAug>> = (0..4).map(Node::augmented).collect();
+        let order = Node::scope(|scope| {
+            let handles: Vec<_> = nodes
+                .iter_mut()
+                .map(|node| scope.add(node).unwrap())
+                .collect();
+            scope.attach(handles[1], Side::Left, handles[0]).unwrap();
+            scope.attach(handles[1], Side::Right, handles[2]).unwrap();
+            scope.attach(handles[2], Side::Right, handles[3]).unwrap();
+            assert_eq!(
+                scope.attach(handles[3], Side::Left, handles[0]),
+                Err(TreeError::DifferentTrees)
+            );
+            assert_eq!(
+                scope.attach(handles[3], Side::Left, handles[1]),
+                Err(TreeError::WouldCreateCycle)
+            );
+            *scope.get_mut(handles[3]) *= 10;
+            assert_eq!(scope.get(handles[1]).size(), 4);
+            assert!(scope.detach(handles[2], Side::Right));
+            assert_eq!(scope.get(handles[1]).size(), 3);
+            let root = scope.get(handles[1]);
+            root.iter(Order::In)
+                .map(|node| *node.get())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(order, [0, 1, 2]);
+        assert!(nodes
+            .iter()
+            .all(|node| node.parent().is_none() && node.left().is_none() && node.size() == 1));
+        assert_eq!(*nodes[3].get(), 30);
+
+        // A panic unlinks the nodes all the same, and linked nodes can't be lent.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            Node::scope(|scope| {
+                let (a, b) = nodes.split_at_mut(1);
+                let (a, b) = (scope.add(&mut a[0]).unwrap(), scope.add(&mut b[0]).unwrap());
+                scope.attach(a, Side::Left, b).unwrap();
+                panic!("halfway through");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(nodes[0].left().is_none() && nodes[1].parent().is_none());
+        let (a, b) = (&mut Node::new(0), &mut Node::new(1));
+        a.replace_left(Some(b));
+        Node::scope(|scope| assert_eq!(scope.add(a).err(), Some(TreeError::SlotOccupied)));
+    }
+}
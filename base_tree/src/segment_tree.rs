@@ -0,0 +1,214 @@
+//! Range aggregate queries over a sequence, kept in a balanced tree of augmented nodes.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use core::marker::PhantomData;
+use core::ops::{Add, RangeBounds};
+use core::ptr::{self, NonNull};
+
+use crate::{bounds, Augment, Node};
+
+/// An associative operation folding adjacent values of a range into one.
+pub trait RangeOp<T> {
+    fn combine(left: &T, right: &T) -> T;
+}
+
+pub struct Sum;
+
+impl<T: Clone + Add<Output = T>> RangeOp<T> for Sum {
+    fn combine(left: &T, right: &T) -> T {
+        left.clone() + right.clone()
+    }
+}
+
+pub struct Min;
+
+impl<T: Clone + Ord> RangeOp<T> for Min {
+    fn combine(left: &T, right: &T) -> T {
+        left.min(right).clone()
+    }
+}
+
+pub struct Max;
+
+impl<T: Clone + Ord> RangeOp<T> for Max {
+    fn combine(left: &T, right: &T) -> T {
+        left.max(right).clone()
+    }
+}
+
+/// Summarizes every subtree by folding its payloads in order with `Op`.
+pub(crate) struct Fold<Op>(PhantomData<Op>);
+
+impl<T: Clone, Op: RangeOp<T>> Augment<T> for Fold<Op> {
+    type Summary = T;
+
+    fn combine(left: Option<&T>, data: &T, right: Option<&T>) -> T {
+        let folded = match left {
+            Some(left) => Op::combine(left, data),
+            None => data.clone(),
+        };
+        match right {
+            Some(right) => Op::combine(&folded, right),
+            None => folded,
+        }
+    }
+}
+
+type FoldNode<T, Op> = Node<T, Fold<Op>>;
+
+/// A fixed length sequence supporting point updates and `Op`-folds over any range, both in
+/// O(log n).
+pub struct SegmentTree<T: Clone, Op: RangeOp<T>> {
+    // The nodes are stored in sequence order, and linked into a balanced tree. The allocation is
+    // only ever accessed through this pointer, and freed on drop.
+    nodes: NonNull<FoldNode<T, Op>>,
+    len: usize,
+    root: Option<NonNull<FoldNode<T, Op>>>,
+}
+
+impl<T: Clone, Op: RangeOp<T>> SegmentTree<T, Op> {
+    pub fn new(values: &[T]) -> Self {
+        let nodes: Box<[_]> = values
+            .iter()
+            .cloned()
+            .map(FoldNode::<T, Op>::augmented)
+            .collect();
+        let len = nodes.len();
+        // Safety: `Box::into_raw` never returns null.
+        let nodes = unsafe { NonNull::new_unchecked(Box::into_raw(nodes).cast()) };
+
+        let mut root = None;
+        let mut stack = vec![(0, len, None::<(NonNull<FoldNode<T, Op>>, bool)>)];
+        while let Some((start, end, parent)) = stack.pop() {
+            if start >= end {
+                continue;
+            }
+            let mid = start + (end - start) / 2;
+            // Safety: `mid < len`, and every node is linked exactly once.
+            let ptr = unsafe { nodes.add(mid) };
+            match parent {
+                None => root = Some(ptr),
+                Some((parent, true)) => unsafe { (*parent.as_ptr()).left = Some(ptr) },
+                Some((parent, false)) => unsafe { (*parent.as_ptr()).right = Some(ptr) },
+            }
+            unsafe { (*ptr.as_ptr()).parent = parent.map(|(parent, _)| parent) };
+            stack.push((start, mid, Some((ptr, true))));
+            stack.push((mid + 1, end, Some((ptr, false))));
+        }
+        if let Some(root) = root {
+            unsafe { Node::refresh_subtree(root) };
+        }
+
+        Self { nodes, len, root }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        (index < self.len).then(|| unsafe { self.nodes.add(index).as_ref() }.get())
+    }
+
+    /// Replaces the value at `index`, returning the old one, or `None` if out of bounds.
+    pub fn set(&mut self, index: usize, value: T) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+        let node = unsafe { self.nodes.add(index).as_mut() };
+        Some(node.replace_data(value))
+    }
+
+    /// Folds the values in `range` with `Op`, or returns `None` if the range is empty.
+    pub fn query(&self, range: impl RangeBounds<usize>) -> Option<T> {
+        let (start, end) = bounds(&range, self.len);
+        let end = end.min(self.len);
+        if start >= end {
+            return None;
+        }
+        unsafe { self.query_subtree(self.root?, 0, self.len, start, end) }
+    }
+
+    /// Folds the part of `start..end` covered by the subtree at `ptr`, which holds the values
+    /// `low..high`. The tree is balanced, so this recurses O(log n) deep.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a node of this tree.
+    unsafe fn query_subtree(
+        &self,
+        ptr: NonNull<FoldNode<T, Op>>,
+        low: usize,
+        high: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<T> {
+        let node = unsafe { ptr.as_ref() };
+        if start <= low && high <= end {
+            return Some(node.summary().clone());
+        }
+        if end <= low || high <= start {
+            return None;
+        }
+
+        let index = unsafe { ptr.offset_from(self.nodes) } as usize;
+        let left = node
+            .left
+            .and_then(|left| unsafe { self.query_subtree(left, low, index, start, end) });
+        let here = (start..end).contains(&index).then(|| node.get().clone());
+        let right = node
+            .right
+            .and_then(|right| unsafe { self.query_subtree(right, index + 1, high, start, end) });
+
+        [left, here, right]
+            .into_iter()
+            .flatten()
+            .reduce(|acc, value| Op::combine(&acc, &value))
+    }
+}
+
+impl<T: Clone, Op: RangeOp<T>> Drop for SegmentTree<T, Op> {
+    fn drop(&mut self) {
+        let nodes = ptr::slice_from_raw_parts_mut(self.nodes.as_ptr(), self.len);
+        // Safety: this is the allocation made in `new`, and no references into it remain.
+        drop(unsafe { Box::from_raw(nodes) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut values = vec![5, 3, 8, 1, 9, 2, 7, 4, 6, 0, 11];
+        let mut sums = SegmentTree::<i32, Sum>::new(&values);
+        let mut mins = SegmentTree::<i32, Min>::new(&values);
+        let maxes = SegmentTree::<i32, Max>::new(&values);
+
+        assert_eq!(sums.set(3, 20), Some(1));
+        assert_eq!(mins.set(3, 20), Some(1));
+        assert_eq!(sums.set(11, 0), None);
+        values[3] = 20;
+
+        for start in 0..=values.len() {
+            for end in start..=values.len() {
+                let range = &values[start..end];
+                assert_eq!(
+                    sums.query(start..end),
+                    range.iter().copied().reduce(|a, b| a + b)
+                );
+                assert_eq!(mins.query(start..end), range.iter().copied().min());
+            }
+        }
+        assert_eq!(maxes.query(..), Some(11));
+        assert_eq!(maxes.query(2..=3), Some(8));
+        assert_eq!(sums.get(3), Some(&20));
+        assert!(SegmentTree::<i32, Sum>::new(&[]).query(..).is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::{Augment, Node};
+
+/// Owns many nodes at once, handing each out by a mutable reference that lasts as long as the
+/// slab is borrowed, so they can be linked to each other as usual. The nodes are kept in chunks
+/// that never move, and are freed together when the slab is dropped or cleared, a chunk at a time
+/// rather than a node at a time.
+///
+/// Nodes of a slab must only be linked to nodes of the same slab, which disappear with it.
+pub struct NodeSlab<T, A: Augment<T> = ()> {
+    // Each chunk is filled up to its capacity and never grown, so its nodes stay in place.
+    chunks: RefCell<Vec<Vec<Node<T, A>>>>,
+}
+
+const FIRST_CHUNK: usize = 64;
+
+impl<T, A: Augment<T>> NodeSlab<T, A> {
+    pub const fn new() -> Self {
+        Self {
+            chunks: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Creates a slab with room for `capacity` nodes before it allocates again.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let slab = Self::new();
+        if capacity > 0 {
+            slab.chunks.borrow_mut().push(Vec::with_capacity(capacity));
+        }
+        slab
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.borrow().iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.borrow().iter().all(Vec::is_empty)
+    }
+
+    /// Adds a new unlinked node holding `data`.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, data: T) -> &mut Node<T, A> {
+        let mut chunks = self.chunks.borrow_mut();
+        let full = chunks
+            .last()
+            .is_none_or(|chunk| chunk.len() == chunk.capacity());
+        if full {
+            let capacity = chunks
+                .last()
+                .map_or(FIRST_CHUNK, |chunk| chunk.capacity() * 2);
+            chunks.push(Vec::with_capacity(capacity));
+        }
+        let chunk = chunks.last_mut().unwrap();
+        // Pushing within the capacity writes past the nodes already handed out, without moving or
+        // reborrowing them.
+        chunk.push(Node::augmented(data));
+        // Safety: the node stays where it is until the slab is dropped or cleared, which needs the
+        // slab to no longer be borrowed, and nothing else refers to it yet.
+        unsafe { &mut *chunk.as_mut_ptr().add(chunk.len() - 1) }
+    }
+
+    /// Drops every node, keeping the largest chunk to fill again.
+    pub fn clear(&mut self) {
+        let chunks = self.chunks.get_mut();
+        let largest = (0..chunks.len()).max_by_key(|&at| chunks[at].capacity());
+        let Some(mut kept) = largest.map(|at| chunks.swap_remove(at)) else {
+            return;
+        };
+        chunks.clear();
+        kept.clear();
+        chunks.push(kept);
+    }
+}
+
+impl<T, A: Augment<T>> Default for NodeSlab<T, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, A: Augment<T>> fmt::Debug for NodeSlab<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeSlab")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Order, Side, SizeAug};
+    use std::vec::Vec;
+
+    #[test]
+    fn nodes_freed_together() {
+        let mut slab = NodeSlab::<usize, SizeAug>::with_capacity(10);
+        for round in 0..3 {
+            // A left spine long enough to need several chunks.
+            let root = slab.alloc(0);
+            let mut tip = &mut *root as *mut Node<usize, SizeAug>;
+            for data in 1..1000 {
+                let node = slab.alloc(data);
+                // Safety: `tip` is the last node allocated, which nothing else borrows.
+                unsafe { (*tip).try_attach(Side::Left, node).unwrap() };
+                tip = node;
+            }
+            assert_eq!(root.size(), 1000);
+            let order: Vec<usize> = root.iter(Order::Pre).map(|node| *node.get()).collect();
+            assert_eq!(order, (0..1000).collect::<Vec<_>>());
+            assert_eq!(slab.len(), 1000);
+            slab.clear();
+            assert!(slab.is_empty(), "round {round}");
+        }
+        assert_eq!(slab.chunks.borrow().len(), 1);
+        assert!(NodeSlab::<u8>::new().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Tree;
+
+/// A node that can be built in a `const` or `static`, to bake small lookup trees into the binary.
+/// It refers to its children by plain references, and has no parent.
+#[derive(Debug)]
+pub struct StaticNode<'a, T> {
+    data: T,
+    left: Option<&'a StaticNode<'a, T>>,
+    right: Option<&'a StaticNode<'a, T>>,
+}
+
+impl<'a, T> StaticNode<'a, T> {
+    pub const fn new(
+        data: T,
+        left: Option<&'a StaticNode<'a, T>>,
+        right: Option<&'a StaticNode<'a, T>>,
+    ) -> Self {
+        Self { data, left, right }
+    }
+
+    pub const fn leaf(data: T) -> Self {
+        Self::new(data, None, None)
+    }
+
+    pub const fn get(&self) -> &T {
+        &self.data
+    }
+
+    pub const fn left(&self) -> Option<&'a StaticNode<'a, T>> {
+        self.left
+    }
+
+    pub const fn right(&self) -> Option<&'a StaticNode<'a, T>> {
+        self.right
+    }
+
+    /// Copies the tree below this node into an owned [`Tree`] of the same shape.
+    pub fn to_tree(&self) -> Tree<T>
+    where
+        T: Clone,
+    {
+        // Children are built before their parents, left before right.
+        let mut pending = vec![(self, false)];
+        let mut built: Vec<Tree<T>> = Vec::new();
+        while let Some((node, expanded)) = pending.pop() {
+            if expanded {
+                let right = node.right.map_or_else(Tree::new, |_| built.pop().unwrap());
+                let left = node.left.map_or_else(Tree::new, |_| built.pop().unwrap());
+                built.push(Tree::join(left, node.data.clone(), right));
+            } else {
+                pending.push((node, true));
+                pending.extend(node.right.map(|child| (child, false)));
+                pending.extend(node.left.map(|child| (child, false)));
+            }
+        }
+        built.pop().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Node, Order};
+
+    const ONE: StaticNode<&str> = StaticNode::leaf("one");
+    static TREE: StaticNode<&str> = StaticNode::new(
+        "three",
+        Some(&StaticNode::new("two", Some(&ONE), None)),
+        Some(&StaticNode::leaf("four")),
+    );
+
+    #[test]
+    fn bake_into_static() {
+        let two = TREE.left().unwrap();
+        assert_eq!(two.left().map(StaticNode::get), Some(&"one"));
+        assert!(two.right().is_none());
+
+        let tree = TREE.to_tree();
+        let root = tree.root().unwrap();
+        assert!(root
+            .iter(Order::Pre)
+            .map(Node::get)
+            .eq(&["three", "two", "one", "four"]));
+        // With `ids`, nodes mint their identity on first use, so every use of a constant is a new
+        // node.
+        #[cfg_attr(feature = "ids", allow(clippy::declare_interior_mutable_const))]
+        const NODE: Node<u8> = Node::new(7);
+        let node = NODE;
+        assert_eq!(node.get(), &7);
+    }
+}
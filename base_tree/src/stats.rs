@@ -0,0 +1,100 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Augment, Node};
+
+/// The shape of a tree, as reported by [`Tree::stats`](crate::Tree::stats) and the `stats` of
+/// the maps.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    /// The number of nodes at each depth, starting with the root's.
+    pub levels: Vec<usize>,
+}
+
+impl TreeStats {
+    pub(crate) fn of<T, A: Augment<T>>(root: &Node<T, A>) -> Self {
+        let mut stats = Self::default();
+        let mut stack = vec![(root, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            stats.nodes += 1;
+            if stats.levels.len() == depth {
+                stats.levels.push(0);
+            }
+            stats.levels[depth] += 1;
+            let children = [node.left(), node.right()];
+            if children.iter().all(Option::is_none) {
+                stats.leaves += 1;
+            }
+            stack.extend(
+                children
+                    .into_iter()
+                    .flatten()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+        stats
+    }
+
+    /// The number of nodes on the longest path down from the root, zero for the empty tree.
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// The mean depth of the nodes, the root being at depth zero.
+    pub fn average_depth(&self) -> f64 {
+        let total: usize = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(depth, &n)| depth * n)
+            .sum();
+        match self.nodes {
+            0 => 0.0,
+            nodes => total as f64 / nodes as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Shape, Tree};
+
+    #[test]
+    fn shape_and_rebalancing() {
+        let stats = Tree::from_shape(0..10, Shape::Balanced, |_| 0).stats();
+        assert_eq!(stats.nodes, 10);
+        assert_eq!(stats.levels, [1, 2, 4, 3]);
+        assert_eq!(stats.height(), 4);
+        assert_eq!(stats.leaves, 4);
+        assert_eq!(stats.average_depth(), 1.9);
+        let chain = Tree::from_shape(0..4, Shape::LeftSkewed, |_| 0).stats();
+        assert_eq!((chain.levels, chain.leaves), (vec![1; 4], 1));
+        assert_eq!(Tree::<u8>::new().stats(), TreeStats::default());
+
+        // Sorted insertions are the worst case for all the maps.
+        #[cfg(feature = "bst")]
+        {
+            use crate::aa_tree::AaTreeMap;
+            use crate::{BstMap, WeightBalancedMap};
+
+            let (mut scapegoat, mut weighted, mut aa) =
+                (BstMap::new(), WeightBalancedMap::new(), AaTreeMap::new());
+            for key in 0..1023 {
+                scapegoat.insert(key, ());
+                weighted.insert(key, ());
+                aa.insert(key, ());
+            }
+            for stats in [scapegoat.stats(), weighted.stats(), aa.stats()] {
+                assert_eq!(stats.nodes, 1023);
+                assert!(stats.height() <= 20, "{stats:?}");
+            }
+            assert!(scapegoat.rebuilds() > 0);
+            assert!(weighted.rotations() > 0);
+            assert!(aa.rotations() > 0);
+            assert_eq!(BstMap::<u8, ()>::new().rebuilds(), 0);
+        }
+    }
+}
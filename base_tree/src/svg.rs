@@ -0,0 +1,127 @@
+//! Drawing trees as SVG pictures, laid out as tidy trees.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::{Augment, Node};
+
+/// Pixels between neighbouring nodes, and between levels.
+const UNIT: f64 = 40.0;
+const LEVEL: f64 = 60.0;
+const RADIUS: f64 = 15.0;
+
+/// Draws the tree below `root` as a standalone SVG document. Every node is a circle with its
+/// `label` in the middle, and `style` gives the CSS of its circle, an empty string keeping the
+/// default look.
+pub fn to_svg<T, A: Augment<T>>(
+    root: &Node<T, A>,
+    mut label: impl FnMut(&T) -> String,
+    mut style: impl FnMut(&T) -> String,
+) -> String {
+    let nodes: Vec<_> = root.layout().collect();
+    let at = |x: f64, depth: u32| {
+        (
+            RADIUS * 2.0 + x * UNIT,
+            RADIUS * 2.0 + f64::from(depth) * LEVEL,
+        )
+    };
+    let positions: BTreeMap<*const Node<T, A>, (f64, f64)> = nodes
+        .iter()
+        .map(|&(node, x, depth)| (node as *const _, at(x, depth)))
+        .collect();
+    let (width, height) = nodes
+        .iter()
+        .fold((0.0, 0.0), |(width, height), &(_, x, depth)| {
+            let (x, y) = at(x, depth);
+            (f64::max(width, x), f64::max(height, y))
+        });
+
+    let mut svg = String::new();
+    // Writing to a `String` can't fail.
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"12\">",
+        width + RADIUS * 2.0,
+        height + RADIUS * 2.0,
+    );
+    let _ = writeln!(svg, "  <g stroke=\"black\">");
+    for &(node, _, _) in &nodes {
+        let (x1, y1) = positions[&(node as *const _)];
+        for child in [node.left(), node.right()].into_iter().flatten() {
+            let (x2, y2) = positions[&(child as *const _)];
+            let _ = writeln!(
+                svg,
+                "    <line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\"/>"
+            );
+        }
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    for &(node, x, depth) in &nodes {
+        let (x, y) = at(x, depth);
+        let _ = write!(svg, "  <circle cx=\"{x}\" cy=\"{y}\" r=\"{RADIUS}\"");
+        let style = style(node.get());
+        if style.is_empty() {
+            svg.push_str(" fill=\"white\" stroke=\"black\"/>\n");
+        } else {
+            let _ = writeln!(svg, " style=\"{}\"/>", escape(&style));
+        }
+        let _ = writeln!(
+            svg,
+            "  <text x=\"{x}\" y=\"{y}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+            escape(&label(node.get())),
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Shape, Tree};
+    use alloc::string::ToString;
+
+    #[test]
+    fn draws_every_node_and_edge() {
+        let tree = Tree::from_shape(1..=7, Shape::Balanced, |_| 0);
+        let root = tree.root().unwrap();
+        let svg = to_svg(
+            root,
+            |n| n.to_string(),
+            |&n| match n % 2 {
+                0 => String::from("fill: #fdd"),
+                _ => String::new(),
+            },
+        );
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"180\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<circle").count(), 7);
+        assert_eq!(svg.matches("<line").count(), 6);
+        assert_eq!(svg.matches("style=\"fill: #fdd\"").count(), 3);
+        // The root is centred over the bottom level, which is a unit apart.
+        assert!(svg.contains("<circle cx=\"90\" cy=\"30\""));
+        assert!(svg.contains(">4</text>"));
+
+        let lone = Tree::with_root("<&>");
+        let svg = to_svg(lone.root().unwrap(), |s| s.to_string(), |_| String::new());
+        assert!(svg.contains(">&lt;&amp;&gt;</text>"));
+    }
+}
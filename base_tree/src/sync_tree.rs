@@ -0,0 +1,141 @@
+//! A tree behind a lock, for sharing between threads.
+
+use core::error::Error;
+use core::fmt::{self, Display};
+use std::sync::{PoisonError, RwLock};
+
+use crate::Tree;
+
+/// A [`Tree`] behind a [`RwLock`], reached only through closures so that no reference into it
+/// outlives the lock. Any number of readers may hold it at once, or a single writer.
+///
+/// A `write` closure that panics poisons the tree, as it may have been left halfway through a
+/// change. From then on, `read` and `write` refuse, without calling their closures, until the tree
+/// has been looked over and [`SyncTree::clear_poison`] called, or it is taken out with
+/// [`SyncTree::into_inner`]. A `read` closure that panics changed nothing, and poisons nothing.
+pub struct SyncTree<T> {
+    tree: RwLock<Tree<T>>,
+}
+
+/// The error of [`SyncTree::read`] and [`SyncTree::write`] once a `write` closure has panicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Poisoned;
+
+impl Display for Poisoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a closure panicked while it was changing the tree")
+    }
+}
+
+impl Error for Poisoned {}
+
+impl<T> SyncTree<T> {
+    pub fn new(tree: Tree<T>) -> Self {
+        Self {
+            tree: RwLock::new(tree),
+        }
+    }
+
+    /// Calls `f` with shared access to the tree, waiting for any writer to finish first.
+    pub fn read<R>(&self, f: impl FnOnce(&Tree<T>) -> R) -> Result<R, Poisoned> {
+        let tree = self.tree.read().map_err(|_| Poisoned)?;
+        Ok(f(&tree))
+    }
+
+    /// Calls `f` with exclusive access to the tree, waiting for all readers and writers to finish
+    /// first.
+    pub fn write<R>(&self, f: impl FnOnce(&mut Tree<T>) -> R) -> Result<R, Poisoned> {
+        let mut tree = self.tree.write().map_err(|_| Poisoned)?;
+        Ok(f(&mut tree))
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.tree.is_poisoned()
+    }
+
+    /// Lets `read` and `write` go ahead again after a `write` closure panicked.
+    pub fn clear_poison(&self) {
+        self.tree.clear_poison();
+    }
+
+    /// Takes the tree out, poisoned or not.
+    pub fn into_inner(self) -> Tree<T> {
+        self.tree
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Default for SyncTree<T> {
+    fn default() -> Self {
+        Self::new(Tree::new())
+    }
+}
+
+impl<T> From<Tree<T>> for SyncTree<T> {
+    fn from(tree: Tree<T>) -> Self {
+        Self::new(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Order, Side};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn shared_between_threads() {
+        let shared = Arc::new(SyncTree::new(Tree::with_root(0)));
+        let threads: Vec<_> = (1..=4usize)
+            .map(|n| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let path = vec![Side::Right; n];
+                    // Each thread adds to its own chain, below the ones of the threads before.
+                    loop {
+                        let grafted = shared.write(|tree| {
+                            tree.graft(&path, Tree::join(Tree::with_root(n), n, Tree::new()))
+                                .is_ok()
+                        });
+                        if grafted.unwrap() {
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let count = shared.read(|tree| tree.root().unwrap().iter(Order::Pre).count());
+        assert_eq!(count, Ok(9));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            shared.write(|_| panic!("halfway through a change")).ok();
+        }));
+        assert!(result.is_err());
+        assert!(shared.is_poisoned());
+        assert_eq!(shared.read(|_| unreachable!()), Err::<(), _>(Poisoned));
+        shared.clear_poison();
+        assert_eq!(shared.read(Tree::is_empty), Ok(false));
+        let tree = Arc::into_inner(shared).unwrap().into_inner();
+        assert_eq!(tree.stats().nodes, 9);
+    }
+    #[test]
+    fn panicking_reads_do_not_poison() {
+        let shared = SyncTree::new(Tree::with_root(1));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            shared.read(|_| panic!("only looking")).ok();
+        }));
+        assert!(result.is_err());
+        assert!(!shared.is_poisoned());
+        assert_eq!(shared.read(|tree| *tree.root().unwrap().get()), Ok(1));
+        assert_eq!(shared.write(|tree| tree.clear()), Ok(()));
+    }
+}
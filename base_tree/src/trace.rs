@@ -0,0 +1,116 @@
+//! Reporting structural changes as they are made, to find out how a tree ended up in the shape
+//! it has. A single hook for the whole program hears about every change to any tree, along with
+//! the path from the root down to where it happened, and can hand it on to a logger. Without a
+//! hook, a change costs one atomic load more; with one, finding the path takes time proportional
+//! to the depth.
+
+use alloc::vec::Vec;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::{Augment, Node, Side};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    /// A subtree was hung into a slot; the path leads to its root.
+    Attach,
+    /// A subtree is about to be taken out of a slot; the path leads to its root.
+    Detach,
+    /// The node at the path is about to be rotated towards `side`, its child on the other side
+    /// taking its place.
+    Rotate(Side),
+    /// The subtree at the path, holding this many nodes, is about to be rebuilt in balance.
+    Rebalance(usize),
+}
+
+pub type Hook = fn(Mutation, &[Side]);
+
+static HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs `hook` to be called on every structural change from now on, or removes the hook.
+/// It must not touch the tree it is told about, which may be halfway through an operation.
+pub fn set_hook(hook: Option<Hook>) {
+    let hook = hook.map_or(ptr::null_mut(), |hook| hook as *mut ());
+    HOOK.store(hook, Ordering::Release);
+}
+
+/// Tells the hook, if there is one, about `mutation` at `ptr`.
+pub(crate) fn emit<T, A: Augment<T>>(ptr: NonNull<Node<T, A>>, mutation: Mutation) {
+    let hook = HOOK.load(Ordering::Acquire);
+    if hook.is_null() {
+        return;
+    }
+    // Safety: only `set_hook` stores anything else, and it stores a `Hook`.
+    let hook = unsafe { core::mem::transmute::<*mut (), Hook>(hook) };
+
+    // Walk up to the root, or to the parent of a link-cut tree's path that does not count the
+    // node among its children.
+    let mut path = Vec::new();
+    let mut current = ptr;
+    // Safety: the callers have access to the node and its ancestors.
+    while let Some(parent) = unsafe { current.as_ref() }.parent {
+        let parent_node = unsafe { parent.as_ref() };
+        let side = if parent_node.left == Some(current) {
+            Side::Left
+        } else if parent_node.right == Some(current) {
+            Side::Right
+        } else {
+            break;
+        };
+        path.push(side);
+        current = parent;
+    }
+    path.reverse();
+    hook(mutation, &path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{Shape, Tree};
+
+    std::thread_local! {
+        // Other tests may be changing trees on other threads while the hook is installed.
+        static SEEN: RefCell<Vec<(Mutation, Vec<Side>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(mutation: Mutation, path: &[Side]) {
+        SEEN.with(|seen| seen.borrow_mut().push((mutation, path.to_vec())));
+    }
+
+    #[test]
+    fn reports_changes_with_paths() {
+        use Side::{Left, Right};
+
+        let mut tree = Tree::from_shape(0..7, Shape::Balanced, |_| 0);
+        let mut other = Tree::with_root(7);
+        set_hook(Some(record));
+        {
+            // Safety: the leaf is handed back to its own tree before that is dropped.
+            let root = unsafe { tree.root_mut() }.unwrap();
+            let leaf = unsafe { other.root_mut() }.unwrap();
+            root.try_graft(&[Left, Left, Left], leaf).unwrap();
+            root.try_detach(&[Left, Left, Left]).unwrap();
+            root.right_mut().unwrap().rotate_right();
+            root.balance();
+        }
+        set_hook(None);
+        tree.graft(&[Left, Left, Left], Tree::with_root(8)).unwrap();
+
+        let seen = SEEN.with(|seen| seen.take());
+        assert_eq!(
+            seen[..4],
+            [
+                (Mutation::Attach, vec![Left, Left, Left]),
+                (Mutation::Detach, vec![Left, Left, Left]),
+                (Mutation::Rotate(Right), vec![Right]),
+                (Mutation::Rebalance(7), vec![]),
+            ]
+        );
+        assert!(seen[4..]
+            .iter()
+            .all(|(mutation, _)| matches!(mutation, Mutation::Rotate(_))));
+    }
+}
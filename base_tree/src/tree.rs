@@ -0,0 +1,1797 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{Debug, Display, Write};
+use core::hash::{Hash, Hasher};
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::thread;
+
+use crate::{
+    random_below, Augment, EdgeError, HeapSize, MemoryReport, Node, Side, TreeError, TreeStats,
+};
+
+/// A tree owning all of its nodes, each of which is a separate heap allocation freed when the
+/// tree is dropped.
+pub struct Tree<T> {
+    // Always the topmost node, or one of its descendants if the root was rotated away through
+    // `root_mut`. All nodes reachable from it are owned by the tree.
+    root: Option<NonNull<Node<T>>>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+impl<T> Tree<T> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a tree consisting of only a root holding `data`.
+    pub fn with_root(data: T) -> Self {
+        Self::join(Self::new(), data, Self::new())
+    }
+
+    /// Creates a tree with a root holding `data`, and `left` and `right` as its subtrees.
+    pub fn join(left: Self, data: T, right: Self) -> Self {
+        let root = alloc(data);
+        // Safety: the node was just allocated, and the subtrees are handed over by their trees.
+        let node = unsafe { &mut *root.as_ptr() };
+        node.left = left.into_root();
+        node.right = right.into_root();
+        for child in [node.left, node.right].into_iter().flatten() {
+            unsafe { (*child.as_ptr()).parent = Some(root) };
+        }
+        Self {
+            root: Some(root),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// The number of nodes, counted in O(n), as the tree keeps no count of them.
+    pub fn len(&self) -> usize {
+        self.root().map_or(0, |root| root.iter(Order::Pre).count())
+    }
+
+    /// Drops every node, leaving the tree empty.
+    pub fn clear(&mut self) {
+        if let Some(root) = self.root_ptr() {
+            // Forgotten first, so that a panicking payload leaks the rest rather than freeing
+            // it twice.
+            self.root = None;
+            unsafe { free_subtree(root) };
+        }
+    }
+
+    pub fn root(&self) -> Option<&Node<T>> {
+        self.root_ptr().map(|ptr| unsafe { ptr.as_ref() })
+    }
+
+    /// The payloads in `order`, cloned, as [`Node::to_vec`] collects them.
+    pub fn to_vec(&self, order: Order) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.root().map_or_else(Vec::new, |root| root.to_vec(order))
+    }
+
+    /// # Safety
+    ///
+    /// The tree frees its nodes as `Box`es, so any node linked into it through the returned
+    /// reference must have been allocated as one and leaked. Nodes detached through it are no
+    /// longer owned by the tree, and are leaked unless the caller frees them.
+    pub unsafe fn root_mut(&mut self) -> Option<&mut Node<T>> {
+        self.root = self.root_ptr();
+        self.root.map(|mut ptr| unsafe { ptr.as_mut() })
+    }
+
+    /// Hangs `subtree` into the empty slot at `path`, or makes it the whole tree if the path is
+    /// empty and so is this tree.
+    pub fn graft(&mut self, path: &[Side], subtree: Self) -> Result<(), TreeError> {
+        let Some((&side, above)) = path.split_last() else {
+            if !self.is_empty() {
+                return Err(TreeError::SlotOccupied);
+            }
+            *self = subtree;
+            return Ok(());
+        };
+        let root = self.root_ptr().ok_or(TreeError::PathOutOfBounds)?;
+        let parent = Node::at_path_raw(root, above).ok_or(TreeError::PathOutOfBounds)?;
+        if unsafe { parent.as_ref() }.link(side).is_some() {
+            return Err(TreeError::SlotOccupied);
+        }
+        if let Some(child) = subtree.into_root() {
+            // Safety: the subtree was handed over by its tree, and the slot is free.
+            unsafe {
+                *(*parent.as_ptr()).link_mut(side) = Some(child);
+                (*child.as_ptr()).parent = Some(parent);
+            }
+            #[cfg(feature = "tracing")]
+            crate::trace::emit(child, crate::trace::Mutation::Attach);
+        }
+        Ok(())
+    }
+
+    /// Cuts the subtree at `path` out of the tree, returning the rest of the tree and the subtree.
+    /// The slot the subtree hung from is left empty, so grafting it back at `path` gives the
+    /// original tree. The empty path splits off the whole tree, and a path to an empty slot, or
+    /// off the tree, splits off an empty one.
+    pub fn split_at(mut self, path: &[Side]) -> (Self, Self) {
+        if path.is_empty() {
+            return (Self::new(), self);
+        }
+        let Some(root) = self.root_ptr() else {
+            return (self, Self::new());
+        };
+        // The root kept by the tree may be in the subtree to be cut off.
+        self.root = Some(root);
+        // Safety: the tree owns every node below its root, and hands over those it cuts off.
+        let subtree = unsafe { (*root.as_ptr()).try_detach(path) }
+            .ok()
+            .map(NonNull::from);
+        (self, unsafe { Self::from_root_ptr(subtree) })
+    }
+
+    /// See [`Node::get_disjoint_mut`], with paths starting at the root.
+    pub fn get_disjoint_mut(&mut self, a: &[Side], b: &[Side]) -> Option<(&mut T, &mut T)> {
+        // Safety: only payloads are handed out, which cannot change the structure.
+        unsafe { self.root_mut() }?.get_disjoint_mut(a, b)
+    }
+
+    /// Empties the tree, yielding its payloads in `order`. Nodes are freed as they are yielded,
+    /// and whatever is left when the iterator is dropped is freed then.
+    pub fn drain(&mut self, order: Order) -> Drain<'_, T> {
+        let root = self.root_ptr();
+        self.root = None;
+        let mut drain = Drain {
+            pending: VecDeque::new(),
+            order,
+            _marker: PhantomData,
+        };
+        if let Some(root) = root {
+            match order {
+                Order::In => drain.descend(root),
+                _ => drain.pending.push_back(root),
+            }
+        }
+        drain
+    }
+
+    /// Combines the trees into one, node by node, the payloads of nodes in the same place going
+    /// through `f` with the one of `self` first. Where only one of the trees has a node,
+    /// `mismatch` decides what happens to its subtree. Trees refused for it are dropped.
+    pub fn merge_with(
+        self,
+        other: Self,
+        mismatch: Mismatch,
+        mut f: impl FnMut(T, T) -> T,
+    ) -> Result<Self, TreeError> {
+        if mismatch == Mismatch::Error && cmp_shape(&self, &other) != Ordering::Equal {
+            return Err(TreeError::ShapeMismatch);
+        }
+        // Everything not yet merged stays owned by a tree, so a panic in `f` leaks nothing.
+        let mut merged = Self::new();
+        let mut stack = vec![(self, other, None)];
+        while let Some((left, right, parent)) = stack.pop() {
+            let subtree = match (left.is_empty(), right.is_empty()) {
+                (false, false) => {
+                    let (left_data, left_left, left_right) = left.split_root().unwrap();
+                    let (right_data, right_left, right_right) = right.split_root().unwrap();
+                    let node = alloc(f(left_data, right_data));
+                    stack.push((left_right, right_right, Some((node, Side::Right))));
+                    stack.push((left_left, right_left, Some((node, Side::Left))));
+                    // Safety: the node was just allocated.
+                    unsafe { Self::from_root_ptr(Some(node)) }
+                }
+                (false, true) if mismatch == Mismatch::KeepLeft => left,
+                (true, false) if mismatch == Mismatch::KeepRight => right,
+                _ => continue,
+            };
+            match parent {
+                None => merged = subtree,
+                Some((parent, side)) => {
+                    if let Some(child) = subtree.into_root() {
+                        // Safety: the parent belongs to `merged`, and nothing went into its slot
+                        // yet.
+                        unsafe {
+                            *(*parent.as_ptr()).link_mut(side) = Some(child);
+                            (*child.as_ptr()).parent = Some(parent);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Counts the nodes, leaves and levels of the tree.
+    pub fn stats(&self) -> TreeStats {
+        self.root().map(TreeStats::of).unwrap_or_default()
+    }
+
+    /// Counts the nodes and the bytes they take up. The payloads' own heap allocations are left
+    /// out; see [`Tree::memory_usage_deep`].
+    pub fn memory_usage(&self) -> MemoryReport {
+        let nodes = self.root().map_or(0, |root| root.iter(Order::Pre).count());
+        MemoryReport {
+            nodes,
+            node_bytes: nodes * mem::size_of::<Node<T>>(),
+            heap_bytes: 0,
+        }
+    }
+
+    /// Like [`Tree::memory_usage`], also counting what the payloads allocate themselves.
+    pub fn memory_usage_deep(&self) -> MemoryReport
+    where
+        T: HeapSize,
+    {
+        let heap_bytes = self.root().map_or(0, |root| {
+            root.iter(Order::Pre)
+                .map(|node| node.get().heap_size())
+                .sum()
+        });
+        MemoryReport {
+            heap_bytes,
+            ..self.memory_usage()
+        }
+    }
+
+    /// Builds the min-Cartesian tree of `values`: its in-order sequence is `values`, and every
+    /// node is no greater than its descendants. Equal values keep the earlier one as the ancestor.
+    /// Runs in O(n).
+    pub fn cartesian_from_slice(values: &[T]) -> Self
+    where
+        T: Clone + Ord,
+    {
+        Self::cartesian_from_slice_by(values, T::cmp)
+    }
+
+    /// Like [`Tree::cartesian_from_slice`], ordered by `compare`. Reversing the comparison gives
+    /// the max-Cartesian tree.
+    pub fn cartesian_from_slice_by(
+        values: &[T],
+        mut compare: impl FnMut(&T, &T) -> Ordering,
+    ) -> Self
+    where
+        T: Clone,
+    {
+        // The right spine of the tree built so far, from the root down.
+        let mut spine: Vec<NonNull<Node<T>>> = Vec::new();
+        for value in values {
+            let node = alloc(value.clone());
+            let mut last = None;
+            while let Some(&top) = spine.last() {
+                if compare(unsafe { top.as_ref() }.get(), value) != Ordering::Greater {
+                    break;
+                }
+                last = spine.pop();
+            }
+            // Safety: all the nodes were just allocated, and are only reachable from the spine.
+            unsafe {
+                if let Some(last) = last {
+                    (*node.as_ptr()).left = Some(last);
+                    (*last.as_ptr()).parent = Some(node);
+                }
+                if let Some(&top) = spine.last() {
+                    (*top.as_ptr()).right = Some(node);
+                    (*node.as_ptr()).parent = Some(top);
+                }
+            }
+            spine.push(node);
+        }
+
+        Self {
+            root: spine.first().copied(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Builds the tree described by `(parent, child, side)` edges between nodes named by ids, with
+    /// the payload of each node given in `payloads`. Fails unless the edges make a single binary
+    /// tree out of all the payloads; none at all give an empty tree.
+    pub fn from_edges<I: Ord + Clone>(
+        edges: impl IntoIterator<Item = (I, I, Side)>,
+        payloads: impl IntoIterator<Item = (I, T)>,
+    ) -> Result<Self, EdgeError<I>> {
+        let mut payloads: BTreeMap<I, T> = payloads.into_iter().collect();
+        let mut children: BTreeMap<I, (Option<I>, Option<I>)> = BTreeMap::new();
+        let mut has_parent = BTreeSet::new();
+        for (parent, child, side) in edges {
+            for id in [&parent, &child] {
+                if !payloads.contains_key(id) {
+                    return Err(EdgeError::UnknownNode(id.clone()));
+                }
+            }
+            if !has_parent.insert(child.clone()) {
+                return Err(EdgeError::MultipleParents(child));
+            }
+            let slots = children.entry(parent.clone()).or_default();
+            let slot = match side {
+                Side::Left => &mut slots.0,
+                Side::Right => &mut slots.1,
+            };
+            if slot.is_some() {
+                return Err(EdgeError::SlotOccupied(parent, side));
+            }
+            *slot = Some(child);
+        }
+
+        let mut roots = payloads.keys().filter(|id| !has_parent.contains(*id));
+        let Some(root) = roots.next().cloned() else {
+            return if payloads.is_empty() {
+                Ok(Self::new())
+            } else {
+                Err(EdgeError::Cycle)
+            };
+        };
+        if roots.next().is_some() {
+            return Err(EdgeError::MultipleRoots);
+        }
+        // Every node but the root has a single parent, so whatever isn't below the root is part
+        // of a cycle.
+        let mut reached = 0;
+        let mut stack = vec![&root];
+        while let Some(id) = stack.pop() {
+            reached += 1;
+            if let Some((left, right)) = children.get(id) {
+                stack.extend(right.iter().chain(left));
+            }
+        }
+        if reached != payloads.len() {
+            return Err(EdgeError::Cycle);
+        }
+
+        let mut tree = None;
+        let mut stack = vec![(root, None::<(NonNull<Node<T>>, Side)>)];
+        while let Some((id, parent)) = stack.pop() {
+            let ptr = alloc(payloads.remove(&id).unwrap());
+            // Safety: the new nodes belong to nobody else until handed to the tree.
+            match parent {
+                None => tree = Some(ptr),
+                Some((parent, side)) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            if let Some((left, right)) = children.remove(&id) {
+                stack.extend(right.map(|right| (right, Some((ptr, Side::Right)))));
+                stack.extend(left.map(|left| (left, Some((ptr, Side::Left)))));
+            }
+        }
+        Ok(unsafe { Self::from_root_ptr(tree) })
+    }
+
+    /// Rebuilds the one tree with the given in-order and pre-order sequences, whose payloads must
+    /// be distinct, in O(n) expected time. Returns `None` if no tree has both sequences.
+    #[cfg(feature = "std")]
+    pub fn from_inorder_preorder(inorder: &[T], preorder: &[T]) -> Option<Self>
+    where
+        T: Clone + Eq + Hash,
+    {
+        Self::from_inorder_and(inorder, preorder.iter(), false)
+    }
+
+    /// Like [`Tree::from_inorder_preorder`], from the post-order sequence.
+    #[cfg(feature = "std")]
+    pub fn from_inorder_postorder(inorder: &[T], postorder: &[T]) -> Option<Self>
+    where
+        T: Clone + Eq + Hash,
+    {
+        Self::from_inorder_and(inorder, postorder.iter().rev(), true)
+    }
+
+    /// Builds the tree from `walk`, either its pre-order or, `mirrored`, its post-order backwards,
+    /// which is the pre-order of the mirror image.
+    #[cfg(feature = "std")]
+    fn from_inorder_and<'a>(
+        inorder: &[T],
+        walk: impl ExactSizeIterator<Item = &'a T>,
+        mirrored: bool,
+    ) -> Option<Self>
+    where
+        T: 'a + Clone + Eq + Hash,
+    {
+        if walk.len() != inorder.len() {
+            return None;
+        }
+        // Positions in the in-order sequence, counted from the end for the mirror image.
+        let mut positions = HashMap::with_capacity(inorder.len());
+        for (at, value) in inorder.iter().enumerate() {
+            let at = if mirrored { inorder.len() - 1 - at } else { at };
+            if positions.insert(value, at).is_some() {
+                return None;
+            }
+        }
+        let (near, far) = if mirrored {
+            (Side::Right, Side::Left)
+        } else {
+            (Side::Left, Side::Right)
+        };
+
+        // Owns the nodes built so far, so that they are freed on bad input.
+        let mut tree = Self::new();
+        // The nodes whose far subtree is still to come, with their positions, deepest on top.
+        let mut open: Vec<(NonNull<Node<T>>, usize)> = Vec::new();
+        for value in walk {
+            let at = *positions.get(value)?;
+            let ptr = alloc(value.clone());
+            // A node goes below the last node on its near side if it comes before it, or else on
+            // the far side of the deepest node it comes after.
+            let parent = match open.last() {
+                None => None,
+                Some(&(top, top_at)) if at < top_at => Some((top, near)),
+                Some(_) => {
+                    let mut parent = None;
+                    while let Some(&(_, node_at)) = open.last() {
+                        if node_at > at {
+                            break;
+                        }
+                        parent = open.pop();
+                    }
+                    parent.map(|(node, _)| (node, far))
+                }
+            };
+            match parent {
+                None => tree.root = Some(ptr),
+                // Safety: the new nodes belong to nobody else but the tree.
+                Some((parent, side)) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            open.push((ptr, at));
+        }
+        // Distinct payloads always give a tree, but not necessarily one with this in-order.
+        let order = in_order(&tree);
+        order.eq(inorder.iter()).then_some(tree)
+    }
+
+    /// Lists every node as an `(id, parent id, side below the parent, payload)` row, numbering
+    /// the nodes in pre-order from 0. The root comes first, without a parent or side. The rows
+    /// give back the tree through [`Tree::from_edges`].
+    pub fn to_edge_list(&self) -> Vec<(usize, Option<usize>, Option<Side>, &T)> {
+        let mut rows = Vec::new();
+        let mut stack: Vec<_> = self.root().map(|root| (root, None)).into_iter().collect();
+        while let Some((node, parent)) = stack.pop() {
+            let id = rows.len();
+            rows.push((
+                id,
+                parent.map(|(id, _)| id),
+                parent.map(|(_, side)| side),
+                node.get(),
+            ));
+            stack.extend(node.right().map(|right| (right, Some((id, Side::Right)))));
+            stack.extend(node.left().map(|left| (left, Some((id, Side::Left)))));
+        }
+        rows
+    }
+
+    /// Every slot of the tree in pre-order, with `None` for those without a child, in the form
+    /// [`Tree::from_preorder_with_null_markers`] reads back.
+    pub fn preorder_with_null_markers(&self) -> impl Iterator<Item = Option<&T>> {
+        slots(self.root())
+    }
+
+    /// Builds the tree whose slots in pre-order are `slots`, with `None` for an empty one, in
+    /// O(n). Returns `None` unless the slots describe exactly one tree, with none left over; a
+    /// single `None` gives an empty tree.
+    pub fn from_preorder_with_null_markers(
+        slots: impl IntoIterator<Item = Option<T>>,
+    ) -> Option<Self> {
+        // Owns the nodes built so far, so that they are freed if the slots run out.
+        let mut tree = Self::new();
+        // The slots still to be filled, the next one on top. The root has no parent.
+        let mut pending = vec![None::<(NonNull<Node<T>>, Side)>];
+        for slot in slots {
+            let parent = pending.pop()?;
+            let Some(data) = slot else {
+                continue;
+            };
+            let ptr = alloc(data);
+            match parent {
+                None => tree.root = Some(ptr),
+                // Safety: the new nodes belong to nobody else but the tree.
+                Some((parent, side)) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            pending.push(Some((ptr, Side::Right)));
+            pending.push(Some((ptr, Side::Left)));
+        }
+        pending.is_empty().then_some(tree)
+    }
+
+    /// Dumps the structure of the tree for debugging, one line per node, numbered in pre-order:
+    ///
+    /// ```text
+    /// #0 2 parent: - left: #1 right: #2
+    /// #1 1 parent: #0 left left: - right: -
+    /// #2 3 parent: #0 right left: - right: -
+    /// ```
+    ///
+    /// Parent pointers say which slot of the parent refers back to the node, and those that lead
+    /// elsewhere than the node the walk came from are flagged, so nodes broken by unsafe code
+    /// show up. Cycles through child pointers are cut short.
+    pub fn debug_snapshot(&self) -> String
+    where
+        T: Debug,
+    {
+        // Unlike `root_ptr`, the snapshot climbs from the stored root to the top itself, and stops
+        // if the parent pointers loop, so it still finishes when they are broken.
+        match self.root {
+            // Safety: the tree owns every node reachable through child pointers.
+            Some(root) => unsafe { crate::display::snapshot(root) },
+            None => String::from("(empty)\n"),
+        }
+    }
+
+    /// The rows of [`Tree::to_edge_list`] as CSV, under an `id,parent,side,payload` header.
+    /// Payloads are always quoted, the root has empty parent and side fields.
+    pub fn to_csv(&self) -> String
+    where
+        T: Display,
+    {
+        let mut csv = String::from("id,parent,side,payload\n");
+        for (id, parent, side, payload) in self.to_edge_list() {
+            let parent = parent.map(|parent| parent.to_string()).unwrap_or_default();
+            let side = match side {
+                Some(Side::Left) => "left",
+                Some(Side::Right) => "right",
+                None => "",
+            };
+            let payload = payload.to_string().replace('"', "\"\"");
+            // Writing to a `String` can't fail.
+            let _ = writeln!(csv, "{id},{parent},{side},\"{payload}\"");
+        }
+        csv
+    }
+
+    /// Builds a tree holding `values` in in-order, shaped according to `shape`. For
+    /// [`Shape::Random`], `choose(n)` picks which of the `n` values of each subtree goes at its
+    /// root, and must return less than `n`; it is not called for the other shapes.
+    pub fn from_shape(
+        values: impl IntoIterator<Item = T>,
+        shape: Shape,
+        mut choose: impl FnMut(usize) -> usize,
+    ) -> Self {
+        let nodes: Vec<NonNull<Node<T>>> = values.into_iter().map(alloc).collect();
+        // Safety: the nodes were just allocated, and are not linked yet.
+        let root = unsafe {
+            link_in_order(&nodes, |start, end| match shape {
+                Shape::Balanced => start + (end - start) / 2,
+                Shape::Random => {
+                    let offset = choose(end - start);
+                    assert!(offset < end - start, "chose {offset} of {}", end - start);
+                    start + offset
+                }
+                Shape::LeftSkewed => end - 1,
+                Shape::RightSkewed => start,
+            })
+        };
+
+        Self {
+            root,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Builds a chain of left children holding `values` from the root down, as deep as the tree
+    /// of that many nodes can get, for testing what must not recurse on the height of a tree.
+    pub fn left_spine(values: impl IntoIterator<Item = T>) -> Self {
+        Self::chain(values, |_| Side::Left)
+    }
+
+    /// Like [`Tree::left_spine`], with right children.
+    pub fn right_spine(values: impl IntoIterator<Item = T>) -> Self {
+        Self::chain(values, |_| Side::Right)
+    }
+
+    /// A chain holding `values` from the root down that turns at every node, starting with a left
+    /// child, which defeats balancing by single rotations.
+    pub fn zigzag(values: impl IntoIterator<Item = T>) -> Self {
+        Self::chain(values, |depth| {
+            if depth % 2 == 0 {
+                Side::Left
+            } else {
+                Side::Right
+            }
+        })
+    }
+
+    /// Hangs each of `values` below the previous one, on the side `side` gives for its depth.
+    fn chain(values: impl IntoIterator<Item = T>, mut side: impl FnMut(usize) -> Side) -> Self {
+        let mut tree = Self::new();
+        let mut tip: Link<T, ()> = None;
+        for (depth, value) in values.into_iter().enumerate() {
+            let ptr = alloc(value);
+            match tip {
+                None => tree.root = Some(ptr),
+                // Safety: the new nodes belong to nobody else but the tree.
+                Some(parent) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side(depth - 1)) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            tip = Some(ptr);
+        }
+        tree
+    }
+
+    /// Builds a balanced tree with `values` in in-order, so a sorted slice gives a search tree.
+    /// Large slices are split among threads, each building its subtrees, which are then joined.
+    /// The result has the same shape as with [`Shape::Balanced`].
+    #[cfg(feature = "std")]
+    pub fn from_sorted_slice_parallel(values: &[T]) -> Self
+    where
+        T: Clone + Send + Sync,
+    {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self::from_slice_parallel(values, threads, 1 << 14)
+    }
+
+    /// Builds `values` into a balanced tree using up to `threads` threads, handing slices of at
+    /// most `serial` values to a single thread.
+    #[cfg(feature = "std")]
+    fn from_slice_parallel(values: &[T], threads: usize, serial: usize) -> Self
+    where
+        T: Clone + Send + Sync,
+    {
+        if threads <= 1 || values.len() <= serial {
+            return Self::from_shape(values.iter().cloned(), Shape::Balanced, |_| unreachable!());
+        }
+        let mid = values.len() / 2;
+        let (left, right) = thread::scope(|scope| {
+            let left =
+                scope.spawn(|| Self::from_slice_parallel(&values[..mid], threads / 2, serial));
+            let right =
+                Self::from_slice_parallel(&values[mid + 1..], threads - threads / 2, serial);
+            (left.join().unwrap(), right)
+        });
+        Self::join(left, values[mid].clone(), right)
+    }
+
+    /// Maps every payload with `map` and combines the results in in-order with `reduce`, which
+    /// must be associative with `identity()` as its identity, though not commutative. The tree
+    /// is cut into subtrees below its top levels, which are folded on threads of their own.
+    #[cfg(feature = "std")]
+    pub fn par_fold<R: Send>(
+        &self,
+        identity: impl Fn() -> R + Sync,
+        map: impl Fn(&T) -> R + Sync,
+        reduce: impl Fn(R, R) -> R + Sync,
+    ) -> R
+    where
+        T: Sync,
+    {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        self.fold_parallel(threads, identity, map, reduce)
+    }
+
+    #[cfg(feature = "std")]
+    fn fold_parallel<R: Send>(
+        &self,
+        threads: usize,
+        identity: impl Fn() -> R + Sync,
+        map: impl Fn(&T) -> R + Sync,
+        reduce: impl Fn(R, R) -> R + Sync,
+    ) -> R
+    where
+        T: Sync,
+    {
+        enum Piece<'a, T> {
+            Node(&'a Node<T>),
+            Subtree(Subtree<'a, T>),
+        }
+        struct Subtree<'a, T>(&'a Node<T>);
+        // Safety: the tree is `Sync` when `T` is, and so are shared references into it.
+        unsafe impl<T: Sync> Send for Subtree<'_, T> {}
+
+        // The nodes above `depth` in in-order, with the subtrees hanging below them in between,
+        // at least `threads` of them if the tree is full enough.
+        let depth = threads.next_power_of_two().trailing_zeros();
+        let mut pieces = Vec::new();
+        let mut stack: Vec<(&Node<T>, u32, bool)> = self
+            .root()
+            .map(|root| (root, 0, false))
+            .into_iter()
+            .collect();
+        while let Some((node, level, expanded)) = stack.pop() {
+            if expanded {
+                pieces.push(Piece::Node(node));
+            } else if level == depth {
+                pieces.push(Piece::Subtree(Subtree(node)));
+            } else {
+                stack.extend(node.right().map(|right| (right, level + 1, false)));
+                stack.push((node, level, true));
+                stack.extend(node.left().map(|left| (left, level + 1, false)));
+            }
+        }
+        let (identity, map, reduce) = (&identity, &map, &reduce);
+        let results: Vec<R> = thread::scope(|scope| {
+            let handles: Vec<_> = pieces
+                .into_iter()
+                .map(|piece| match piece {
+                    Piece::Node(node) => Ok(map(node.get())),
+                    Piece::Subtree(subtree) => Err(scope.spawn(move || {
+                        // Moved as a whole, so that the closure is `Send`.
+                        let subtree = subtree;
+                        subtree
+                            .0
+                            .iter(Order::In)
+                            .fold(identity(), |acc, node| reduce(acc, map(node.get())))
+                    })),
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.unwrap_or_else(|handle| handle.join().unwrap()))
+                .collect()
+        });
+        results.into_iter().fold(identity(), reduce)
+    }
+
+    /// Takes ownership of the nodes linked below `root`.
+    ///
+    /// # Safety
+    ///
+    /// All of them must have been allocated with [`alloc`], and be referred to by nothing else.
+    pub(crate) unsafe fn from_root_ptr(root: Option<NonNull<Node<T>>>) -> Self {
+        Self {
+            root,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Numbers the nodes from 0 in `order`, pairing each payload with its number in a tree of the
+    /// same shape.
+    pub fn enumerate(self, order: Order) -> Tree<(usize, T)> {
+        let numbers: BTreeMap<*const Node<T>, usize> = self
+            .root()
+            .into_iter()
+            .flat_map(|root| root.iter(order))
+            .enumerate()
+            .map(|(number, node)| (node as *const _, number))
+            .collect();
+        let mut root = None;
+        let mut stack = vec![(self, None::<(NonNull<Node<(usize, T)>>, Side)>)];
+        while let Some((tree, parent)) = stack.pop() {
+            let Some(old) = tree.root_ptr() else {
+                continue;
+            };
+            let number = numbers[&old.as_ptr().cast_const()];
+            let (data, left, right) = tree.split_root().unwrap();
+            let ptr = alloc((number, data));
+            // Safety: the new nodes belong to nobody else until handed to the tree.
+            match parent {
+                None => root = Some(ptr),
+                Some((parent, side)) => unsafe {
+                    *(*parent.as_ptr()).link_mut(side) = Some(ptr);
+                    (*ptr.as_ptr()).parent = Some(parent);
+                },
+            }
+            stack.push((right, Some((ptr, Side::Right))));
+            stack.push((left, Some((ptr, Side::Left))));
+        }
+        unsafe { Tree::from_root_ptr(root) }
+    }
+
+    /// Takes the tree apart into the payload of its root and its two subtrees.
+    fn split_root(self) -> Option<(T, Self, Self)> {
+        // Safety: the tree hands over all of its nodes, and the subtrees are cut loose from the
+        // root before it is freed.
+        let root = unsafe { Box::from_raw(self.into_root()?.as_ptr()) };
+        let subtree = |child: Link<T, ()>| {
+            if let Some(child) = child {
+                unsafe { (*child.as_ptr()).parent = None };
+            }
+            unsafe { Self::from_root_ptr(child) }
+        };
+        let (left, right) = (subtree(root.left), subtree(root.right));
+        Some((root.data, left, right))
+    }
+
+    /// Gives up ownership of all the nodes, returning the root.
+    fn into_root(self) -> Option<NonNull<Node<T>>> {
+        let root = self.root_ptr();
+        mem::forget(self);
+        root
+    }
+
+    fn root_ptr(&self) -> Option<NonNull<Node<T>>> {
+        let mut root = self.root?;
+        while let Some(parent) = unsafe { root.as_ref() }.parent {
+            root = parent;
+        }
+        Some(root)
+    }
+}
+
+impl<A, B> Tree<(A, B)> {
+    /// Splits the pairs apart into two trees of the same shape as this one, the first holding
+    /// the first halves.
+    pub fn unzip(self) -> (Tree<A>, Tree<B>) {
+        let (mut first, mut second) = (None, None);
+        let mut stack = vec![(self, None::<(NonNull<Node<A>>, NonNull<Node<B>>, Side)>)];
+        while let Some((tree, parents)) = stack.pop() {
+            let Some(((a, b), left, right)) = tree.split_root() else {
+                continue;
+            };
+            let (a, b) = (alloc(a), alloc(b));
+            // Safety: the new nodes belong to nobody else until handed to the trees.
+            match parents {
+                None => (first, second) = (Some(a), Some(b)),
+                Some((parent_a, parent_b, side)) => unsafe {
+                    *(*parent_a.as_ptr()).link_mut(side) = Some(a);
+                    (*a.as_ptr()).parent = Some(parent_a);
+                    *(*parent_b.as_ptr()).link_mut(side) = Some(b);
+                    (*b.as_ptr()).parent = Some(parent_b);
+                },
+            }
+            stack.push((right, Some((a, b, Side::Right))));
+            stack.push((left, Some((a, b, Side::Left))));
+        }
+        unsafe { (Tree::from_root_ptr(first), Tree::from_root_ptr(second)) }
+    }
+}
+
+impl Tree<usize> {
+    /// Builds a tree of `n` nodes numbered `0..n` in in-order, shaped according to `shape`.
+    /// `rng` is only called for [`Shape::Random`], and should return uniformly distributed bits,
+    /// like `|| rng.next_u64()` for any generator of the `rand` crate.
+    pub fn random(mut rng: impl FnMut() -> u64, n: usize, shape: Shape) -> Self {
+        Self::from_shape(0..n, shape, |len| random_below(rng(), len))
+    }
+}
+
+/// How [`Tree::from_shape`] arranges its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Shape {
+    /// Every subtree is split at its middle, for the least possible height.
+    Balanced,
+    /// Every subtree is split at a randomly chosen value, like inserting the values into a plain
+    /// search tree in random order.
+    Random,
+    /// A single chain of left children, as deep as possible.
+    LeftSkewed,
+    /// A single chain of right children, as deep as possible.
+    RightSkewed,
+}
+
+/// What [`Tree::merge_with`] does where only one of the trees has a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mismatch {
+    /// Refuse to merge trees of different shapes.
+    Error,
+    /// Keep the subtrees only the first tree has, and drop those only the second one has.
+    KeepLeft,
+    /// Keep the subtrees only the second tree has, and drop those only the first one has.
+    KeepRight,
+}
+
+/// An order to visit the nodes of a tree in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// Every node before its descendants, left subtrees first.
+    Pre,
+    /// Every node after its left subtree, and before its right one.
+    In,
+    /// Every node after its descendants, left subtrees first.
+    Post,
+    /// By depth, and left to right within a level.
+    Level,
+}
+
+/// The iterator returned by [`Tree::drain`].
+pub struct Drain<'a, T> {
+    // Roots of subtrees owned by the iterator. Links to children that have already been visited,
+    // or are pending on their own, are cleared.
+    pending: VecDeque<NonNull<Node<T>>>,
+    order: Order,
+    _marker: PhantomData<&'a mut Tree<T>>,
+}
+
+impl<T> Drain<'_, T> {
+    /// Queues `ptr` and its chain of left descendants, to be visited bottom up, for in-order.
+    fn descend(&mut self, ptr: NonNull<Node<T>>) {
+        let mut current = Some(ptr);
+        while let Some(ptr) = current {
+            self.pending.push_back(ptr);
+            current = unsafe { (*ptr.as_ptr()).left.take() };
+        }
+    }
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // Safety: the pending nodes are owned by the iterator, and only reachable through it.
+        let ptr = match self.order {
+            Order::Pre => {
+                let ptr = self.pending.pop_back()?;
+                let node = unsafe { &mut *ptr.as_ptr() };
+                self.pending.extend(node.right.take());
+                self.pending.extend(node.left.take());
+                ptr
+            }
+            Order::In => {
+                let ptr = self.pending.pop_back()?;
+                if let Some(right) = unsafe { (*ptr.as_ptr()).right.take() } {
+                    self.descend(right);
+                }
+                ptr
+            }
+            Order::Post => loop {
+                let &ptr = self.pending.back()?;
+                let node = unsafe { &mut *ptr.as_ptr() };
+                match node.left.take().or_else(|| node.right.take()) {
+                    Some(child) => self.pending.push_back(child),
+                    None => break self.pending.pop_back()?,
+                }
+            },
+            Order::Level => {
+                let ptr = self.pending.pop_front()?;
+                let node = unsafe { &mut *ptr.as_ptr() };
+                self.pending.extend(node.left.take());
+                self.pending.extend(node.right.take());
+                ptr
+            }
+        };
+        Some(unsafe { Box::from_raw(ptr.as_ptr()) }.data)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.pending.len(), None)
+    }
+}
+
+impl<T> FusedIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        for ptr in self.pending.drain(..) {
+            unsafe { free_subtree(ptr) };
+        }
+    }
+}
+
+// Safety: a tree owns its nodes like a `Box` does, and hands out access to them only through
+// references to itself.
+unsafe impl<T: Send> Send for Tree<T> {}
+unsafe impl<T: Sync> Sync for Tree<T> {}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every slot of a tree in pre-order, empty ones included, which tells the tree apart from any
+/// other: both its shape and payloads.
+fn slots<T>(root: Option<&Node<T>>) -> impl Iterator<Item = Option<&T>> {
+    let mut stack = vec![root];
+    core::iter::from_fn(move || {
+        let slot = stack.pop()?;
+        if let Some(node) = slot {
+            stack.push(node.right());
+            stack.push(node.left());
+        }
+        Some(slot.map(Node::get))
+    })
+}
+
+/// Trees are equal when they have the same shape, and equal payloads in the same places.
+impl<T: PartialEq> PartialEq for Tree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        slots(self.root()).eq(slots(other.root()))
+    }
+}
+
+impl<T: Eq> Eq for Tree<T> {}
+
+/// Trees are ordered by their payloads in in-order, lexicographically. Trees holding the same
+/// sequence are ordered by shape: by which slots are filled, in pre-order, an empty slot coming
+/// before a filled one.
+impl<T: PartialOrd> PartialOrd for Tree<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match in_order(self).partial_cmp(in_order(other))? {
+            Ordering::Equal => Some(cmp_shape(self, other)),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl<T: Ord> Ord for Tree<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        in_order(self)
+            .cmp(in_order(other))
+            .then_with(|| cmp_shape(self, other))
+    }
+}
+
+fn in_order<T>(tree: &Tree<T>) -> impl Iterator<Item = &T> {
+    let nodes = tree
+        .root()
+        .into_iter()
+        .flat_map(|root| root.iter(Order::In));
+    nodes.map(Node::get)
+}
+
+fn cmp_shape<T>(a: &Tree<T>, b: &Tree<T>) -> Ordering {
+    let filled = |tree| slots(tree).map(|slot| slot.is_some());
+    filled(a.root()).cmp(filled(b.root()))
+}
+
+impl<T: Hash> Hash for Tree<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for slot in slots(self.root()) {
+            slot.hash(state);
+        }
+    }
+}
+
+impl<T> Drop for Tree<T> {
+    fn drop(&mut self) {
+        if let Some(root) = self.root_ptr() {
+            unsafe { free_subtree(root) };
+        }
+    }
+}
+
+pub(crate) fn alloc<T, A: Augment<T>>(data: T) -> NonNull<Node<T, A>> {
+    NonNull::from(Box::leak(Box::new(Node::augmented(data))))
+}
+
+/// Frees `root` and all of its descendants.
+///
+/// # Safety
+///
+/// Every node of the subtree must have been allocated with [`alloc`], and nothing may refer to
+/// them afterwards.
+pub(crate) unsafe fn free_subtree<T, A: Augment<T>>(root: NonNull<Node<T, A>>) {
+    let mut stack = vec![root];
+    while let Some(ptr) = stack.pop() {
+        let node = unsafe { Box::from_raw(ptr.as_ptr()) };
+        stack.extend(node.left);
+        stack.extend(node.right);
+    }
+}
+
+pub(crate) type Link<T, A> = Option<NonNull<Node<T, A>>>;
+
+// The helpers below all require the nodes they are handed, and the nodes linked to them, to be
+// dereferentiable and exclusively accessible by the caller.
+
+/// Links `nodes` into a tree with them in in-order, refreshes its summaries and returns its root.
+/// `pick(start, end)` chooses which of `nodes[start..end]` becomes the root of their subtree.
+///
+/// # Safety
+///
+/// The nodes must be distinct and unlinked, and the caller must have exclusive access to them.
+pub(crate) unsafe fn link_in_order<T, A: Augment<T>>(
+    nodes: &[NonNull<Node<T, A>>],
+    mut pick: impl FnMut(usize, usize) -> usize,
+) -> Link<T, A> {
+    let mut root = None;
+    let mut stack = vec![(0, nodes.len(), None::<(NonNull<Node<T, A>>, bool)>)];
+    while let Some((start, end, parent)) = stack.pop() {
+        if start >= end {
+            continue;
+        }
+        let index = pick(start, end);
+        let ptr = nodes[index];
+        match parent {
+            None => root = Some(ptr),
+            Some((parent, true)) => unsafe { (*parent.as_ptr()).left = Some(ptr) },
+            Some((parent, false)) => unsafe { (*parent.as_ptr()).right = Some(ptr) },
+        }
+        unsafe { (*ptr.as_ptr()).parent = parent.map(|(parent, _)| parent) };
+        stack.push((start, index, Some((ptr, true))));
+        stack.push((index + 1, end, Some((ptr, false))));
+    }
+    if let Some(root) = root {
+        unsafe { Node::refresh_subtree(root) };
+    }
+    root
+}
+
+/// # Safety
+///
+/// See above.
+pub(crate) unsafe fn leftmost<T, A: Augment<T>>(
+    mut ptr: NonNull<Node<T, A>>,
+) -> NonNull<Node<T, A>> {
+    while let Some(left) = unsafe { ptr.as_ref() }.left {
+        ptr = left;
+    }
+    ptr
+}
+
+/// # Safety
+///
+/// See above.
+pub(crate) unsafe fn rightmost<T, A: Augment<T>>(
+    mut ptr: NonNull<Node<T, A>>,
+) -> NonNull<Node<T, A>> {
+    while let Some(right) = unsafe { ptr.as_ref() }.right {
+        ptr = right;
+    }
+    ptr
+}
+
+/// The next node in in-order.
+///
+/// # Safety
+///
+/// See above.
+pub(crate) unsafe fn successor<T, A: Augment<T>>(
+    ptr: NonNull<Node<T, A>>,
+) -> Option<NonNull<Node<T, A>>> {
+    if let Some(right) = unsafe { ptr.as_ref() }.right {
+        return Some(unsafe { leftmost(right) });
+    }
+    let mut child = ptr;
+    while let Some(parent) = unsafe { child.as_ref() }.parent {
+        if unsafe { parent.as_ref() }.left == Some(child) {
+            return Some(parent);
+        }
+        child = parent;
+    }
+    None
+}
+
+/// The previous node in in-order.
+///
+/// # Safety
+///
+/// See above.
+pub(crate) unsafe fn predecessor<T, A: Augment<T>>(
+    ptr: NonNull<Node<T, A>>,
+) -> Option<NonNull<Node<T, A>>> {
+    if let Some(left) = unsafe { ptr.as_ref() }.left {
+        return Some(unsafe { rightmost(left) });
+    }
+    let mut child = ptr;
+    while let Some(parent) = unsafe { child.as_ref() }.parent {
+        if unsafe { parent.as_ref() }.right == Some(child) {
+            return Some(parent);
+        }
+        child = parent;
+    }
+    None
+}
+
+/// Removes the payload at `ptr` from the binary search tree rooted at `*root`, keeping the
+/// in-order sequence of the other payloads. A node with two children trades payloads with its
+/// successor, which is unlinked in its place. Returns the unlinked node, along with the parent it
+/// was unlinked from; the summaries from there up are left for the caller to bring up to date.
+///
+/// # Safety
+///
+/// See above, and `ptr` must be in the tree rooted at `*root`.
+pub(crate) unsafe fn unlink<T, A: Augment<T>>(
+    root: &mut Link<T, A>,
+    ptr: NonNull<Node<T, A>>,
+) -> (Box<Node<T, A>>, Link<T, A>) {
+    let node = unsafe { &mut *ptr.as_ptr() };
+    let target = match (node.left, node.right) {
+        (Some(_), Some(right)) => {
+            let next = unsafe { leftmost(right) };
+            mem::swap(&mut node.data, unsafe { &mut (*next.as_ptr()).data });
+            next
+        }
+        _ => ptr,
+    };
+
+    let target_node = unsafe { &mut *target.as_ptr() };
+    let child = target_node.left.take().or(target_node.right.take());
+    let parent = target_node.parent.take();
+    if let Some(child) = child {
+        unsafe { (*child.as_ptr()).parent = parent };
+    }
+    match parent {
+        None => *root = child,
+        Some(parent) => {
+            let parent = unsafe { &mut *parent.as_ptr() };
+            if parent.left == Some(target) {
+                parent.left = child;
+            } else {
+                parent.right = child;
+            }
+        }
+    }
+    (unsafe { Box::from_raw(target.as_ptr()) }, parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Descend;
+
+    fn check_heap<T: Copy + Ord>(node: Option<&Node<T>>, out: &mut Vec<T>) {
+        if let Some(node) = node {
+            for child in [node.left(), node.right()].into_iter().flatten() {
+                assert!(child.get() >= node.get());
+                assert!(std::ptr::eq(child.parent().unwrap(), node));
+            }
+            check_heap(node.left(), out);
+            out.push(*node.get());
+            check_heap(node.right(), out);
+        }
+    }
+
+    #[test]
+    fn cartesian_tree() {
+        let values = [9, 3, 7, 1, 8, 12, 10, 20, 15, 18, 5];
+        let tree = Tree::cartesian_from_slice(&values);
+        assert_eq!(tree.root().map(Node::get), Some(&1));
+        let mut order = Vec::new();
+        check_heap(tree.root(), &mut order);
+        assert_eq!(order, values);
+
+        let tree = Tree::cartesian_from_slice_by(&values, |a, b| b.cmp(a));
+        assert_eq!(tree.root().map(Node::get), Some(&20));
+        assert_eq!(tree.root().and_then(Node::right).map(Node::get), Some(&18));
+
+        assert!(Tree::<i32>::cartesian_from_slice(&[]).is_empty());
+    }
+
+    #[test]
+    fn shaped_trees() {
+        fn check(node: Option<&Node<usize>>, out: &mut Vec<usize>) -> usize {
+            let Some(node) = node else {
+                return 0;
+            };
+            let left = check(node.left(), out);
+            out.push(*node.get());
+            let right = check(node.right(), out);
+            left.max(right) + 1
+        }
+
+        let mut lcg = crate::lcg(1);
+        let mut choose = |n: usize| lcg(n as u64) as usize;
+        for (shape, height) in [
+            (Shape::Balanced, Some(7)),
+            (Shape::Random, None),
+            (Shape::LeftSkewed, Some(100)),
+            (Shape::RightSkewed, Some(100)),
+        ] {
+            let tree = Tree::from_shape(0..100, shape, &mut choose);
+            let mut order = Vec::new();
+            let depth = check(tree.root(), &mut order);
+            assert!(order.into_iter().eq(0..100));
+            assert!(height.is_none_or(|height| depth == height));
+        }
+        let mut rng = crate::lcg_words(5);
+        let random = Tree::random(&mut rng, 1000, Shape::Random);
+        let mut order = Vec::new();
+        // Random search trees are about 3 log n deep, so this is all but certain for any seed.
+        assert!(check(random.root(), &mut order) < 60);
+        assert!(order.into_iter().eq(0..1000));
+        assert_eq!(
+            check(
+                Tree::random(rng, 10, Shape::LeftSkewed).root(),
+                &mut Vec::new()
+            ),
+            10
+        );
+
+        let chain = Tree::from_shape(0..3, Shape::LeftSkewed, |_| unreachable!());
+        let left = chain.root().and_then(Node::left);
+        assert_eq!(left.and_then(Node::left).map(Node::get), Some(&0));
+    }
+
+    #[test]
+    fn drain_in_every_order() {
+        let tree = || Tree::from_shape(0..7, Shape::Balanced, |_| unreachable!());
+        for (order, expected) in [
+            (Order::Pre, [3, 1, 0, 2, 5, 4, 6]),
+            (Order::In, [0, 1, 2, 3, 4, 5, 6]),
+            (Order::Post, [0, 2, 1, 4, 6, 5, 3]),
+            (Order::Level, [3, 1, 5, 0, 2, 4, 6]),
+        ] {
+            let mut tree = tree();
+            assert!(tree.drain(order).eq(expected));
+            assert!(tree.is_empty());
+        }
+
+        // Stopping early still frees everything.
+        let counter = std::rc::Rc::new(());
+        for order in [Order::Pre, Order::In, Order::Post, Order::Level] {
+            let values = (0..100).map(|_| counter.clone());
+            let mut tree = Tree::from_shape(values, Shape::Random, |n| n / 3);
+            assert_eq!(tree.drain(order).take(10).count(), 10);
+            assert!(tree.is_empty());
+            assert_eq!(std::rc::Rc::strong_count(&counter), 1);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn parallel_construction() {
+        let values: Vec<_> = (0..1000).collect();
+        let serial = Tree::from_shape(values.clone(), Shape::Balanced, |_| unreachable!());
+        let serial: Vec<_> = serial
+            .root()
+            .unwrap()
+            .iter(Order::Pre)
+            .map(Node::get)
+            .collect();
+        for threads in [1, 2, 3, 8] {
+            let tree = Tree::from_slice_parallel(&values, threads, 10);
+            let root = tree.root().unwrap();
+            assert!(root
+                .iter(Order::Pre)
+                .map(Node::get)
+                .eq(serial.iter().copied()));
+            // Parent pointers line up across the joins too.
+            assert!(root.descendants().all(|node| {
+                let (left, right) = node.parent().unwrap().children();
+                [left, right]
+                    .into_iter()
+                    .flatten()
+                    .any(|child| std::ptr::eq(child, node))
+            }));
+        }
+        assert!(Tree::<i32>::from_sorted_slice_parallel(&[]).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn parallel_fold() {
+        let tree = Tree::random(crate::lcg_words(11), 500, Shape::Random);
+        let serial: String = in_order(&tree).map(|n| n.to_string()).collect();
+        for threads in [1, 2, 3, 8] {
+            // Concatenation is associative but not commutative, so this checks the order too.
+            let folded = tree.fold_parallel(threads, String::new, |n| n.to_string(), |a, b| a + &b);
+            assert_eq!(folded, serial);
+        }
+        assert_eq!(tree.par_fold(|| 0, |&n| n, |a, b| a + b), 499 * 500 / 2);
+        assert_eq!(Tree::<u8>::new().par_fold(|| 7, |_| 0, |a, b| a + b), 7);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let tree = Tree::from_shape(
+            ["a".to_string(), String::with_capacity(10), String::new()],
+            Shape::Balanced,
+            |_| 0,
+        );
+        let report = tree.memory_usage_deep();
+        assert_eq!(report.nodes, 3);
+        assert_eq!(report.node_bytes, 3 * std::mem::size_of::<Node<String>>());
+        assert_eq!(report.heap_bytes, 11);
+        assert_eq!(tree.memory_usage().total_bytes(), report.node_bytes);
+        assert_eq!(
+            Tree::<(u8, Vec<u32>)>::new().memory_usage_deep(),
+            MemoryReport::default()
+        );
+    }
+
+    fn hash<T: Hash>(value: &T) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn structural_equality() {
+        let tree = |shape| Tree::from_shape(0..5, shape, |n| n / 2);
+        assert!(tree(Shape::Balanced) == tree(Shape::Balanced));
+        assert_eq!(hash(&tree(Shape::Random)), hash(&tree(Shape::Random)));
+        // Same payloads in the same order, but shaped differently.
+        assert!(tree(Shape::LeftSkewed) != tree(Shape::RightSkewed));
+        assert_ne!(
+            hash(&tree(Shape::LeftSkewed)),
+            hash(&tree(Shape::RightSkewed))
+        );
+        let other = Tree::from_shape([0, 1, 2, 3, 5], Shape::Balanced, |_| 0);
+        assert!(tree(Shape::Balanced) != other);
+        assert!(Tree::<u8>::new() == Tree::new());
+        assert!(Tree::new() != Tree::with_root(0));
+    }
+
+    #[test]
+    fn total_order() {
+        let tree = |values: &[u8], shape| Tree::from_shape(values.iter().copied(), shape, |_| 0);
+        let mut trees = [
+            tree(&[1, 2], Shape::LeftSkewed),
+            tree(&[0, 5, 6], Shape::Balanced),
+            Tree::new(),
+            tree(&[1, 2], Shape::RightSkewed),
+            tree(&[1], Shape::Balanced),
+        ];
+        trees.sort();
+        let sorted: Vec<_> = trees
+            .iter()
+            .map(|tree| in_order(tree).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            sorted,
+            [&[][..], &[&0, &5, &6], &[&1], &[&1, &2], &[&1, &2]]
+        );
+        // The right leaning tree has its root's left slot empty, where the left leaning one has a
+        // node.
+        assert!(trees[3] == tree(&[1, 2], Shape::RightSkewed));
+        assert!(trees[3] < trees[4]);
+        assert_eq!(
+            tree(&[3], Shape::Balanced).partial_cmp(&tree(&[3], Shape::Balanced)),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn degenerate_chains() {
+        // Deep enough to overflow the stack of any traversal that recurses.
+        let n = 1_000_000;
+        let mut rng = crate::lcg_words(9);
+        for shape in [Shape::LeftSkewed, Shape::RightSkewed] {
+            let mut tree = Tree::from_shape(0..n, shape, |_| unreachable!());
+            let root = tree.root().unwrap();
+            for order in [Order::Pre, Order::In, Order::Post, Order::Level] {
+                assert_eq!(root.iter(order).count(), n);
+            }
+            assert_eq!(root.descendants().count(), n - 1);
+            assert_eq!(root.iter_limited(usize::MAX, usize::MAX).count(), n);
+            assert_eq!(root.iter_pruned(|_| Descend::Yes).count(), n);
+            assert_eq!(root.layout().count(), n);
+            assert_eq!(root.sample(&mut rng, 3).len(), 3);
+            assert_eq!(tree.memory_usage().nodes, n);
+            let other = Tree::from_shape(0..n, shape, |_| unreachable!());
+            assert!(tree == other);
+            assert_eq!(hash(&tree), hash(&other));
+
+            // Safety: nodes are only moved around within the tree.
+            let root = unsafe { tree.root_mut() }.unwrap().balance();
+            let mut limited = root.iter_limited(20, usize::MAX);
+            assert_eq!(limited.by_ref().count(), n);
+            assert!(!limited.truncated());
+            root.map_in_place(|n| n + 1);
+            root.flatten_preorder();
+            assert_eq!(tree.drain(Order::Post).take(10).count(), 10);
+        }
+        drop(Tree::from_shape(
+            0..n,
+            Shape::LeftSkewed,
+            |_| unreachable!(),
+        ));
+    }
+
+    #[test]
+    fn snapshot_flags_broken_links() {
+        let mut tree = Tree::from_shape(1..=4, Shape::Balanced, |_| 0);
+        assert_eq!(
+            tree.debug_snapshot(),
+            "#0 3 parent: - left: #1 right: #3\n\
+             #1 2 parent: #0 left left: #2 right: -\n\
+             #2 1 parent: #1 left left: - right: -\n\
+             #3 4 parent: #0 right left: - right: -\n"
+        );
+
+        // Safety: the links are put back before the tree is dropped.
+        let root = unsafe { tree.root_mut() }.unwrap();
+        let one = NonNull::from(root.at_path(&[Side::Left, Side::Left]).unwrap());
+        let four = root.right.unwrap();
+        unsafe {
+            (*one.as_ptr()).parent = Some(four);
+            (*four.as_ptr()).right = Some(one);
+        }
+        let snapshot = tree.debug_snapshot();
+        assert!(
+            snapshot.contains("#2 1 parent: #3 right left: - right: - <- reached from #1 left\n")
+        );
+        assert!(snapshot.contains("#3 4 parent: #0 right left: - right: #2 (seen before)\n"));
+
+        unsafe {
+            (*four.as_ptr()).right = None;
+            (*one.as_ptr()).parent = (*four.as_ptr()).parent.unwrap().as_ref().left;
+        }
+        assert_eq!(Tree::<u8>::new().debug_snapshot(), "(empty)\n");
+    }
+
+    #[test]
+    fn snapshot_after_rotating_the_root() {
+        // The stored root ends up below the new top, which the snapshot starts from all the same.
+        let mut tree = Tree::from_shape(1..=3, Shape::Balanced, |_| 0);
+        // Safety: the rotation keeps the tree well formed.
+        unsafe { tree.root_mut() }.unwrap().rotate_right();
+        assert_eq!(
+            tree.debug_snapshot(),
+            "#0 1 parent: - left: - right: #1\n\
+             #1 2 parent: #0 right left: - right: #2\n\
+             #2 3 parent: #1 right left: - right: -\n"
+        );
+    }
+
+    #[test]
+    fn join_subtrees() {
+        let left = Tree::join(Tree::with_root(0), 1, Tree::new());
+        let tree = Tree::join(left, 2, Tree::with_root(3));
+        let root = tree.root().unwrap();
+        assert_eq!(root.get(), &2);
+        assert_eq!(root.right().map(Node::get), Some(&3));
+        let left = root.left().unwrap();
+        assert!(std::ptr::eq(left.parent().unwrap(), root));
+        assert_eq!(left.left().map(Node::get), Some(&0));
+        assert!(left.right().is_none());
+
+        let mut tree = tree;
+        let (three, zero) = tree
+            .get_disjoint_mut(&[Side::Right], &[Side::Left, Side::Left])
+            .unwrap();
+        mem::swap(three, zero);
+        assert_eq!(tree.root().and_then(Node::right).map(Node::get), Some(&0));
+        assert!(tree.get_disjoint_mut(&[Side::Right], &[]).is_none());
+    }
+
+    #[test]
+    fn merge_position_wise() {
+        let sum = |a: i32, b: i32| a + b;
+        let a = Tree::from_shape(0..7, Shape::Balanced, |_| 0);
+        let b = Tree::from_shape((0..7).map(|n| n * 10), Shape::Balanced, |_| 0);
+        let merged = a.merge_with(b, Mismatch::Error, sum).unwrap();
+        assert!(in_order(&merged).eq(&[0, 11, 22, 33, 44, 55, 66]));
+
+        // The second tree lacks the left subtree of the first, and has a right child more.
+        let a = || {
+            Tree::join(
+                Tree::with_root(1),
+                2,
+                Tree::join(Tree::new(), 3, Tree::new()),
+            )
+        };
+        let b = || {
+            Tree::join(
+                Tree::new(),
+                20,
+                Tree::join(Tree::new(), 30, Tree::with_root(40)),
+            )
+        };
+        let left = a().merge_with(b(), Mismatch::KeepLeft, sum).unwrap();
+        assert!(left == Tree::join(Tree::with_root(1), 22, Tree::with_root(33)));
+        let right = a().merge_with(b(), Mismatch::KeepRight, sum).unwrap();
+        let expected = Tree::join(
+            Tree::new(),
+            22,
+            Tree::join(Tree::new(), 33, Tree::with_root(40)),
+        );
+        assert!(right == expected);
+        assert_eq!(
+            a().merge_with(b(), Mismatch::Error, sum).err(),
+            Some(TreeError::ShapeMismatch)
+        );
+        let empty = Tree::new()
+            .merge_with(a(), Mismatch::KeepRight, sum)
+            .unwrap();
+        assert!(empty == a());
+    }
+
+    #[test]
+    fn unzip_pairs() {
+        let pairs = Tree::from_shape((0..6).map(|n| (n, n.to_string())), Shape::Random, |len| {
+            len / 3
+        });
+        let shape = Tree::from_shape(0..6, Shape::Random, |len| len / 3);
+        let (numbers, strings) = pairs.unzip();
+        assert!(numbers == shape);
+        assert!(
+            strings
+                == Tree::from_shape((0..6).map(|n| n.to_string()), Shape::Random, |len| len / 3)
+        );
+        let (a, b) = Tree::<(u8, u8)>::new().unzip();
+        assert!(a.is_empty() && b.is_empty());
+    }
+
+    #[test]
+    fn enumerate_in_every_order() {
+        let tree = || Tree::from_shape("abcde".chars(), Shape::Random, |len| len / 3);
+        for order in [Order::Pre, Order::In, Order::Post, Order::Level] {
+            let expected: Vec<char> = tree().drain(order).collect();
+            let numbered = tree().enumerate(order);
+            let root = numbered.root().unwrap();
+            let visited: Vec<(usize, char)> = root.iter(order).map(|n| *n.get()).collect();
+            assert!(visited.iter().map(|&(n, _)| n).eq(0..5), "{order:?}");
+            assert!(visited.iter().map(|&(_, c)| c).eq(expected), "{order:?}");
+            let (_, chars) = numbered.unzip();
+            assert!(chars == tree());
+        }
+        assert!(Tree::<u8>::new().enumerate(Order::In).is_empty());
+    }
+
+    #[test]
+    fn from_edges() {
+        use Side::{Left, Right};
+        let payloads = || (0..5).map(|id| (id, id * 10));
+        let edges = [(2, 0, Left), (0, 1, Right), (2, 3, Right), (3, 4, Right)];
+        let tree = Tree::from_edges(edges, payloads()).unwrap();
+        assert!(tree
+            .root()
+            .unwrap()
+            .iter(Order::In)
+            .map(Node::get)
+            .eq(&[0, 10, 20, 30, 40]));
+        assert_eq!(
+            tree.root().unwrap().at_path(&[Right, Right]).map(Node::get),
+            Some(&40)
+        );
+        assert!(Tree::<u8>::from_edges::<u8>([], []).unwrap().is_empty());
+
+        for (edges, error) in [
+            (vec![(2, 0, Left), (2, 5, Right)], EdgeError::UnknownNode(5)),
+            (
+                vec![(2, 0, Left), (3, 0, Right)],
+                EdgeError::MultipleParents(0),
+            ),
+            (
+                vec![(2, 0, Left), (2, 1, Left)],
+                EdgeError::SlotOccupied(2, Left),
+            ),
+            (vec![(2, 0, Left), (2, 1, Right)], EdgeError::MultipleRoots),
+            (
+                vec![(2, 0, Left), (0, 1, Right), (3, 4, Right), (4, 3, Left)],
+                EdgeError::Cycle,
+            ),
+        ] {
+            assert_eq!(Tree::from_edges(edges, payloads()).err(), Some(error));
+        }
+        assert_eq!(
+            Tree::from_edges([(0, 1, Left), (1, 0, Left)], [(0, ()), (1, ())]).err(),
+            Some(EdgeError::Cycle)
+        );
+    }
+
+    #[test]
+    fn edge_list_round_trip() {
+        let tree = Tree::from_shape(["a", "b\"", "c", "d"], Shape::Balanced, |_| 0);
+        let rows = tree.to_edge_list();
+        assert_eq!(rows[0], (0, None, None, &"c"));
+        assert_eq!(rows[1], (1, Some(0), Some(Side::Left), &"b\""));
+        let edges = rows
+            .iter()
+            .filter_map(|&(id, parent, side, _)| Some((parent?, id, side?)));
+        let payloads = rows.iter().map(|&(id, _, _, &payload)| (id, payload));
+        assert!(Tree::from_edges(edges, payloads).unwrap() == tree);
+
+        let csv = tree.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("id,parent,side,payload"));
+        assert_eq!(lines.next(), Some("0,,,\"c\""));
+        assert_eq!(lines.next(), Some("1,0,left,\"b\"\"\""));
+        assert_eq!(lines.count(), 2);
+        assert!(Tree::<u8>::new().to_edge_list().is_empty());
+    }
+
+    #[test]
+    fn preorder_null_markers_round_trip() {
+        let mut random = crate::lcg_words(3);
+        for n in [0, 1, 2, 50] {
+            let tree = Tree::random(&mut random, n, Shape::Random);
+            let slots: Vec<_> = tree
+                .preorder_with_null_markers()
+                .map(|slot| slot.copied())
+                .collect();
+            assert_eq!(slots.len(), 2 * n + 1);
+            assert!(Tree::from_preorder_with_null_markers(slots).unwrap() == tree);
+        }
+        let chain = (0..100_000)
+            .map(Some)
+            .chain(core::iter::repeat_n(None, 100_001));
+        let chain = Tree::from_preorder_with_null_markers(chain).unwrap();
+        assert_eq!(chain.stats().levels.len(), 100_000);
+
+        assert!(Tree::<u8>::from_preorder_with_null_markers([]).is_none());
+        assert!(Tree::from_preorder_with_null_markers([Some(1), None]).is_none());
+        assert!(Tree::<u8>::from_preorder_with_null_markers([None, None]).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rebuild_from_traversals() {
+        let mut random = crate::lcg_words(8);
+        for n in [0, 1, 2, 3, 200] {
+            let tree = Tree::random(&mut random, n, Shape::Random);
+            let order = |order| -> Vec<usize> {
+                let nodes = tree.root().into_iter().flat_map(|root| root.iter(order));
+                nodes.map(|node| *node.get()).collect()
+            };
+            let (inorder, pre, post) = (order(Order::In), order(Order::Pre), order(Order::Post));
+            assert!(Tree::from_inorder_preorder(&inorder, &pre).unwrap() == tree);
+            assert!(Tree::from_inorder_postorder(&inorder, &post).unwrap() == tree);
+        }
+        let chain: Vec<u32> = (0..100_000).collect();
+        let reversed: Vec<u32> = chain.iter().rev().copied().collect();
+        let tree = Tree::from_inorder_preorder(&chain, &reversed).unwrap();
+        assert_eq!(tree.stats().levels.len(), 100_000);
+
+        assert!(Tree::from_inorder_preorder(&[1, 2], &[1]).is_none());
+        assert!(Tree::from_inorder_preorder(&[1, 1], &[1, 1]).is_none());
+        assert!(Tree::from_inorder_preorder(&[1, 2], &[1, 3]).is_none());
+        assert!(Tree::from_inorder_preorder(&[1, 2], &[1, 1]).is_none());
+        assert!(Tree::from_inorder_preorder(&[1, 2, 3], &[2, 3, 1]).is_none());
+        assert!(Tree::from_inorder_postorder(&[1, 2, 3], &[3, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn spines() {
+        let left = Tree::left_spine(0..100_000);
+        assert_eq!(left.stats().levels.len(), 100_000);
+        assert!(left.to_vec(Order::Pre).into_iter().eq(0..100_000));
+        let right = Tree::right_spine(0..100_000);
+        assert!(right.to_vec(Order::In).into_iter().eq(0..100_000));
+        let zigzag = Tree::zigzag(0..5);
+        let root = zigzag.root().unwrap();
+        let path = [Side::Left, Side::Right, Side::Left, Side::Right];
+        assert_eq!(root.at_path(&path).map(Node::get), Some(&4));
+        assert_eq!(zigzag.to_vec(Order::In), [1, 3, 4, 2, 0]);
+        assert!(Tree::<u8>::zigzag([]).is_empty());
+    }
+
+    #[test]
+    fn clear_and_count() {
+        let mut tree = Tree::from_shape(0..10, Shape::Random, |n| n / 3);
+        assert_eq!(tree.len(), 10);
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        tree.clear();
+        assert_eq!(tree.graft(&[], Tree::left_spine(0..1000)), Ok(()));
+        assert_eq!(tree.len(), 1000);
+    }
+
+    #[test]
+    fn split_into_two_trees() {
+        let tree = Tree::from_shape(0..15, Shape::Balanced, |_| 0);
+        let path = [Side::Left, Side::Right];
+        let (rest, subtree) = Tree::from_shape(0..15, Shape::Balanced, |_| 0).split_at(&path);
+        assert_eq!(subtree.to_vec(Order::In), [4, 5, 6]);
+        assert!(subtree.root().unwrap().parent().is_none());
+        assert_eq!(rest.len(), 12);
+        assert!(rest.root().unwrap().at_path(&path).is_none());
+        let mut rest = rest;
+        assert_eq!(rest.graft(&path, subtree), Ok(()));
+        assert!(rest == tree);
+
+        let (rest, whole) = rest.split_at(&[]);
+        assert!(rest.is_empty() && whole == tree);
+        let (whole, nothing) = whole.split_at(&[Side::Left; 5]);
+        assert!(whole == tree && nothing.is_empty());
+    }
+
+    #[test]
+    fn graft_copies() {
+        let source = Tree::from_shape(0..3, Shape::Balanced, |_| 0);
+        let mut dest = Tree::with_root(10);
+        let subtree = source.root().unwrap();
+        assert_eq!(subtree.clone_subtree_into(&mut dest, &[Side::Left]), Ok(()));
+        assert_eq!(
+            subtree.clone_subtree_into(&mut dest, &[Side::Left]),
+            Err(TreeError::SlotOccupied)
+        );
+        assert_eq!(
+            subtree.clone_subtree_into(&mut dest, &[Side::Right, Side::Left]),
+            Err(TreeError::PathOutOfBounds)
+        );
+        assert!(dest.root().unwrap().left().unwrap().clone_subtree() == source);
+        let nodes = dest.root().unwrap().iter(Order::In).map(Node::get);
+        assert!(nodes.eq(&[0, 1, 2, 10]));
+
+        let mut empty = Tree::new();
+        assert_eq!(empty.graft(&[], Tree::with_root(1)), Ok(()));
+        assert_eq!(empty.graft(&[], Tree::new()), Err(TreeError::SlotOccupied));
+        assert!(empty == Tree::with_root(1));
+    }
+}
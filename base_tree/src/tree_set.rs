@@ -0,0 +1,314 @@
+//! An ordered set, kept as a [`BstMap`] without values.
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::iter::{self, FusedIterator};
+use core::ops::RangeBounds;
+
+use crate::bst::{self, BstMap, Compare, Natural};
+
+/// An ordered set of values, balanced as a scapegoat tree like the [`BstMap`] it is made of, and
+/// ordered by `C` as that is.
+pub struct TreeSet<T, C = Natural> {
+    map: BstMap<T, (), C>,
+}
+
+impl<T> TreeSet<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<T, C> TreeSet<T, C> {
+    /// An empty set ordering its values by `cmp`, as [`BstMap::with_comparator`] makes one.
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            map: BstMap::with_comparator(cmp),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the values in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.map.iter())
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(value, _)| value)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(value, _)| value)
+    }
+
+    /// Removes the smallest value, so the set can serve as a priority queue.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(value, _)| value)
+    }
+
+    /// Removes the largest value.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(value, _)| value)
+    }
+
+    /// Keeps only the values `f` returns true for, in a single pass over the set.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.map.retain(|value, _| f(value));
+    }
+}
+
+impl<T, C: Compare<T>> TreeSet<T, C> {
+    /// Adds `value`, returning whether it was new. A value already in the set is kept.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Removes `value`, returning whether it was in the set.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Iterates over the values in `range`, in order.
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+        R: RangeBounds<Q>,
+    {
+        Iter(self.map.range(range))
+    }
+
+    /// The largest value at most `value`.
+    pub fn floor<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.floor(value).map(|(value, _)| value)
+    }
+
+    /// The smallest value at least `value`.
+    pub fn ceiling<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.ceiling(value).map(|(value, _)| value)
+    }
+
+    /// The largest value below `value`.
+    pub fn lower<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.lower(value).map(|(value, _)| value)
+    }
+
+    /// The smallest value above `value`.
+    pub fn upper<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized,
+        C: Compare<Q>,
+    {
+        self.map.upper(value).map(|(value, _)| value)
+    }
+
+    /// The values in either set, in order, found by walking both side by side in O(n + m). Values
+    /// are told apart by the comparator of this set.
+    pub fn union<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a T> + 'a {
+        merge(self, other, [true, true, true])
+    }
+
+    /// The values in both sets, in order.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a T> + 'a {
+        merge(self, other, [false, true, false])
+    }
+
+    /// The values in this set but not in `other`, in order.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a T> + 'a {
+        merge(self, other, [true, false, false])
+    }
+}
+
+/// Walks `a` and `b` side by side, yielding the values only in `a`, in both, and only in `b` as
+/// `keep` says, each value once.
+fn merge<'a, T, C: Compare<T>>(
+    a: &'a TreeSet<T, C>,
+    b: &'a TreeSet<T, C>,
+    [only_a, both, only_b]: [bool; 3],
+) -> impl Iterator<Item = &'a T> + 'a {
+    let cmp = a.map.comparator();
+    let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+    iter::from_fn(move || loop {
+        let (value, keep) = match (a.peek(), b.peek()) {
+            (None, None) => return None,
+            // Once one side runs out, there is no need to go through the rest of the other.
+            (Some(_), None) if !only_a => return None,
+            (None, Some(_)) if !only_b => return None,
+            (Some(_), None) => (a.next(), only_a),
+            (None, Some(_)) => (b.next(), only_b),
+            (Some(x), Some(y)) => match cmp.compare(x, y) {
+                Ordering::Less => (a.next(), only_a),
+                Ordering::Greater => (b.next(), only_b),
+                Ordering::Equal => {
+                    b.next();
+                    (a.next(), both)
+                }
+            },
+        };
+        if keep {
+            return value;
+        }
+    })
+}
+
+impl<T, C: Default> Default for TreeSet<T, C> {
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<T: Debug, C> Debug for TreeSet<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, C: Compare<T>> Extend<T> for TreeSet<T, C> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        self.map.extend(values.into_iter().map(|value| (value, ())));
+    }
+}
+
+impl<T, C: Compare<T> + Default> FromIterator<T> for TreeSet<T, C> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut set = Self::default();
+        set.extend(values);
+        set
+    }
+}
+
+impl<'a, T, C> IntoIterator for &'a TreeSet<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T>(bst::Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|(value, _)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(value, _)| value)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::vec::Vec;
+
+    #[test]
+    fn matches_btree_set() {
+        let mut random = crate::lcg(21);
+        let (mut set, mut model) = (TreeSet::new(), BTreeSet::new());
+        for round in 0..3000 {
+            let value = random(500);
+            if round % 3 == 2 {
+                assert_eq!(set.remove(&value), model.remove(&value));
+            } else {
+                assert_eq!(set.insert(value), model.insert(value));
+            }
+        }
+        assert!(set.iter().eq(model.iter()));
+        assert!(set.iter().rev().eq(model.iter().rev()));
+        assert!(set.range(100..200).eq(model.range(100..200)));
+        assert!(set.range(..=50).rev().eq(model.range(..=50).rev()));
+        assert_eq!(set.range(300..).len(), model.range(300..).count());
+        assert_eq!(set.range(1000..).len(), 0);
+        for probe in [0, 17, 250, 499, 600] {
+            assert_eq!(set.floor(&probe), model.range(..=probe).next_back());
+            assert_eq!(set.upper(&probe), model.range(probe + 1..).next());
+        }
+
+        let other: TreeSet<u64> = (0..600).step_by(3).collect();
+        let other_model: BTreeSet<u64> = other.iter().copied().collect();
+        let union: Vec<_> = set.union(&other).collect();
+        assert_eq!(union, model.union(&other_model).collect::<Vec<_>>());
+        let both: Vec<_> = set.intersection(&other).collect();
+        assert_eq!(both, model.intersection(&other_model).collect::<Vec<_>>());
+        let only: Vec<_> = other.difference(&set).collect();
+        assert_eq!(only, other_model.difference(&model).collect::<Vec<_>>());
+        assert!(TreeSet::new().intersection(&set).next().is_none());
+
+        assert_eq!((set.first(), set.last()), (model.first(), model.last()));
+        for _ in 0..100 {
+            assert_eq!(set.pop_first(), model.pop_first());
+            assert_eq!(set.pop_last(), model.pop_last());
+        }
+        assert!(set.iter().eq(model.iter()));
+        set.retain(|value| value % 2 == 0);
+        model.retain(|value| value % 2 == 0);
+        assert!(set.iter().eq(model.iter()));
+    }
+
+    #[test]
+    fn custom_comparator() {
+        let by_case = |a: &&str, b: &&str| a.to_lowercase().cmp(&b.to_lowercase());
+        let mut set = TreeSet::with_comparator(by_case);
+        set.extend("b A c a B d".split(' '));
+        assert!(set.iter().eq(&["A", "b", "c", "d"]));
+        assert!(!set.insert("C") && set.contains(&"D"));
+        let mut other = TreeSet::with_comparator(by_case);
+        other.extend(["B", "e"]);
+        assert!(set.intersection(&other).eq(&["b"]));
+        assert_eq!(set.union(&other).count(), 5);
+    }
+}
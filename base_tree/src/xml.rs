@@ -0,0 +1,434 @@
+//! Export to and import from XML, with a tree written as nested elements:
+//!
+//! ```text
+//! <tree>
+//!   <root key="2">
+//!     <left key="1"/>
+//!     <right key="3"/>
+//!   </root>
+//! </tree>
+//! ```
+//!
+//! Every node is an element named after where it hangs, and its payload is spread over its
+//! attributes by a callback. The empty tree is `<tree/>`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display, Write};
+
+use crate::{Node, Side, Tree};
+
+pub fn to_xml<T>(tree: &Tree<T>, attributes: impl FnMut(&T) -> Vec<(String, String)>) -> String {
+    let mut xml = String::new();
+    // Writing to a `String` can't fail.
+    let _ = write_xml(&mut xml, tree, attributes);
+    xml
+}
+
+/// Writes the tree as it is walked, one element per line, indented by depth. The attribute names
+/// returned by `attributes` must be valid XML names; their values are escaped.
+pub fn write_xml<T, W: Write + ?Sized>(
+    out: &mut W,
+    tree: &Tree<T>,
+    mut attributes: impl FnMut(&T) -> Vec<(String, String)>,
+) -> fmt::Result {
+    enum Work<'a, T> {
+        Open(&'a Node<T>, &'static str, usize),
+        Close(&'static str, usize),
+    }
+
+    let Some(root) = tree.root() else {
+        return out.write_str("<tree/>\n");
+    };
+    out.write_str("<tree>\n")?;
+    let mut stack = vec![Work::Open(root, "root", 1)];
+    while let Some(work) = stack.pop() {
+        let (node, name, depth) = match work {
+            Work::Close(name, depth) => {
+                writeln!(out, "{:indent$}</{name}>", "", indent = 2 * depth)?;
+                continue;
+            }
+            Work::Open(node, name, depth) => (node, name, depth),
+        };
+        write!(out, "{:indent$}<{name}", "", indent = 2 * depth)?;
+        for (key, value) in attributes(node.get()) {
+            write!(out, " {key}=\"")?;
+            escape(out, &value)?;
+            out.write_char('"')?;
+        }
+        if node.left().is_none() && node.right().is_none() {
+            out.write_str("/>\n")?;
+            continue;
+        }
+        out.write_str(">\n")?;
+        stack.push(Work::Close(name, depth));
+        if let Some(right) = node.right() {
+            stack.push(Work::Open(right, "right", depth + 1));
+        }
+        if let Some(left) = node.left() {
+            stack.push(Work::Open(left, "left", depth + 1));
+        }
+    }
+    out.write_str("</tree>\n")
+}
+
+fn escape<W: Write + ?Sized>(out: &mut W, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '&' => out.write_str("&amp;")?,
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '"' => out.write_str("&quot;")?,
+            // Kept from being normalised into spaces when read back.
+            '\n' => out.write_str("&#10;")?,
+            '\t' => out.write_str("&#9;")?,
+            '\r' => out.write_str("&#13;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmlError {
+    /// A character in a place it can't be, at the given byte offset.
+    UnexpectedToken(usize),
+    /// The element at the given byte offset is not `tree`, `root`, `left` or `right` where one of
+    /// them belongs, or is a second child on the same side.
+    UnexpectedElement(usize),
+    /// The closing tag at the given byte offset does not match the open element.
+    MismatchedTag(usize),
+    /// The entity reference at the given byte offset is not one XML predefines, nor a character.
+    UnknownEntity(usize),
+    /// The element at the given byte offset has attributes the callback turned down.
+    InvalidPayload(usize),
+    UnexpectedEnd,
+}
+
+impl Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlError::UnexpectedToken(at) => write!(f, "unexpected token at offset {at}"),
+            XmlError::UnexpectedElement(at) => write!(f, "unexpected element at offset {at}"),
+            XmlError::MismatchedTag(at) => write!(f, "mismatched closing tag at offset {at}"),
+            XmlError::UnknownEntity(at) => write!(f, "unknown entity at offset {at}"),
+            XmlError::InvalidPayload(at) => write!(f, "invalid attributes at offset {at}"),
+            XmlError::UnexpectedEnd => write!(f, "unexpected end of XML"),
+        }
+    }
+}
+
+impl Error for XmlError {}
+
+/// Reads a tree written by [`write_xml`], building each payload from its element's attributes,
+/// in the order they appear. An XML declaration, comments and whitespace between elements are
+/// skipped; other text is refused.
+pub fn from_xml<T>(
+    input: &str,
+    mut payload: impl FnMut(&[(String, String)]) -> Option<T>,
+) -> Result<Tree<T>, XmlError> {
+    struct Open<T> {
+        name: String,
+        data: T,
+        left: Option<Tree<T>>,
+        right: Option<Tree<T>>,
+    }
+
+    let mut cursor = Cursor { input, at: 0 };
+    cursor.skip_misc()?;
+    if cursor.eat("<?xml") {
+        cursor.skip_past("?>")?;
+    }
+
+    let start = cursor.skip_misc()?;
+    let (name, _, closed) = cursor.tag()?;
+    if name != "tree" {
+        return Err(XmlError::UnexpectedElement(start));
+    }
+    let mut tree = Tree::new();
+    if !closed {
+        let mut open: Vec<Open<T>> = Vec::new();
+        loop {
+            let start = cursor.skip_misc()?;
+            if cursor.eat("</") {
+                let name = cursor.name()?;
+                cursor.skip_whitespace();
+                cursor.expect('>')?;
+                let Some(node) = open.pop() else {
+                    if name != "tree" {
+                        return Err(XmlError::MismatchedTag(start));
+                    }
+                    break;
+                };
+                if name != node.name {
+                    return Err(XmlError::MismatchedTag(start));
+                }
+                let joined = Tree::join(
+                    node.left.unwrap_or_default(),
+                    node.data,
+                    node.right.unwrap_or_default(),
+                );
+                match open.last_mut() {
+                    Some(parent) if node.name == "left" => parent.left = Some(joined),
+                    Some(parent) => parent.right = Some(joined),
+                    None => tree = joined,
+                }
+                continue;
+            }
+
+            let (name, attributes, closed) = cursor.tag()?;
+            let slot = match (open.last(), name.as_str()) {
+                (None, "root") if tree.is_empty() => None,
+                (Some(parent), "left") if parent.left.is_none() => Some(Side::Left),
+                (Some(parent), "right") if parent.right.is_none() => Some(Side::Right),
+                _ => return Err(XmlError::UnexpectedElement(start)),
+            };
+            let data = payload(&attributes).ok_or(XmlError::InvalidPayload(start))?;
+            if !closed {
+                open.push(Open {
+                    name,
+                    data,
+                    left: None,
+                    right: None,
+                });
+                continue;
+            }
+            let leaf = Tree::with_root(data);
+            match (open.last_mut(), slot) {
+                (Some(parent), Some(Side::Left)) => parent.left = Some(leaf),
+                (Some(parent), _) => parent.right = Some(leaf),
+                (None, _) => tree = leaf,
+            }
+        }
+    }
+
+    match cursor.skip_misc()? {
+        at if at == input.len() => Ok(tree),
+        at => Err(XmlError::UnexpectedToken(at)),
+    }
+}
+
+struct Cursor<'a> {
+    input: &'a str,
+    at: usize,
+}
+
+impl Cursor<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.at..]
+    }
+
+    fn error(&self) -> XmlError {
+        if self.at < self.input.len() {
+            XmlError::UnexpectedToken(self.at)
+        } else {
+            XmlError::UnexpectedEnd
+        }
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.rest().starts_with(text);
+        if found {
+            self.at += text.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), XmlError> {
+        if !self.rest().starts_with(c) {
+            return Err(self.error());
+        }
+        self.at += c.len_utf8();
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), XmlError> {
+        let found = self.rest().find(end).ok_or(XmlError::UnexpectedEnd)?;
+        self.at += found + end.len();
+        Ok(())
+    }
+
+    /// Skips whitespace and comments, returning where the next token starts.
+    fn skip_misc(&mut self) -> Result<usize, XmlError> {
+        loop {
+            self.skip_whitespace();
+            if !self.eat("<!--") {
+                return Ok(self.at);
+            }
+            self.skip_past("-->")?;
+        }
+    }
+
+    fn name(&mut self) -> Result<String, XmlError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_-.:".contains(c)))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error());
+        }
+        let name = String::from(&rest[..len]);
+        self.at += len;
+        Ok(name)
+    }
+
+    /// Reads an opening tag, returning its name, its attributes, and whether it closed itself.
+    #[allow(clippy::type_complexity)]
+    fn tag(&mut self) -> Result<(String, Vec<(String, String)>, bool), XmlError> {
+        self.expect('<')?;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            let before = self.at;
+            self.skip_whitespace();
+            if self.eat("/>") {
+                return Ok((name, attributes, true));
+            }
+            if self.eat(">") {
+                return Ok((name, attributes, false));
+            }
+            if self.at == before {
+                return Err(self.error());
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            attributes.push((key, self.value()?));
+        }
+    }
+
+    fn value(&mut self) -> Result<String, XmlError> {
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error()),
+        };
+        self.at += 1;
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.rest().chars().next() else {
+                return Err(XmlError::UnexpectedEnd);
+            };
+            let start = self.at;
+            self.at += c.len_utf8();
+            match c {
+                c if c == quote => return Ok(value),
+                '<' => return Err(XmlError::UnexpectedToken(start)),
+                '&' => {
+                    let end = self
+                        .rest()
+                        .find(';')
+                        .ok_or(XmlError::UnknownEntity(start))?;
+                    let entity = &self.rest()[..end];
+                    let decoded = match entity {
+                        "amp" => Some('&'),
+                        "lt" => Some('<'),
+                        "gt" => Some('>'),
+                        "quot" => Some('"'),
+                        "apos" => Some('\''),
+                        _ => entity
+                            .strip_prefix("#x")
+                            .map(|hex| u32::from_str_radix(hex, 16))
+                            .or_else(|| entity.strip_prefix('#').map(str::parse))
+                            .and_then(Result::ok)
+                            .and_then(char::from_u32),
+                    };
+                    value.push(decoded.ok_or(XmlError::UnknownEntity(start))?);
+                    self.at += end + 1;
+                }
+                // Literal whitespace in attribute values is normalised to spaces.
+                '\n' | '\t' | '\r' => value.push(' '),
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn attributes(&(key, ref label): &(u32, String)) -> Vec<(String, String)> {
+        vec![
+            (String::from("key"), key.to_string()),
+            (String::from("label"), label.clone()),
+        ]
+    }
+
+    fn payload(attributes: &[(String, String)]) -> Option<(u32, String)> {
+        match attributes {
+            [(key, value), (label_key, label)] if key == "key" && label_key == "label" => {
+                Some((value.parse().ok()?, label.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let tree = Tree::join(
+            Tree::with_root((1, String::from("<a & b>"))),
+            (2, String::from("\"quoted\"\n")),
+            Tree::new(),
+        );
+        let xml = to_xml(&tree, attributes);
+        assert_eq!(
+            xml,
+            "<tree>\n  \
+               <root key=\"2\" label=\"&quot;quoted&quot;&#10;\">\n    \
+                 <left key=\"1\" label=\"&lt;a &amp; b&gt;\"/>\n  \
+               </root>\n\
+             </tree>\n"
+        );
+        assert!(from_xml(&xml, payload).unwrap() == tree);
+
+        let hand_written = "<?xml version='1.0'?>\n<!-- snapshot --><tree><root key='7' \
+                            label='&#x1F333;&apos;'><right label='' key='8'></right></root></tree>";
+        let tree = from_xml(hand_written, |attributes| {
+            let (_, key) = attributes.iter().find(|(name, _)| name == "key")?;
+            key.parse::<u32>().ok()
+        })
+        .unwrap();
+        assert_eq!(tree.root().map(|root| *root.get()), Some(7));
+        assert_eq!(
+            tree.root().unwrap().right().map(|right| *right.get()),
+            Some(8)
+        );
+        assert!(from_xml("<tree/>", payload).unwrap().is_empty());
+        assert_eq!(to_xml(&Tree::new(), attributes), "<tree/>\n");
+
+        for (input, error) in [
+            ("", XmlError::UnexpectedEnd),
+            ("<forest/>", XmlError::UnexpectedElement(0)),
+            ("<tree><left/></tree>", XmlError::UnexpectedElement(6)),
+            (
+                "<tree><root/><root/></tree>",
+                XmlError::UnexpectedElement(13),
+            ),
+            (
+                "<tree><root><left/><left/></root></tree>",
+                XmlError::UnexpectedElement(19),
+            ),
+            ("<tree><root></left></tree>", XmlError::MismatchedTag(12)),
+            (
+                "<tree><root a='&nbsp;'/></tree>",
+                XmlError::UnknownEntity(15),
+            ),
+            ("<tree>text</tree>", XmlError::UnexpectedToken(6)),
+            ("<tree></tree><tree/>", XmlError::UnexpectedToken(13)),
+            ("<tree><root", XmlError::UnexpectedEnd),
+        ] {
+            assert_eq!(from_xml(input, |_| Some(())).err(), Some(error), "{input}");
+        }
+        let error = from_xml("<tree><root key='1'/></tree>", payload).err();
+        assert_eq!(error, Some(XmlError::InvalidPayload(6)));
+    }
+}